@@ -348,10 +348,11 @@ fn multiple_entries_example() -> Result<(), Box<dyn std::error::Error>> {
         *status_counts.entry(status).or_insert(0) += 1;
 
         println!(
-            "Entry {}: {} {} -> {} ({} bytes)",
+            "Entry {}: {} {} {} -> {} ({} bytes)",
             entry_count,
             entry.field("remote_addr")?,
-            entry.field("request")?,
+            entry.field("request_method")?,
+            entry.field("uri_path")?,
             status,
             bytes
         );
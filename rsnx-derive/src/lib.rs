@@ -0,0 +1,304 @@
+//! Derive macro for [rsnx](https://docs.rs/rsnx) log line structs.
+//!
+//! `#[derive(NginxLog)]` implements `rsnx::NginxLog` for a struct with named
+//! fields. The log format is given with `#[nginx(format = "...")]`, and each
+//! field is read from the variable of the same name, or the one given with
+//! `#[nginx(variable = "...")]`. Fields must implement `FromStr`; `Option`
+//! fields are `None` when the value is `-`.
+//!
+//! Every field must name a variable of the format, which is checked when the
+//! struct is compiled:
+//!
+//! ```compile_fail
+//! use rsnx::NginxLog;
+//!
+//! #[derive(NginxLog)]
+//! #[nginx(format = "$remote_addr $status")]
+//! struct Hit {
+//!     remote_addr: String,
+//!     body_bytes_sent: u64, // error: `$body_bytes_sent` is not in the log format
+//! }
+//! ```
+//!
+//! The format can't use a variable twice, as each field is read from a single
+//! value:
+//!
+//! ```compile_fail
+//! use rsnx::NginxLog;
+//!
+//! #[derive(NginxLog)]
+//! #[nginx(format = "$status $status")] // error: `$status` appears more than once
+//! struct Hit {
+//!     status: u16,
+//! }
+//! ```
+//!
+//! This crate is re-exported by `rsnx` when its `derive` feature is enabled.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, GenericArgument, LitStr,
+    PathArguments, Type,
+};
+
+/// Derive `rsnx::NginxLog` for a struct.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::NginxLog;
+///
+/// #[derive(NginxLog)]
+/// #[nginx(format = r#"$remote_addr "$request" $status $body_bytes_sent"#)]
+/// struct Hit {
+///     remote_addr: std::net::IpAddr,
+///     status: u16,
+///     #[nginx(variable = "body_bytes_sent")]
+///     bytes: Option<u64>,
+/// }
+///
+/// let hit = Hit::parse(r#"127.0.0.1 "GET / HTTP/1.1" 200 612"#)?;
+/// assert_eq!(hit.status, 200);
+/// assert_eq!(hit.bytes, Some(612));
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[proc_macro_derive(NginxLog, attributes(nginx))]
+pub fn derive_nginx_log(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// A struct field and the variable it is read from.
+struct Field<'a> {
+    ident: &'a syn::Ident,
+    variable: String,
+    /// The type inside `Option`, if the field is optional.
+    optional: Option<&'a Type>,
+    ty: &'a Type,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "NginxLog can't be derived for generic structs",
+        ));
+    }
+
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &data.fields,
+                    "NginxLog can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "NginxLog can only be derived for structs",
+            ))
+        }
+    };
+
+    let format = struct_format(input)?;
+    let variables = format_variables(&format.value());
+    if let Some(duplicate) = variables
+        .iter()
+        .enumerate()
+        .find_map(|(i, variable)| variables[..i].contains(variable).then_some(variable))
+    {
+        return Err(syn::Error::new_spanned(
+            &format,
+            format!("`${}` appears more than once in the log format", duplicate),
+        ));
+    }
+
+    let mut fields = Vec::new();
+    for field in named {
+        let ident = field.ident.as_ref().expect("named fields have identifiers");
+        let variable = match field_variable(&field.attrs)? {
+            Some(variable) => variable,
+            None => ident.to_string().trim_start_matches("r#").to_string(),
+        };
+        if !variables.contains(&variable) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                format!("`${}` is not in the log format", variable),
+            ));
+        }
+
+        fields.push(Field {
+            ident,
+            variable,
+            optional: option_inner(&field.ty),
+            ty: &field.ty,
+        });
+    }
+
+    let names = fields.iter().map(|field| &field.variable);
+    let values = fields.iter().enumerate().map(|(index, field)| {
+        let ident = field.ident;
+        let variable = &field.variable;
+        match field.optional {
+            Some(inner) => quote! {
+                #ident: ::rsnx::typed::optional::<#inner>(values[#index], #variable)?
+            },
+            None => {
+                let ty = field.ty;
+                quote! {
+                    #ident: ::rsnx::typed::required::<#ty>(values[#index], #variable)?
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl ::rsnx::NginxLog for #name {
+            const FORMAT: &'static str = #format;
+            const VARIABLES: &'static [&'static str] = &[#(#names),*];
+
+            fn from_values(values: &[::std::option::Option<&str>]) -> ::rsnx::Result<Self> {
+                ::std::result::Result::Ok(Self {
+                    #(#values,)*
+                })
+            }
+
+            fn log_parser() -> ::rsnx::Result<&'static ::rsnx::LogParser<Self>> {
+                static PARSER: ::std::sync::OnceLock<::rsnx::LogParser<#name>> =
+                    ::std::sync::OnceLock::new();
+                if let ::std::option::Option::Some(parser) = PARSER.get() {
+                    return ::std::result::Result::Ok(parser);
+                }
+                let parser = ::rsnx::LogParser::new()?;
+                ::std::result::Result::Ok(PARSER.get_or_init(|| parser))
+            }
+        }
+    })
+}
+
+/// Get the format from the struct's `#[nginx(format = "...")]` attribute.
+fn struct_format(input: &DeriveInput) -> syn::Result<LitStr> {
+    let mut format = None;
+    for attr in nginx_attrs(&input.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("format") {
+                format = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `format = \"...\"`"))
+            }
+        })?;
+    }
+
+    format.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            "missing log format, add #[nginx(format = \"...\")]",
+        )
+    })
+}
+
+/// Get the variable from a field's `#[nginx(variable = "...")]` attribute.
+fn field_variable(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    let mut variable = None;
+    for attr in nginx_attrs(attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("variable") {
+                let name = meta.value()?.parse::<LitStr>()?.value();
+                variable = Some(name.trim_start_matches('$').to_string());
+                Ok(())
+            } else {
+                Err(meta.error("expected `variable = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(variable)
+}
+
+fn nginx_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("nginx"))
+}
+
+/// Get the type inside an `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) if args.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
+
+/// Get the names of the `$variable` and `${variable}` fields of a format.
+///
+/// This follows the rules of `rsnx::format::tokenize`.
+fn format_variables(format: &str) -> Vec<String> {
+    let mut variables = Vec::new();
+    let mut rest = format;
+
+    while let Some(dollar) = rest.find('$') {
+        let after = &rest[dollar + 1..];
+        match braced_name(after).or_else(|| bare_name(after)) {
+            Some((name, remaining)) => {
+                variables.push(name.to_string());
+                rest = remaining;
+            }
+            None => rest = after,
+        }
+    }
+
+    variables
+}
+
+fn bare_name(after: &str) -> Option<(&str, &str)> {
+    let len = after
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(after.len());
+    (len > 0).then(|| after.split_at(len))
+}
+
+fn braced_name(after: &str) -> Option<(&str, &str)> {
+    let inner = after.strip_prefix('{')?;
+    let (name, remaining) = bare_name(inner)?;
+    Some((name, remaining.strip_prefix('}')?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_variables() {
+        assert_eq!(
+            format_variables(r#"$remote_addr [$time_local] ${request_time}s $ $$status"#),
+            ["remote_addr", "time_local", "request_time", "status"]
+        );
+        assert!(format_variables("no variables").is_empty());
+    }
+
+    #[test]
+    fn test_option_inner() {
+        let ty: Type = syn::parse_quote!(Option<u64>);
+        assert!(option_inner(&ty).is_some());
+        let ty: Type = syn::parse_quote!(std::option::Option<String>);
+        assert!(option_inner(&ty).is_some());
+        let ty: Type = syn::parse_quote!(Vec<u64>);
+        assert!(option_inner(&ty).is_none());
+    }
+}
@@ -0,0 +1,316 @@
+//! Saving how far a log has been read, to resume there later.
+//!
+//! A [`Checkpoint`] records the file an entry was read from and the byte
+//! offset just past it. The file is identified by its device and inode and by
+//! a fingerprint of its first bytes, so a checkpoint still finds its file after
+//! logrotate renamed, copied or compressed it. See
+//! [`RotatedReader::resume_from`](crate::RotatedReader::resume_from), or
+//! [`Reader::resume_from`](crate::Reader::resume_from) for a single file.
+//!
+//! Checkpoints are saved as a few lines of text:
+//!
+//! ```text
+//! path=/var/log/nginx/access.log
+//! device=2049
+//! inode=1835093
+//! fingerprint=1024:6c62272e07bb0142
+//! offset=48213
+//! ```
+
+use crate::error::{Error, Result};
+use crate::input::Input;
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The number of bytes at the start of a file that identify it.
+const FINGERPRINT_LEN: usize = 1024;
+
+/// The number of bytes a fingerprint must cover to identify a file on its own.
+///
+/// Shorter heads, such as a single request line, are easily shared by unrelated
+/// files, so the device and inode must match as well.
+const MIN_FINGERPRINT_LEN: u64 = 64;
+
+/// A position in a log file, saved between runs.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rsnx::{Checkpoint, RotatedReader};
+///
+/// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+/// let mut reader =
+///     RotatedReader::open("/var/log/nginx/access.log", format)?.with_hold_partial(true);
+/// if let Some(checkpoint) = Checkpoint::load("access.checkpoint")? {
+///     reader = reader.resume_from(&checkpoint)?;
+/// }
+///
+/// for entry in reader.by_ref() {
+///     println!("{}", entry?.field("request")?);
+/// }
+///
+/// if let Some(checkpoint) = reader.checkpoint() {
+///     checkpoint.save("access.checkpoint")?;
+/// }
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Checkpoint {
+    /// The path the file had when the checkpoint was taken.
+    path: PathBuf,
+    /// The device of the file, where known.
+    device: Option<u64>,
+    /// The inode of the file, where known.
+    inode: Option<u64>,
+    /// The number of bytes the fingerprint covers.
+    fingerprint_len: u64,
+    /// The hash of the first bytes of the file.
+    fingerprint: u64,
+    /// The byte offset to resume reading at.
+    offset: u64,
+}
+
+impl Checkpoint {
+    /// Create a checkpoint at an offset in a file.
+    pub(crate) fn new(path: &Path, identity: &FileIdentity, offset: u64) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            device: identity.id.map(|(device, _)| device),
+            inode: identity.id.map(|(_, inode)| inode),
+            fingerprint_len: identity.head.len() as u64,
+            fingerprint: fnv1a(&identity.head),
+            offset,
+        }
+    }
+
+    /// Get the path the file had when the checkpoint was taken.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the byte offset to resume reading at.
+    ///
+    /// For compressed files this is an offset in the decoded contents.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Check if a file is the one the checkpoint was taken in.
+    ///
+    /// The fingerprints must match. If the fingerprint covers fewer than
+    /// [`MIN_FINGERPRINT_LEN`] bytes, the device and inode must match as well, so
+    /// a file that was short when the checkpoint was taken is only found again
+    /// if it was renamed, not copied or compressed.
+    pub(crate) fn is_same_file(&self, identity: &FileIdentity) -> bool {
+        let Some(head) = identity.head.get(..self.fingerprint_len as usize) else {
+            return false;
+        };
+        let same_id = self.device.zip(self.inode) == identity.id && identity.id.is_some();
+        fnv1a(head) == self.fingerprint && (self.fingerprint_len >= MIN_FINGERPRINT_LEN || same_id)
+    }
+
+    /// Load a checkpoint saved with [`Checkpoint::save`].
+    ///
+    /// Returns `None` if the file doesn't exist, such as on the first run.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(text) => text.parse().map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save the checkpoint to a file.
+    ///
+    /// The checkpoint is written to a temporary file next to `path` first and
+    /// then renamed over it, so an interrupted save leaves the old one intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, self.to_string())?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path={}", self.path.display())?;
+        if let Some(device) = self.device {
+            writeln!(f, "device={}", device)?;
+        }
+        if let Some(inode) = self.inode {
+            writeln!(f, "inode={}", inode)?;
+        }
+        writeln!(
+            f,
+            "fingerprint={}:{:016x}",
+            self.fingerprint_len, self.fingerprint
+        )?;
+        writeln!(f, "offset={}", self.offset)
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut path = None;
+        let mut device = None;
+        let mut inode = None;
+        let mut fingerprint = None;
+        let mut offset = None;
+
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| Error::invalid_checkpoint(format!("malformed line '{}'", line)))?;
+            match key {
+                "path" => path = Some(PathBuf::from(value)),
+                "device" => device = Some(parse_number(key, value, 10)?),
+                "inode" => inode = Some(parse_number(key, value, 10)?),
+                "fingerprint" => {
+                    let (len, hash) = value.split_once(':').ok_or_else(|| {
+                        Error::invalid_checkpoint(format!("malformed fingerprint '{}'", value))
+                    })?;
+                    fingerprint = Some((parse_number(key, len, 10)?, parse_number(key, hash, 16)?));
+                }
+                "offset" => offset = Some(parse_number(key, value, 10)?),
+                _ => return Err(Error::invalid_checkpoint(format!("unknown key '{}'", key))),
+            }
+        }
+
+        let missing = |key| Error::invalid_checkpoint(format!("missing '{}'", key));
+        let (fingerprint_len, fingerprint) = fingerprint.ok_or_else(|| missing("fingerprint"))?;
+        Ok(Self {
+            path: path.ok_or_else(|| missing("path"))?,
+            device,
+            inode,
+            fingerprint_len,
+            fingerprint,
+            offset: offset.ok_or_else(|| missing("offset"))?,
+        })
+    }
+}
+
+fn parse_number(key: &str, value: &str, radix: u32) -> Result<u64> {
+    u64::from_str_radix(value, radix)
+        .map_err(|e| Error::invalid_checkpoint(format!("invalid {} '{}': {}", key, value, e)))
+}
+
+/// What identifies a file across renames: its device and inode, and its first bytes.
+#[derive(Debug, Clone)]
+pub(crate) struct FileIdentity {
+    id: Option<(u64, u64)>,
+    /// Up to [`FINGERPRINT_LEN`] bytes from the start of the decoded contents.
+    head: Vec<u8>,
+}
+
+impl FileIdentity {
+    /// Read the identity of a file.
+    pub(crate) fn of(path: &Path) -> Result<Self> {
+        let id = file_id(&fs::metadata(path)?);
+        let mut head = Vec::with_capacity(FINGERPRINT_LEN);
+        Input::open(path)?
+            .take(FINGERPRINT_LEN as u64)
+            .read_to_end(&mut head)?;
+        Ok(Self { id, head })
+    }
+}
+
+/// Get the device and inode of a file, which change when its path is replaced.
+#[cfg(unix)]
+pub(crate) fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Get the device and inode of a file, which aren't known on this platform.
+#[cfg(not(unix))]
+pub(crate) fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Hash bytes with 64-bit FNV-1a, which is stable across platforms and releases.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let identity = FileIdentity {
+            id: Some((2049, 1835093)),
+            head: b"10.0.0.1 200\n".to_vec(),
+        };
+        let checkpoint = Checkpoint::new(Path::new("/var/log/access.log"), &identity, 13);
+        let text = checkpoint.to_string();
+        assert!(text.contains("fingerprint=13:"));
+        assert_eq!(text.parse::<Checkpoint>().unwrap(), checkpoint);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.checkpoint");
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), Some(checkpoint));
+    }
+
+    #[test]
+    fn test_invalid() {
+        for text in [
+            "path=/a\noffset=1",
+            "path=/a\nfingerprint=1:zz\noffset=1",
+            "path=/a\nfingerprint=1:ff\noffset=-1",
+            "path=/a\nfingerprint=1:ff\noffset=1\nsize=2",
+            "garbage",
+        ] {
+            assert!(
+                matches!(
+                    text.parse::<Checkpoint>(),
+                    Err(Error::InvalidCheckpoint { .. })
+                ),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_same_file() {
+        let identity = |id, head: &[u8]| FileIdentity {
+            id,
+            head: head.to_vec(),
+        };
+        let long = [b'a'; 100];
+        let checkpoint = Checkpoint::new(Path::new("a"), &identity(Some((1, 2)), &long), 3);
+        assert!(checkpoint.is_same_file(&identity(Some((1, 2)), &[b'a'; 200])));
+        assert!(checkpoint.is_same_file(&identity(Some((1, 3)), &long)));
+        assert!(checkpoint.is_same_file(&identity(None, &long)));
+        assert!(!checkpoint.is_same_file(&identity(Some((1, 2)), &[b'b'; 100])));
+        assert!(!checkpoint.is_same_file(&identity(Some((1, 2)), &long[..99])));
+
+        // A short head only identifies the file together with its device and inode
+        let short = Checkpoint::new(Path::new("a"), &identity(Some((1, 2)), b"abc"), 3);
+        assert!(short.is_same_file(&identity(Some((1, 2)), b"abcdef")));
+        assert!(!short.is_same_file(&identity(Some((1, 3)), b"abcdef")));
+        assert!(!short.is_same_file(&identity(None, b"abc")));
+        assert!(!short.is_same_file(&identity(Some((1, 2)), b"abd")));
+
+        let empty = Checkpoint::new(Path::new("a"), &identity(Some((1, 2)), b""), 0);
+        assert!(empty.is_same_file(&identity(Some((1, 2)), b"abc")));
+        assert!(!empty.is_same_file(&identity(Some((1, 3)), b"abc")));
+    }
+}
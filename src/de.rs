@@ -0,0 +1,417 @@
+//! Deserializing entries into user-defined types with serde.
+//!
+//! Field values are strings in the log, so they are converted to the type each
+//! struct field asks for: numbers and bools are parsed with [`FromStr`], `-` is
+//! `None` for `Option` fields, and types such as [`Timestamp`](crate::Timestamp)
+//! parse themselves from the string. Struct fields are looked up by name with
+//! [`Entry::field`], so fields derived from `$request` are available too.
+//!
+//! # Example
+//!
+//! ```rust
+//! use rsnx::Reader;
+//! use serde::Deserialize;
+//! use std::io::Cursor;
+//!
+//! #[derive(Deserialize)]
+//! struct LogLine {
+//!     remote_addr: String,
+//!     status: u16,
+//!     body_bytes_sent: Option<u64>,
+//!     #[serde(rename = "request_method")]
+//!     method: String,
+//! }
+//!
+//! let log_data = "127.0.0.1 \"GET / HTTP/1.1\" 200 612\n127.0.0.1 \"HEAD / HTTP/1.1\" 200 -";
+//! let format = r#"$remote_addr "$request" $status $body_bytes_sent"#;
+//!
+//! let lines = Reader::new(Cursor::new(log_data), format)?
+//!     .deserialize::<LogLine>()
+//!     .collect::<Result<Vec<_>, _>>()?;
+//!
+//! assert_eq!(lines[0].body_bytes_sent, Some(612));
+//! assert_eq!(lines[1].method, "HEAD");
+//! assert_eq!(lines[1].body_bytes_sent, None);
+//! # Ok::<(), rsnx::Error>(())
+//! ```
+
+use crate::entry::{Entry, EntryRef};
+use crate::error::{Error, Result};
+use crate::reader::Reader;
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize};
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// Deserialize an entry into any type that implements [`Deserialize`].
+///
+/// # Example
+///
+/// ```rust
+/// # use rsnx::Parser;
+/// # use rsnx::parser::StringParser;
+/// use std::collections::HashMap;
+///
+/// let parser = Parser::new("$remote_addr $status")?;
+/// let entry = parser.parse_string("127.0.0.1 200")?;
+///
+/// let fields: HashMap<&str, &str> = rsnx::de::from_entry(&entry)?;
+/// assert_eq!(fields["status"], "200");
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+pub fn from_entry<'de, T: Deserialize<'de>>(entry: &'de Entry) -> Result<T> {
+    T::deserialize(EntryDeserializer {
+        source: Source::Owned(entry),
+    })
+}
+
+/// Deserialize a borrowed entry into any type that implements [`Deserialize`].
+///
+/// See [`from_entry`] for details.
+pub fn from_entry_ref<'de, T: Deserialize<'de>>(entry: &'de EntryRef<'de>) -> Result<T> {
+    T::deserialize(EntryDeserializer {
+        source: Source::Borrowed(entry),
+    })
+}
+
+/// An iterator that deserializes each entry of a reader.
+///
+/// Created by [`Reader::deserialize`]. Lines are parsed into borrowed entries,
+/// so only the deserialized values are allocated.
+#[derive(Debug)]
+pub struct DeserializeEntries<R: Read, T> {
+    reader: Reader<R>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T> DeserializeEntries<R, T> {
+    pub(crate) fn new(reader: Reader<R>) -> Self {
+        Self {
+            reader,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn reader(&self) -> &Reader<R> {
+        &self.reader
+    }
+
+    /// Consume the iterator, returning the underlying reader.
+    pub fn into_reader(self) -> Reader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for DeserializeEntries<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(
+            self.reader
+                .read_ref()?
+                .and_then(|entry| from_entry_ref(&entry)),
+        )
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::deserialize_error(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Error::field_not_found(field)
+    }
+}
+
+/// The entry being deserialized.
+#[derive(Clone, Copy)]
+enum Source<'de> {
+    Owned(&'de Entry),
+    Borrowed(&'de EntryRef<'de>),
+}
+
+impl<'de> Source<'de> {
+    fn field(self, name: &str) -> Option<&'de str> {
+        match self {
+            Source::Owned(entry) => entry.field(name).ok(),
+            Source::Borrowed(entry) => entry.field(name).ok(),
+        }
+    }
+
+    fn fields(self) -> Vec<(&'de str, &'de str)> {
+        match self {
+            Source::Owned(entry) => entry
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect(),
+            Source::Borrowed(entry) => entry.iter().collect(),
+        }
+    }
+}
+
+/// Deserializes an entry as a map or struct of its fields.
+struct EntryDeserializer<'de> {
+    source: Source<'de>,
+}
+
+impl<'de> de::Deserializer<'de> for EntryDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(FieldsAccess {
+            fields: self.source.fields().into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        // Look the struct's fields up by name, so derived fields are found and
+        // fields the struct doesn't want aren't visited.
+        let fields = fields
+            .iter()
+            .filter_map(|name| Some((*name, self.source.field(name)?)))
+            .collect::<Vec<_>>();
+        visitor.visit_map(FieldsAccess {
+            fields: fields.into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+/// Visits field names and values as map entries.
+struct FieldsAccess<'de, I> {
+    fields: I,
+    /// The field whose value is next.
+    value: Option<(&'de str, &'de str)>,
+}
+
+impl<'de, I> MapAccess<'de> for FieldsAccess<'de, I>
+where
+    I: Iterator<Item = (&'de str, &'de str)>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let Some((name, value)) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.value = Some((name, value));
+        seed.deserialize(BorrowedStrDeserializer::new(name))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (name, value) = self
+            .value
+            .take()
+            .ok_or_else(|| Error::deserialize_error("value requested before key"))?;
+
+        seed.deserialize(ValueDeserializer { name, value })
+            .map_err(|e| match e {
+                Error::DeserializeError { message } => {
+                    Error::deserialize_error(format!("field '{}': {}", name, message))
+                }
+                e => e,
+            })
+    }
+}
+
+/// Deserializes a field value, parsing it as the type the visitor asks for.
+struct ValueDeserializer<'de> {
+    name: &'de str,
+    value: &'de str,
+}
+
+impl ValueDeserializer<'_> {
+    fn parse<T>(&self) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.value.parse().map_err(|e| {
+            Error::field_parse_error(self.name, self.value, std::any::type_name::<T>(), e)
+        })
+    }
+}
+
+/// Implement deserializer methods that parse the value and visit the result.
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.value)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    /// nginx logs `-` for variables without a value.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            "-" => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Values deserialize as unit variants named by the value, such as `GET`.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(self.value))
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Parser, StringParser};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Method {
+        #[serde(rename = "GET")]
+        Get,
+        #[serde(rename = "POST")]
+        Post,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Line<'a> {
+        remote_addr: &'a str,
+        status: u16,
+        request_time: f64,
+        #[serde(rename = "request_method")]
+        method: Method,
+        remote_user: Option<String>,
+        upstream_cache_status: Option<String>,
+    }
+
+    fn parse(line: &str) -> Entry {
+        Parser::new(r#"$remote_addr $remote_user "$request" $status $request_time"#)
+            .unwrap()
+            .parse_string(line)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_deserialize_struct() {
+        let entry = parse(r#"10.0.0.1 - "POST /api HTTP/1.1" 201 0.005"#);
+        assert_eq!(
+            from_entry::<Line>(&entry).unwrap(),
+            Line {
+                remote_addr: "10.0.0.1",
+                status: 201,
+                request_time: 0.005,
+                method: Method::Post,
+                remote_user: None,
+                upstream_cache_status: None,
+            }
+        );
+
+        let entry = parse(r#"10.0.0.1 alice "PUT / HTTP/1.1" 200 0.005"#);
+        assert!(matches!(
+            from_entry::<Line>(&entry),
+            Err(Error::DeserializeError { .. })
+        ));
+
+        let entry = parse(r#"10.0.0.1 alice "GET / HTTP/1.1" 200 slow"#);
+        assert!(matches!(
+            from_entry::<Line>(&entry),
+            Err(Error::FieldParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_field() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Upstream {
+            upstream_addr: String,
+        }
+
+        let entry = parse(r#"10.0.0.1 - "GET / HTTP/1.1" 200 0.005"#);
+        let error = from_entry::<Upstream>(&entry).unwrap_err();
+        assert_eq!(error.to_string(), "field 'upstream_addr' not found");
+    }
+
+    #[test]
+    fn test_deserialize_map() {
+        let entry = parse(r#"10.0.0.1 - "GET / HTTP/1.1" 200 0.005"#);
+        let fields: BTreeMap<&str, &str> = from_entry(&entry).unwrap();
+        assert_eq!(fields.len(), 5);
+        assert_eq!(fields["remote_user"], "-");
+        assert!(!fields.contains_key("request_method"));
+    }
+}
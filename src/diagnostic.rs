@@ -0,0 +1,312 @@
+//! Diagnostics that pinpoint where a log line diverges from its format.
+
+use crate::format::Segment;
+use crate::scanner::Failure;
+use std::fmt;
+
+/// Maximum number of characters shown on each side of the error position when rendering.
+const CONTEXT_CHARS: usize = 40;
+
+/// What the format expected at the position of a mismatch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expected {
+    /// Literal text from the format.
+    Literal(String),
+    /// A value for the named field matching its pattern.
+    Field(String),
+    /// The end of the line.
+    End,
+}
+
+/// The location and cause of a mismatch between a log line and a format.
+///
+/// Produced by [`Parser::diagnose`](crate::Parser::diagnose), or attached to
+/// [`Error::LineFormatMismatch`](crate::Error::LineFormatMismatch) when the parser
+/// has diagnostics enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The log line that failed to match.
+    line: String,
+    /// Index of the segment that failed, or `None` if the line has trailing text.
+    segment_index: Option<usize>,
+    /// Byte offset into the line where the mismatch was found.
+    byte_offset: usize,
+    /// What the format expected at the mismatch position.
+    expected: Expected,
+    /// The text actually found at the mismatch position.
+    found: String,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic for a literal segment that didn't match.
+    pub(crate) fn literal(
+        line: &str,
+        segment_index: usize,
+        byte_offset: usize,
+        literal: &str,
+    ) -> Self {
+        let found = line[byte_offset..]
+            .chars()
+            .take(literal.chars().count())
+            .collect();
+
+        Self {
+            line: line.to_string(),
+            segment_index: Some(segment_index),
+            byte_offset,
+            expected: Expected::Literal(literal.to_string()),
+            found,
+        }
+    }
+
+    /// Create a diagnostic for a field whose pattern didn't match.
+    ///
+    /// The text found runs up to the next whitespace, or is the single
+    /// whitespace character at the mismatch position.
+    pub(crate) fn field(line: &str, segment_index: usize, byte_offset: usize, name: &str) -> Self {
+        let rest = &line[byte_offset..];
+        let len = match rest.find(char::is_whitespace) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(len) => len,
+            None => rest.len(),
+        };
+
+        Self {
+            line: line.to_string(),
+            segment_index: Some(segment_index),
+            byte_offset,
+            expected: Expected::Field(name.to_string()),
+            found: rest[..len].to_string(),
+        }
+    }
+
+    /// Create a diagnostic for text left over after the last segment.
+    pub(crate) fn trailing(line: &str, byte_offset: usize) -> Self {
+        Self {
+            line: line.to_string(),
+            segment_index: None,
+            byte_offset,
+            expected: Expected::End,
+            found: line[byte_offset..].to_string(),
+        }
+    }
+
+    /// Create a diagnostic for the failure the parser's scan program reported.
+    pub(crate) fn from_failure(segments: &[Segment], line: &str, failure: Failure) -> Self {
+        let Failure {
+            segment_index,
+            byte_offset,
+        } = failure;
+
+        match segment_index.map(|i| (i, &segments[i])) {
+            Some((i, Segment::Literal(literal))) => Self::literal(line, i, byte_offset, literal),
+            Some((i, Segment::Field(name))) => Self::field(line, i, byte_offset, name),
+            None => Self::trailing(line, byte_offset),
+        }
+    }
+
+    /// Get the log line that failed to match.
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Get the index of the format segment that failed.
+    ///
+    /// Returns `None` if every segment matched but the line has trailing text.
+    pub fn segment_index(&self) -> Option<usize> {
+        self.segment_index
+    }
+
+    /// Get the byte offset into the line where the mismatch was found.
+    pub fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    /// Get the 1-based character column where the mismatch was found.
+    pub fn column(&self) -> usize {
+        self.line[..self.byte_offset].chars().count() + 1
+    }
+
+    /// Get the literal text the format expected.
+    ///
+    /// Returns `None` if the format expected the end of the line or a field value,
+    /// see [`expected_field`](Self::expected_field).
+    pub fn expected(&self) -> Option<&str> {
+        match &self.expected {
+            Expected::Literal(literal) => Some(literal),
+            Expected::Field(_) | Expected::End => None,
+        }
+    }
+
+    /// Get the name of the field whose pattern didn't match, if the mismatch is in a field.
+    pub fn expected_field(&self) -> Option<&str> {
+        match &self.expected {
+            Expected::Field(name) => Some(name),
+            Expected::Literal(_) | Expected::End => None,
+        }
+    }
+
+    /// Get the text found at the mismatch position. Empty at the end of the line.
+    pub fn found(&self) -> &str {
+        &self.found
+    }
+
+    /// Render the line with a caret under the mismatch position.
+    ///
+    /// Long lines are shortened to the text around the mismatch.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::Parser;
+    ///
+    /// let parser = Parser::new("$remote_addr [$time_local]")?;
+    /// let diagnostic = parser.diagnose("127.0.0.1 08/Nov/2013").unwrap();
+    ///
+    /// assert_eq!(diagnostic.render(), "127.0.0.1 08/Nov/2013\n         ^");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn render(&self) -> String {
+        let before: Vec<char> = self.line[..self.byte_offset].chars().collect();
+        let after: Vec<char> = self.line[self.byte_offset..].chars().collect();
+
+        let mut shown = String::new();
+        let mut caret_column = before.len();
+
+        if before.len() > CONTEXT_CHARS {
+            shown.push_str("...");
+            shown.extend(&before[before.len() - CONTEXT_CHARS..]);
+            caret_column = CONTEXT_CHARS + 3;
+        } else {
+            shown.extend(&before);
+        }
+
+        if after.len() > CONTEXT_CHARS {
+            shown.extend(&after[..CONTEXT_CHARS]);
+            shown.push_str("...");
+        } else {
+            shown.extend(&after);
+        }
+
+        format!("{}\n{}^", shown, " ".repeat(caret_column))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.expected {
+            Expected::Literal(literal) => {
+                write!(f, "expected {:?} at column {}", literal, self.column())?
+            }
+            Expected::Field(name) => write!(
+                f,
+                "expected a value for ${} at column {}",
+                name,
+                self.column()
+            )?,
+            Expected::End => write!(f, "expected end of line at column {}", self.column())?,
+        }
+
+        if self.found.is_empty() {
+            write!(f, ", found end of line")
+        } else {
+            write!(f, ", found {:?}", self.found)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escape::EscapeMode;
+    use crate::parser::Parser;
+    use crate::variables::VariableRegistry;
+
+    #[test]
+    fn test_locate_literal_mismatch() {
+        let parser = Parser::new(r#"$remote_addr [$time_local] "$request""#).unwrap();
+        let line = r#"127.0.0.1 [08/Nov/2013:13:39:18 +0000] GET / HTTP/1.1"#;
+
+        let diagnostic = parser.diagnose(line).unwrap();
+        assert_eq!(diagnostic.segment_index(), Some(3));
+        assert_eq!(diagnostic.expected(), Some("] \""));
+        assert_eq!(diagnostic.found(), "] G");
+        assert_eq!(diagnostic.column(), 38);
+        assert_eq!(
+            diagnostic.to_string(),
+            r#"expected "] \"" at column 38, found "] G""#
+        );
+    }
+
+    #[test]
+    fn test_locate_end_of_line() {
+        let parser = Parser::new("$remote_addr [$time_local]").unwrap();
+        let diagnostic = parser.diagnose("127.0.0.1").unwrap();
+
+        assert_eq!(diagnostic.segment_index(), Some(1));
+        assert_eq!(diagnostic.found(), "");
+        assert_eq!(
+            diagnostic.to_string(),
+            r#"expected " [" at column 10, found end of line"#
+        );
+    }
+
+    #[test]
+    fn test_locate_trailing_text() {
+        let parser = Parser::new("[$level]").unwrap();
+        let diagnostic = parser.diagnose("[INFO] extra").unwrap();
+
+        assert_eq!(diagnostic.segment_index(), None);
+        assert_eq!(diagnostic.expected(), None);
+        assert_eq!(diagnostic.found(), " extra");
+        assert_eq!(diagnostic.render(), "[INFO] extra\n      ^");
+    }
+
+    #[test]
+    fn test_locate_variable_pattern() {
+        let parser = Parser::with_variables(
+            "$time_local $status$body_bytes_sent",
+            &VariableRegistry::builtin(),
+        )
+        .unwrap();
+        let diagnostic = parser.diagnose("08/Nov/2013:13:39:18 +0000 OK12").unwrap();
+
+        assert_eq!(diagnostic.segment_index(), Some(2));
+        assert_eq!(diagnostic.expected(), None);
+        assert_eq!(diagnostic.expected_field(), Some("status"));
+        assert_eq!(diagnostic.found(), "OK12");
+        assert_eq!(
+            diagnostic.to_string(),
+            r#"expected a value for $status at column 28, found "OK12""#
+        );
+    }
+
+    #[test]
+    fn test_locate_escaped_delimiter() {
+        let format = r#""$http_user_agent" $status"#;
+        let parser = Parser::new(format)
+            .unwrap()
+            .with_escape(EscapeMode::Json)
+            .unwrap();
+
+        // The escaped quotes are part of the value, so the mismatch is after them
+        let diagnostic = parser.diagnose(r#""say \"hi\""-200"#).unwrap();
+        assert_eq!(diagnostic.segment_index(), Some(2));
+        assert_eq!(diagnostic.expected(), Some("\" "));
+        assert_eq!(diagnostic.found(), "\"-");
+        assert_eq!(diagnostic.column(), 12);
+    }
+
+    #[test]
+    fn test_render_long_line() {
+        let parser = Parser::new("$a-[$b").unwrap();
+        let line = format!("{}-{}", "x".repeat(100), "y".repeat(100));
+        let diagnostic = parser.diagnose(&line).unwrap();
+
+        let rendered = diagnostic.render();
+        let (text, caret) = rendered.split_once('\n').unwrap();
+        assert!(text.starts_with("..."));
+        assert!(text.ends_with("..."));
+        assert_eq!(caret.len(), CONTEXT_CHARS + 4);
+    }
+}
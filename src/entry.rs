@@ -1,38 +1,162 @@
 //! Core data structures for representing parsed log entries.
 
 use crate::error::{Error, Result};
+use crate::request::{self, RequestLine};
+use crate::schema::Schema;
+use crate::time::Timestamp;
+use crate::upstream::Upstream;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-/// Type alias for the underlying field storage.
+/// Type alias for a map of field names to values.
 /// All field values are stored as strings, with type conversion on demand.
 pub type Fields = HashMap<String, String>;
 
+/// The location of a log line in its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LineInfo {
+    /// The 1-based line number, or `None` if the reader started in the middle of the input.
+    pub line_number: Option<u64>,
+    /// The byte offset of the start of the line.
+    pub byte_offset: u64,
+}
+
+impl LineInfo {
+    /// Create line info for a line with a known line number.
+    pub fn new(line_number: u64, byte_offset: u64) -> Self {
+        Self {
+            line_number: Some(line_number),
+            byte_offset,
+        }
+    }
+}
+
+impl fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line_number {
+            Some(line_number) => write!(f, "line {}, byte {}", line_number, self.byte_offset),
+            None => write!(f, "byte {}", self.byte_offset),
+        }
+    }
+}
+
 /// A parsed log entry containing field name-value pairs.
 /// 
 /// This is the primary data structure returned by log parsing operations.
 /// All field values are stored as strings internally, with type conversion
 /// methods available for accessing values as different types.
-#[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+///
+/// Field names live in a [`Schema`] shared with the parser that produced the
+/// entry, and values are stored by position. Fields keep the order of the format
+/// string; fields set later that aren't part of the schema follow in insertion order.
+///
+/// Entries produced by a [`Reader`](crate::Reader) also carry the [`LineInfo`] of
+/// the line they were parsed from, and entries read from a set of files carry
+/// their source file. Entries parsed from lines that aren't valid UTF-8 keep the
+/// line's original bytes. This is metadata: it is not a field and is ignored when
+/// comparing entries.
+#[derive(Debug, Clone)]
 pub struct Entry {
-    /// The underlying field storage.
-    fields: Fields,
+    /// The field names shared with the parser.
+    schema: Arc<Schema>,
+    /// The field values, indexed by their position in the schema.
+    values: Vec<Option<String>>,
+    /// Fields that aren't part of the schema, in insertion order.
+    extra: Vec<(String, String)>,
+    /// The location of the line this entry was parsed from.
+    line_info: Option<LineInfo>,
+    /// The file this entry was read from.
+    source: Option<Arc<Path>>,
+    /// The bytes of the line this entry was parsed from, if it wasn't valid UTF-8.
+    raw_line: Option<Vec<u8>>,
 }
 
 impl Entry {
     /// Create a new empty entry.
     pub fn new() -> Self {
-        Self {
-            fields: HashMap::new(),
-        }
+        Self::with_schema(Arc::default(), Vec::new())
     }
 
     /// Create a new entry from a fields map.
+    ///
+    /// The fields are sorted by name, since a map has no meaningful order.
     pub fn from_fields(fields: Fields) -> Self {
-        Self { fields }
+        let mut fields: Vec<_> = fields.into_iter().collect();
+        fields.sort_unstable();
+
+        let (names, values): (Vec<_>, Vec<_>) = fields
+            .into_iter()
+            .map(|(name, value)| (name, Some(value)))
+            .unzip();
+        Self::with_schema(Arc::new(Schema::new(names)), values)
+    }
+
+    /// Create an entry from a shared schema and values in schema order.
+    pub(crate) fn with_schema(schema: Arc<Schema>, values: Vec<Option<String>>) -> Self {
+        debug_assert_eq!(schema.len(), values.len());
+        Self {
+            schema,
+            values,
+            extra: Vec::new(),
+            line_info: None,
+            source: None,
+            raw_line: None,
+        }
+    }
+
+    /// Get the schema shared with the parser that produced this entry.
+    pub fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+
+    /// Attach the location of the line this entry was parsed from.
+    pub fn with_line_info(mut self, line_info: LineInfo) -> Self {
+        self.line_info = Some(line_info);
+        self
+    }
+
+    /// Get the location of the line this entry was parsed from, if known.
+    pub fn line_info(&self) -> Option<LineInfo> {
+        self.line_info
+    }
+
+    /// Attach the file this entry was read from.
+    pub fn with_source(mut self, source: Arc<Path>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Get the file this entry was read from, if known.
+    ///
+    /// This is set by readers that read more than one file, such as
+    /// [`RotatedReader`](crate::RotatedReader).
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    /// Attach the bytes of the line this entry was parsed from.
+    pub fn with_raw_line(mut self, line: impl Into<Vec<u8>>) -> Self {
+        self.raw_line = Some(line.into());
+        self
+    }
+
+    /// Get the bytes of the line this entry was parsed from, if it wasn't valid UTF-8.
+    ///
+    /// Readers keep the line when their [`Utf8Policy`](crate::Utf8Policy) decoded
+    /// it, as the values then no longer hold the original bytes: they are
+    /// replaced with U+FFFD, or escaped as `\xHH` text that can't be told apart
+    /// from the same text in the log. The line ending is not included.
+    pub fn raw_line(&self) -> Option<&[u8]> {
+        self.raw_line.as_deref()
     }
 
     /// Get a field value as a string.
@@ -57,13 +181,70 @@ impl Entry {
     /// assert_eq!(entry.field("status").unwrap(), "200");
     /// assert!(entry.field("nonexistent").is_err());
     /// ```
+    ///
+    /// The fields `request_method`, `request_uri`, `uri_path`, `query_string` and
+    /// `server_protocol` are derived from the `request` field when the entry
+    /// doesn't have them. See [`RequestLine`] for how requests are split.
+    ///
+    /// ```rust
+    /// # use rsnx::Parser;
+    /// # use rsnx::parser::StringParser;
+    /// let parser = Parser::new(r#""$request" $status"#)?;
+    /// let entry = parser.parse_string(r#""GET /search?q=rust HTTP/1.1" 200"#)?;
+    ///
+    /// assert_eq!(entry.field("uri_path")?, "/search");
+    /// assert_eq!(entry.field("query_string")?, "q=rust");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
     pub fn field(&self, name: &str) -> Result<&str> {
-        self.fields
-            .get(name)
-            .map(|s| s.as_str())
+        self.get_field(name)
+            .or_else(|| request::derived_field(self.get_field("request"), name))
             .ok_or_else(|| Error::field_not_found(name))
     }
 
+    /// Split the `request` field into its method, URI and protocol.
+    ///
+    /// Returns `None` if the entry has no `request` field or it is malformed.
+    pub fn request(&self) -> Option<RequestLine<'_>> {
+        RequestLine::parse(self.get_field("request")?)
+    }
+
+    /// Get a field value by its position in the schema.
+    ///
+    /// This is an O(1) alternative to [`Entry::field`] when the position of the
+    /// field in the format is known, e.g. from [`Schema::index_of`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use rsnx::Parser;
+    /// # use rsnx::parser::StringParser;
+    /// let parser = Parser::new("$remote_addr $status")?;
+    /// let status = parser.schema().index_of("status").unwrap();
+    ///
+    /// let entry = parser.parse_string("127.0.0.1 200")?;
+    /// assert_eq!(entry.field_by_index(status)?, "200");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn field_by_index(&self, index: usize) -> Result<&str> {
+        match self.values.get(index) {
+            Some(Some(value)) => Ok(value),
+            _ => Err(index_not_found(&self.schema, index)),
+        }
+    }
+
+    /// Look up a field value in the schema, then in the extra fields.
+    fn get_field(&self, name: &str) -> Option<&str> {
+        match self.schema.index_of(name) {
+            Some(index) => self.values[index].as_deref(),
+            None => self
+                .extra
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.as_str()),
+        }
+    }
+
     /// Get a field value as a float.
     /// 
     /// # Arguments
@@ -112,6 +293,113 @@ impl Entry {
         })
     }
 
+    /// Get a field value parsed as any type that implements [`FromStr`].
+    ///
+    /// The `-` nginx logs for empty variables counts as absent, so it returns a
+    /// [`Error::FieldNotFound`] like a missing field. Use [`Entry::get_opt`] for
+    /// fields that are often empty.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use rsnx::Parser;
+    /// # use rsnx::parser::StringParser;
+    /// # use std::net::IpAddr;
+    /// let parser = Parser::new("$remote_addr $status $request_time")?;
+    /// let entry = parser.parse_string("127.0.0.1 200 0.015")?;
+    ///
+    /// let addr: IpAddr = entry.get("remote_addr")?;
+    /// assert!(addr.is_loopback());
+    /// assert_eq!(entry.get::<u16>("status")?, 200);
+    /// assert_eq!(entry.get::<f64>("request_time")?, 0.015);
+    /// assert!(entry.get::<u16>("remote_addr").is_err());
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn get<T>(&self, name: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.get_opt(name)?
+            .ok_or_else(|| Error::field_not_found(name))
+    }
+
+    /// Get a field value parsed as any type that implements [`FromStr`], or
+    /// `None` if the field is missing or `-`.
+    ///
+    /// Returns an error only if the value can't be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use rsnx::Parser;
+    /// # use rsnx::parser::StringParser;
+    /// let parser = Parser::new(r#"$remote_user "$http_referer""#)?;
+    /// let entry = parser.parse_string(r#"- "https://example.com/""#)?;
+    ///
+    /// assert_eq!(entry.get_opt::<String>("remote_user")?, None);
+    /// let referer: Option<String> = entry.get_opt("http_referer")?;
+    /// assert_eq!(referer.as_deref(), Some("https://example.com/"));
+    /// assert_eq!(entry.get_opt::<u64>("body_bytes_sent")?, None);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn get_opt<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        parse_value(name, self.field(name).ok())
+    }
+
+    /// Get the time of the request as a [`Timestamp`].
+    ///
+    /// The time is taken from the first of the `time_local`, `time_iso8601` and
+    /// `msec` fields the entry has. Returns an error if the entry has none of
+    /// them or the value isn't a valid time.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use rsnx::Parser;
+    /// # use rsnx::parser::StringParser;
+    /// let parser = Parser::new("[$time_local] $status")?;
+    /// let entry = parser.parse_string("[08/Nov/2013:13:39:18 +0000] 200")?;
+    ///
+    /// assert_eq!(entry.timestamp()?.unix_timestamp(), 1383917958);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn timestamp(&self) -> Result<Timestamp> {
+        let (name, value) = TIME_FIELDS
+            .iter()
+            .find_map(|name| Some((*name, self.get_field(name)?)))
+            .ok_or_else(|| Error::field_not_found(TIME_FIELDS.join(", ")))?;
+
+        let timestamp = match name {
+            "time_local" => Timestamp::parse_time_local(value),
+            "time_iso8601" => Timestamp::parse_iso8601(value),
+            _ => Timestamp::parse_msec(value),
+        };
+        timestamp.map_err(|e| Error::field_parse_error(name, value, "timestamp", e))
+    }
+
+    /// Get the time of the request as a `chrono` date-time in its logged UTC offset.
+    ///
+    /// See [`Entry::timestamp`] for the fields that are used.
+    #[cfg(feature = "chrono")]
+    pub fn datetime(&self) -> Result<chrono::DateTime<chrono::FixedOffset>> {
+        self.timestamp().map(Into::into)
+    }
+
+    /// Get the upstream attempts of the request as an [`Upstream`].
+    ///
+    /// The attempts are collected from whichever of the
+    /// [`UPSTREAM_FIELDS`](crate::upstream::UPSTREAM_FIELDS) the entry has, such
+    /// as `upstream_addr`, `upstream_status` and `upstream_response_time`.
+    /// Returns an error if the entry has none of them or a value can't be parsed.
+    pub fn upstream(&self) -> Result<Upstream> {
+        Upstream::from_fields(|name| self.get_field(name))
+    }
+
     /// Set a field value as a string.
     /// 
     /// # Arguments
@@ -119,7 +407,16 @@ impl Entry {
     /// * `name` - The field name to set
     /// * `value` - The string value to store
     pub fn set_field(&mut self, name: impl Into<String>, value: impl Into<String>) {
-        self.fields.insert(name.into(), value.into());
+        let name = name.into();
+        let value = value.into();
+
+        if let Some(index) = self.schema.index_of(&name) {
+            self.values[index] = Some(value);
+        } else if let Some((_, existing)) = self.extra.iter_mut().find(|(n, _)| *n == name) {
+            *existing = value;
+        } else {
+            self.extra.push((name, value));
+        }
     }
 
     /// Set a field value from a float.
@@ -129,7 +426,7 @@ impl Entry {
     /// * `name` - The field name to set
     /// * `value` - The float value to convert and store
     pub fn set_float_field(&mut self, name: impl Into<String>, value: f64) {
-        self.fields.insert(name.into(), format!("{:.2}", value));
+        self.set_field(name, format!("{:.2}", value));
     }
 
     /// Set a field value from an unsigned integer.
@@ -139,7 +436,7 @@ impl Entry {
     /// * `name` - The field name to set
     /// * `value` - The unsigned integer value to convert and store
     pub fn set_uint_field(&mut self, name: impl Into<String>, value: u64) {
-        self.fields.insert(name.into(), value.to_string());
+        self.set_field(name, value.to_string());
     }
 
     /// Merge another entry into this one.
@@ -151,8 +448,8 @@ impl Entry {
     /// 
     /// * `other` - The entry to merge into this one
     pub fn merge(&mut self, other: &Entry) {
-        for (key, value) in &other.fields {
-            self.fields.insert(key.clone(), value.clone());
+        for (key, value) in other.iter() {
+            self.set_field(key.clone(), value.clone());
         }
     }
 
@@ -172,7 +469,7 @@ impl Entry {
         field_names
             .iter()
             .map(|&name| {
-                let value = self.fields.get(name).map(|s| s.as_str()).unwrap_or("NULL");
+                let value = self.get_field(name).unwrap_or("NULL");
                 format!("'{}'={}", name, value)
             })
             .collect::<Vec<_>>()
@@ -192,40 +489,126 @@ impl Entry {
     /// 
     /// A new entry containing only the specified fields
     pub fn partial(&self, field_names: &[&str]) -> Entry {
-        let mut fields = HashMap::new();
-        for &name in field_names {
-            let value = self.fields.get(name).cloned().unwrap_or_default();
-            fields.insert(name.to_string(), value);
-        }
-        Entry::from_fields(fields)
+        let schema = Schema::new(field_names.iter().copied());
+        let values = schema
+            .names()
+            .iter()
+            .map(|name| Some(self.get_field(name).unwrap_or_default().to_string()))
+            .collect();
+        Entry::with_schema(Arc::new(schema), values)
     }
 
     /// Get an iterator over all field names and values.
+    ///
+    /// Fields from the schema come first in format order, followed by any
+    /// fields set outside the schema in insertion order.
     pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
-        self.fields.iter()
+        self.schema
+            .names()
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| value.as_ref().map(|value| (name, value)))
+            .chain(self.extra.iter().map(|(name, value)| (name, value)))
     }
 
     /// Get the number of fields in this entry.
     pub fn len(&self) -> usize {
-        self.fields.len()
+        self.values.iter().flatten().count() + self.extra.len()
     }
 
     /// Check if this entry has no fields.
     pub fn is_empty(&self) -> bool {
-        self.fields.is_empty()
+        self.len() == 0
+    }
+
+    /// Copy the fields into a map.
+    pub fn to_fields(&self) -> Fields {
+        self.iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
     }
 
-    /// Get a reference to the underlying fields map.
-    pub fn fields(&self) -> &Fields {
+    /// Get the fields as a map.
+    ///
+    /// Values are no longer stored in a map, so this copies them. Before 0.2.0
+    /// this returned `&Fields`.
+    #[deprecated(note = "use `Entry::iter`, `Entry::field` or `Entry::to_fields` instead")]
+    pub fn fields(&self) -> Fields {
+        self.to_fields()
+    }
+
+    /// Get the fields as a mutable map.
+    ///
+    /// The returned guard holds a copy of the fields, and writes the changes back
+    /// to the entry when dropped. Before 0.2.0 this returned `&mut Fields`.
+    #[deprecated(note = "use `Entry::set_field` instead")]
+    pub fn fields_mut(&mut self) -> FieldsMut<'_> {
+        FieldsMut {
+            fields: self.to_fields(),
+            entry: self,
+        }
+    }
+}
+
+/// A mutable map of an entry's fields, returned by the deprecated [`Entry::fields_mut`].
+///
+/// Changes are written back to the entry when the guard is dropped. Fields added
+/// outside the entry's schema are kept in name order.
+#[derive(Debug)]
+pub struct FieldsMut<'a> {
+    entry: &'a mut Entry,
+    fields: Fields,
+}
+
+impl Deref for FieldsMut<'_> {
+    type Target = Fields;
+
+    fn deref(&self) -> &Fields {
         &self.fields
     }
+}
 
-    /// Get a mutable reference to the underlying fields map.
-    pub fn fields_mut(&mut self) -> &mut Fields {
+impl DerefMut for FieldsMut<'_> {
+    fn deref_mut(&mut self) -> &mut Fields {
         &mut self.fields
     }
 }
 
+impl Drop for FieldsMut<'_> {
+    fn drop(&mut self) {
+        let entry = &mut *self.entry;
+        let mut fields = std::mem::take(&mut self.fields);
+
+        for (name, value) in entry.schema.names().iter().zip(&mut entry.values) {
+            *value = fields.remove(name);
+        }
+        entry
+            .extra
+            .retain_mut(|(name, value)| match fields.remove(name) {
+                Some(new_value) => {
+                    *value = new_value;
+                    true
+                }
+                None => false,
+            });
+
+        let mut added: Vec<_> = fields.into_iter().collect();
+        added.sort_unstable();
+        entry.extra.extend(added);
+    }
+}
+
+impl PartialEq for Entry {
+    /// Entries are equal if they have the same fields and values, regardless of
+    /// field order or metadata.
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(name, value)| other.get_field(name) == Some(value.as_str()))
+    }
+}
+
 impl Default for Entry {
     fn default() -> Self {
         Self::new()
@@ -240,6 +623,257 @@ impl From<Fields> for Entry {
 
 impl From<Entry> for Fields {
     fn from(entry: Entry) -> Self {
-        entry.fields
+        entry.to_fields()
+    }
+}
+
+/// Entries serialize as a struct with a single `fields` map, in field order.
+#[cfg(feature = "serde")]
+impl Serialize for Entry {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeMap, SerializeStruct};
+
+        struct OrderedFields<'a>(&'a Entry);
+
+        impl Serialize for OrderedFields<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(self.0.len()))?;
+                for (name, value) in self.0.iter() {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+
+        let mut state = serializer.serialize_struct("Entry", 1)?;
+        state.serialize_field("fields", &OrderedFields(self))?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Entry")]
+        struct Repr {
+            fields: Fields,
+        }
+
+        Repr::deserialize(deserializer).map(|repr| Entry::from_fields(repr.fields))
+    }
+}
+
+/// The fields [`Entry::timestamp`] reads the time from, in order of preference.
+const TIME_FIELDS: [&str; 3] = ["time_local", "time_iso8601", "msec"];
+
+/// Parse a field value, treating a missing value or nginx's `-` as absent.
+pub(crate) fn parse_value<T>(name: &str, value: Option<&str>) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match value {
+        None | Some("-") => Ok(None),
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| Error::field_parse_error(name, value, std::any::type_name::<T>(), e)),
+    }
+}
+
+/// Build the error for a field position that has no value.
+fn index_not_found(schema: &Schema, index: usize) -> Error {
+    match schema.name(index) {
+        Some(name) => Error::field_not_found(name),
+        None => Error::field_not_found(format!("#{}", index)),
+    }
+}
+
+/// A parsed log entry that borrows its values from the log line.
+///
+/// Produced by [`Parser::parse_ref`](crate::Parser::parse_ref) and
+/// [`Reader::read_ref`](crate::Reader::read_ref). Field names are shared with the
+/// parser and values are slices of the line, so parsing allocates no strings
+/// unless values have to be unescaped. Use [`EntryRef::to_owned`] to keep an
+/// entry beyond the lifetime of the line.
+///
+/// Like an [`Entry`], the [`LineInfo`] and raw line of a borrowed entry are
+/// metadata and are ignored when comparing entries.
+#[derive(Debug, Clone)]
+pub struct EntryRef<'a> {
+    /// The field names, shared with the parser.
+    schema: &'a Arc<Schema>,
+    /// The field values, indexed by their position in the schema.
+    values: Vec<Option<Cow<'a, str>>>,
+    /// The location of the line this entry was parsed from.
+    line_info: Option<LineInfo>,
+    /// The bytes of the line this entry was parsed from, if it wasn't valid UTF-8.
+    raw_line: Option<&'a [u8]>,
+}
+
+impl<'a> EntryRef<'a> {
+    /// Create a borrowed entry from a schema and values in schema order.
+    pub(crate) fn new(schema: &'a Arc<Schema>, values: Vec<Option<Cow<'a, str>>>) -> Self {
+        debug_assert_eq!(schema.len(), values.len());
+        Self {
+            schema,
+            values,
+            line_info: None,
+            raw_line: None,
+        }
+    }
+
+    /// Attach the location of the line this entry was parsed from.
+    pub fn with_line_info(mut self, line_info: LineInfo) -> Self {
+        self.line_info = Some(line_info);
+        self
+    }
+
+    /// Get the location of the line this entry was parsed from, if known.
+    pub fn line_info(&self) -> Option<LineInfo> {
+        self.line_info
+    }
+
+    /// Attach the bytes of the line this entry was parsed from.
+    pub fn with_raw_line(mut self, line: &'a [u8]) -> Self {
+        self.raw_line = Some(line);
+        self
+    }
+
+    /// Get the bytes of the line this entry was parsed from, if it wasn't valid UTF-8.
+    ///
+    /// See [`Entry::raw_line`].
+    pub fn raw_line(&self) -> Option<&'a [u8]> {
+        self.raw_line
+    }
+
+    /// Get a field value as a string.
+    ///
+    /// Returns an error if the field doesn't exist.
+    ///
+    /// Fields derived from `request` are available as for [`Entry::field`].
+    pub fn field(&self, name: &str) -> Result<&str> {
+        self.get_field(name)
+            .or_else(|| request::derived_field(self.get_field("request"), name))
+            .ok_or_else(|| Error::field_not_found(name))
+    }
+
+    /// Split the `request` field into its method, URI and protocol.
+    ///
+    /// Returns `None` if the entry has no `request` field or it is malformed.
+    pub fn request(&self) -> Option<RequestLine<'_>> {
+        RequestLine::parse(self.get_field("request")?)
+    }
+
+    /// Look up a field value in the schema.
+    fn get_field(&self, name: &str) -> Option<&str> {
+        self.schema
+            .index_of(name)
+            .and_then(|index| self.values[index].as_deref())
+    }
+
+    /// Get a field value by its position in the schema.
+    pub fn field_by_index(&self, index: usize) -> Result<&str> {
+        match self.values.get(index) {
+            Some(Some(value)) => Ok(value),
+            _ => Err(index_not_found(self.schema, index)),
+        }
+    }
+
+    /// Get a field value parsed as any type that implements [`FromStr`].
+    ///
+    /// A `-` value counts as absent, as for [`Entry::get`].
+    pub fn get<T>(&self, name: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.get_opt(name)?
+            .ok_or_else(|| Error::field_not_found(name))
+    }
+
+    /// Get a field value parsed as any type that implements [`FromStr`], or
+    /// `None` if the field is missing or `-`.
+    pub fn get_opt<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        parse_value(name, self.field(name).ok())
+    }
+
+    /// Get the schema shared with the parser that produced this entry.
+    pub fn schema(&self) -> &Arc<Schema> {
+        self.schema
+    }
+
+    /// Get an iterator over all field names and values, in format order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.schema
+            .names()
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| value.as_deref().map(|value| (name.as_str(), value)))
+    }
+
+    /// Get the number of fields in this entry.
+    pub fn len(&self) -> usize {
+        self.values.iter().flatten().count()
+    }
+
+    /// Check if this entry has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy the borrowed values into an owned [`Entry`].
+    pub fn to_owned(&self) -> Entry {
+        let values = self
+            .values
+            .iter()
+            .map(|value| value.as_deref().map(str::to_string))
+            .collect();
+
+        Entry {
+            line_info: self.line_info,
+            raw_line: self.raw_line.map(<[u8]>::to_vec),
+            ..Entry::with_schema(Arc::clone(self.schema), values)
+        }
+    }
+}
+
+impl PartialEq for EntryRef<'_> {
+    /// Entries are equal if they have the same fields and values, regardless of
+    /// field order or metadata.
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(name, value)| other.get_field(name) == Some(value))
+    }
+}
+
+impl From<EntryRef<'_>> for Entry {
+    fn from(entry: EntryRef<'_>) -> Self {
+        let values = entry
+            .values
+            .into_iter()
+            .map(|value| value.map(Cow::into_owned))
+            .collect();
+
+        Entry {
+            line_info: entry.line_info,
+            raw_line: entry.raw_line.map(<[u8]>::to_vec),
+            ..Entry::with_schema(Arc::clone(entry.schema), values)
+        }
     }
 }
@@ -1,5 +1,8 @@
 //! Error types for the rsnx library.
 
+use crate::diagnostic::Diagnostic;
+use crate::entry::LineInfo;
+use crate::input::Compression;
 use thiserror::Error;
 
 /// Result type alias for rsnx operations.
@@ -23,8 +26,30 @@ pub enum Error {
     },
 
     /// Error when a log line doesn't match the expected format.
-    #[error("log line '{line}' does not match format '{format}'")]
-    LineFormatMismatch { line: String, format: String },
+    #[error(
+        "log line '{line}'{} does not match format '{format}'{}",
+        location(.line_info),
+        cause(.diagnostic)
+    )]
+    LineFormatMismatch {
+        line: String,
+        format: String,
+        line_info: Option<LineInfo>,
+        diagnostic: Option<Box<Diagnostic>>,
+    },
+
+    /// Error when a log line isn't valid UTF-8.
+    ///
+    /// The line is kept with invalid bytes replaced by U+FFFD.
+    #[error(
+        "log line '{line}'{} is not valid UTF-8 after byte {valid_up_to}",
+        location(.line_info)
+    )]
+    InvalidUtf8 {
+        line: String,
+        valid_up_to: usize,
+        line_info: Option<LineInfo>,
+    },
 
     /// Error when parsing a format string into a regex.
     #[error("invalid format string '{format}': {source}")]
@@ -55,6 +80,41 @@ pub enum Error {
     /// Error when nginx configuration parsing fails.
     #[error("failed to parse nginx configuration: {message}")]
     NginxConfigError { message: String },
+
+    /// Error when an entry cannot be deserialized into the requested type.
+    #[error("failed to deserialize entry: {message}")]
+    DeserializeError { message: String },
+
+    /// Error when input is compressed in a format whose cargo feature isn't enabled.
+    #[error(
+        "input is {compression} compressed, enable the '{}' feature to read it",
+        .compression.feature().unwrap_or_default()
+    )]
+    UnsupportedCompression { compression: Compression },
+
+    /// Error when a way of reading that needs the raw bytes of a file, such as
+    /// memory mapping it, is given a compressed file.
+    #[error("{compression} compressed files can't be {operation}")]
+    CompressedInput {
+        compression: Compression,
+        operation: String,
+    },
+
+    /// Error when a saved checkpoint can't be parsed.
+    #[error("invalid checkpoint: {message}")]
+    InvalidCheckpoint { message: String },
+
+    /// Error when a reader rejected more malformed lines than its error policy allows.
+    #[error("too many malformed lines: {rejected} of {total} lines rejected")]
+    TooManyErrors { rejected: u64, total: u64 },
+
+    /// Error when an error policy is given a rate that isn't between 0.0 and 1.0.
+    #[error("invalid error rate {rate}, expected a value between 0.0 and 1.0")]
+    InvalidErrorRate { rate: f64 },
+
+    /// Error when a pattern registered for a variable can't be used.
+    #[error("invalid pattern for ${name}: {reason}")]
+    InvalidPattern { name: String, reason: String },
 }
 
 impl Error {
@@ -85,6 +145,8 @@ impl Error {
         Self::LineFormatMismatch {
             line: line.into(),
             format: format.into(),
+            line_info: None,
+            diagnostic: None,
         }
     }
 
@@ -109,4 +171,123 @@ impl Error {
             message: message.into(),
         }
     }
+
+    /// Create a new deserialize error.
+    pub fn deserialize_error(message: impl Into<String>) -> Self {
+        Self::DeserializeError {
+            message: message.into(),
+        }
+    }
+
+    /// Create a new unsupported compression error.
+    pub fn unsupported_compression(compression: Compression) -> Self {
+        Self::UnsupportedCompression { compression }
+    }
+
+    /// Create a new compressed input error.
+    pub fn compressed_input(compression: Compression, operation: impl Into<String>) -> Self {
+        Self::CompressedInput {
+            compression,
+            operation: operation.into(),
+        }
+    }
+
+    /// Create a new invalid checkpoint error.
+    pub fn invalid_checkpoint(message: impl Into<String>) -> Self {
+        Self::InvalidCheckpoint {
+            message: message.into(),
+        }
+    }
+
+    /// Create a new invalid UTF-8 error for a line.
+    pub fn invalid_utf8(line: &[u8], valid_up_to: usize) -> Self {
+        Self::InvalidUtf8 {
+            line: String::from_utf8_lossy(line).into_owned(),
+            valid_up_to,
+            line_info: None,
+        }
+    }
+
+    /// Create a new too many errors error.
+    pub fn too_many_errors(rejected: u64, total: u64) -> Self {
+        Self::TooManyErrors { rejected, total }
+    }
+
+    /// Create a new invalid error rate error.
+    pub fn invalid_error_rate(rate: f64) -> Self {
+        Self::InvalidErrorRate { rate }
+    }
+
+    /// Create a new invalid pattern error.
+    pub fn invalid_pattern(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::InvalidPattern {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Check if this error was caused by a single malformed log line.
+    ///
+    /// These are the errors that an [`ErrorPolicy`](crate::ErrorPolicy) may skip.
+    pub fn is_line_error(&self) -> bool {
+        matches!(
+            self,
+            Self::LineFormatMismatch { .. } | Self::InvalidUtf8 { .. }
+        )
+    }
+
+    /// Attach the location of the offending line to a line error.
+    ///
+    /// Errors that aren't about a single line are returned unchanged.
+    pub fn with_line_info(mut self, info: LineInfo) -> Self {
+        if let Self::LineFormatMismatch { line_info, .. } | Self::InvalidUtf8 { line_info, .. } =
+            &mut self
+        {
+            *line_info = Some(info);
+        }
+        self
+    }
+
+    /// Attach a diagnostic explaining where a line diverged from its format.
+    ///
+    /// Errors other than [`Error::LineFormatMismatch`] are returned unchanged.
+    pub fn with_diagnostic(mut self, value: Diagnostic) -> Self {
+        if let Self::LineFormatMismatch { diagnostic, .. } = &mut self {
+            *diagnostic = Some(Box::new(value));
+        }
+        self
+    }
+
+    /// Get the diagnostic explaining where a line diverged from its format, if any.
+    pub fn diagnostic(&self) -> Option<&Diagnostic> {
+        match self {
+            Self::LineFormatMismatch { diagnostic, .. } => diagnostic.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Get the location of the offending line, if known.
+    pub fn line_info(&self) -> Option<LineInfo> {
+        match self {
+            Self::LineFormatMismatch { line_info, .. } | Self::InvalidUtf8 { line_info, .. } => {
+                *line_info
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Format an optional line location for error messages.
+fn location(line_info: &Option<LineInfo>) -> String {
+    line_info
+        .map(|info| format!(" at {}", info))
+        .unwrap_or_default()
+}
+
+/// Format an optional mismatch diagnostic for error messages.
+fn cause(diagnostic: &Option<Box<Diagnostic>>) -> String {
+    diagnostic
+        .as_ref()
+        .map(|diagnostic| format!(": {}", diagnostic))
+        .unwrap_or_default()
 }
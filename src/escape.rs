@@ -0,0 +1,208 @@
+//! Escape sequences nginx uses when writing variable values to access logs.
+
+use crate::error::{Error, Result};
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// How variable values were escaped when nginx wrote them to the log.
+///
+/// This corresponds to the `escape` parameter of the `log_format` directive.
+/// The default is [`EscapeMode::None`], which is also what a
+/// [`Parser`](crate::Parser) uses unless an escape mode is set, so escape
+/// sequences are only interpreted when asked for. nginx itself uses
+/// [`EscapeMode::Default`] when a `log_format` has no `escape` parameter, and
+/// [`extract_nginx_log_format`](crate::nginx::extract_nginx_log_format) returns
+/// it for such directives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscapeMode {
+    /// `escape=default`: `"`, `\` and bytes outside the printable ASCII range
+    /// are written as `\xHH`.
+    Default,
+    /// `escape=json`: values are escaped as JSON string contents, such as `\"`,
+    /// `\\`, `\n` and `\u0001`.
+    Json,
+    /// `escape=none`: values are written as they are.
+    #[default]
+    None,
+}
+
+impl EscapeMode {
+    /// Decode the escape sequences in a value.
+    ///
+    /// Values without escape sequences are returned without copying. Malformed
+    /// sequences are kept as they are.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::EscapeMode;
+    ///
+    /// assert_eq!(EscapeMode::Default.unescape(r"say \x22hi\x22"), r#"say "hi""#);
+    /// assert_eq!(EscapeMode::Json.unescape(r#"say \"hi\""#), r#"say "hi""#);
+    /// assert_eq!(EscapeMode::None.unescape(r"\x22"), r"\x22");
+    /// ```
+    pub fn unescape<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        if !value.contains('\\') {
+            return Cow::Borrowed(value);
+        }
+
+        match self {
+            EscapeMode::Default => Cow::Owned(unescape_default(value)),
+            EscapeMode::Json => Cow::Owned(unescape_json(value)),
+            EscapeMode::None => Cow::Borrowed(value),
+        }
+    }
+
+    /// Check if a backslash in a value always starts an escape sequence.
+    pub(crate) fn escapes_backslash(&self) -> bool {
+        !matches!(self, EscapeMode::None)
+    }
+}
+
+impl FromStr for EscapeMode {
+    type Err = Error;
+
+    /// Parse the value of a `log_format` `escape=` parameter.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(EscapeMode::Default),
+            "json" => Ok(EscapeMode::Json),
+            "none" => Ok(EscapeMode::None),
+            _ => Err(Error::nginx_config_error(format!(
+                "unknown escape mode '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Decode `\xHH` sequences, which nginx writes byte by byte.
+fn unescape_default(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'x') {
+            if let Some(byte) = value.get(i + 2..i + 4).and_then(parse_hex_byte) {
+                decoded.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    match String::from_utf8(decoded) {
+        Ok(decoded) => decoded,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+/// Decode JSON string escapes.
+fn unescape_json(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(backslash) = rest.find('\\') {
+        decoded.push_str(&rest[..backslash]);
+        rest = &rest[backslash..];
+
+        let (c, len) = match rest.as_bytes().get(1) {
+            Some(b'"') => ('"', 2),
+            Some(b'\\') => ('\\', 2),
+            Some(b'/') => ('/', 2),
+            Some(b'b') => ('\u{8}', 2),
+            Some(b'f') => ('\u{c}', 2),
+            Some(b'n') => ('\n', 2),
+            Some(b'r') => ('\r', 2),
+            Some(b't') => ('\t', 2),
+            Some(b'u') => parse_json_unicode(rest).unwrap_or(('\\', 1)),
+            _ => ('\\', 1),
+        };
+        decoded.push(c);
+        rest = &rest[len..];
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decode a `\uXXXX` escape at the start of `s`, combining surrogate pairs.
+///
+/// Returns the character and the length of the escape. Unpaired surrogates
+/// decode to U+FFFD.
+fn parse_json_unicode(s: &str) -> Option<(char, usize)> {
+    let high = parse_hex_u16(s.get(2..6)?)?;
+    if !(0xD800..0xDC00).contains(&high) {
+        return Some((char::from_u32(high.into()).unwrap_or('\u{FFFD}'), 6));
+    }
+
+    let low = s
+        .get(6..8)
+        .filter(|prefix| *prefix == "\\u")
+        .and_then(|_| parse_hex_u16(s.get(8..12)?))
+        .filter(|low| (0xDC00..0xE000).contains(low));
+
+    match low {
+        Some(low) => {
+            let code = 0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(low) - 0xDC00);
+            Some((char::from_u32(code)?, 12))
+        }
+        None => Some(('\u{FFFD}', 6)),
+    }
+}
+
+fn parse_hex_byte(s: &str) -> Option<u8> {
+    s.bytes()
+        .all(|b| b.is_ascii_hexdigit())
+        .then(|| u8::from_str_radix(s, 16).ok())
+        .flatten()
+}
+
+fn parse_hex_u16(s: &str) -> Option<u16> {
+    s.bytes()
+        .all(|b| b.is_ascii_hexdigit())
+        .then(|| u16::from_str_radix(s, 16).ok())
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape_default() {
+        let mode = EscapeMode::Default;
+        assert_eq!(mode.unescape(r"\x5C\x22quoted\x22"), r#"\"quoted""#);
+        assert_eq!(mode.unescape(r"caf\xC3\xA9"), "café");
+        assert_eq!(mode.unescape(r"\xZZ \x4"), r"\xZZ \x4");
+        assert_eq!(mode.unescape(r"\xFF"), "\u{FFFD}");
+        assert!(matches!(mode.unescape("plain"), Cow::Borrowed("plain")));
+    }
+
+    #[test]
+    fn test_unescape_json() {
+        let mode = EscapeMode::Json;
+        assert_eq!(mode.unescape(r#"a\"b\\c\/d"#), r#"a"b\c/d"#);
+        assert_eq!(mode.unescape(r"line\nbreak\t\u0001"), "line\nbreak\t\u{1}");
+        assert_eq!(mode.unescape(r"\ud83d\ude00"), "\u{1F600}");
+        assert_eq!(mode.unescape(r"\ud83d"), "\u{FFFD}");
+        assert_eq!(mode.unescape(r"\q\u12"), r"\q\u12");
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("json".parse::<EscapeMode>().unwrap(), EscapeMode::Json);
+        assert_eq!("none".parse::<EscapeMode>().unwrap(), EscapeMode::None);
+        assert!("html".parse::<EscapeMode>().is_err());
+    }
+
+    #[test]
+    fn test_default() {
+        let parser = crate::Parser::new("$request").unwrap();
+        assert_eq!(EscapeMode::default(), EscapeMode::None);
+        assert_eq!(parser.escape(), EscapeMode::default());
+    }
+}
@@ -0,0 +1,528 @@
+//! Following a log file as it grows, like `tail -F`.
+
+use crate::checkpoint::file_id;
+use crate::entry::{Entry, LineInfo};
+use crate::error::Result;
+use crate::parser::Parser;
+use crate::policy::{ErrorPolicy, ErrorSummary, ErrorTracker, Utf8Policy};
+use crate::reader::is_blank_line;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::iter::FusedIterator;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The default time to wait between checks for new data.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of bytes read from the file at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A reader that keeps reading a log file as lines are appended, like `tail -F`.
+///
+/// The reader starts at the end of the file, so only lines written after it
+/// was opened are returned, unless [`FollowReader::from_beginning`] is used.
+/// When no complete line is available, [`FollowReader::read`] polls the file
+/// until one is written, and a partial last line is held back until its
+/// newline arrives.
+///
+/// The reader follows the path rather than the open file:
+///
+/// - when the file is renamed away and a new one is created, as logrotate does
+///   by default, the rest of the old file is read before switching to the new one
+/// - when the file shrinks, as with logrotate's `copytruncate`, reading starts
+///   over from its beginning
+/// - when the file is removed, the reader waits for it to be created again
+///
+/// Replaced files are recognized by their inode on Unix. On other platforms
+/// only truncation is detected.
+///
+/// [`FollowReader::read`] and iteration block until a line arrives, and only end
+/// when the error policy stops the reader, after which they keep returning
+/// `None`. To stop waiting after a while, use [`FollowReader::read_timeout`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rsnx::FollowReader;
+/// use std::time::Duration;
+///
+/// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+/// let reader = FollowReader::open("/var/log/nginx/access.log", format)?
+///     .with_poll_interval(Duration::from_millis(100));
+///
+/// for entry in reader {
+///     let entry = entry?;
+///     println!("{} {}", entry.field("status")?, entry.field("request")?);
+/// }
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct FollowReader {
+    path: Arc<Path>,
+    /// The file being read, or `None` if it didn't exist yet.
+    file: Option<FollowedFile>,
+    parser: Parser,
+    /// The error policy and summary of rejected lines.
+    tracker: ErrorTracker,
+    utf8_policy: Utf8Policy,
+    poll_interval: Duration,
+    /// Data read from the file that hasn't been returned yet.
+    buffer: Vec<u8>,
+    /// The start of the unread data in the buffer.
+    start: usize,
+    /// Set to return the partial last line of a file that was replaced.
+    flush: bool,
+    /// The location of the next line to be read.
+    position: LineInfo,
+}
+
+/// An open file and how much of it has been read.
+#[derive(Debug)]
+struct FollowedFile {
+    file: File,
+    /// The identity of the file, to tell when the path is replaced.
+    id: Option<(u64, u64)>,
+    /// The number of bytes read from the file.
+    offset: u64,
+}
+
+impl FollowedFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        Ok(Self {
+            file,
+            id,
+            offset: 0,
+        })
+    }
+}
+
+impl FollowReader {
+    /// Start following a log file from its current end.
+    ///
+    /// The file doesn't have to exist yet; it is read from the beginning once
+    /// it is created. Returns an error if the format string is invalid or the
+    /// file can't be opened.
+    pub fn open(path: impl AsRef<Path>, format: &str) -> Result<Self> {
+        Self::with_parser(path, Parser::new(format)?)
+    }
+
+    /// Start following a log file with a custom parser.
+    pub fn with_parser(path: impl AsRef<Path>, parser: Parser) -> Result<Self> {
+        let path: Arc<Path> = Arc::from(path.as_ref());
+        let mut position = LineInfo::new(1, 0);
+        let file = match FollowedFile::open(&path) {
+            Ok(mut file) => {
+                file.offset = file.file.metadata()?.len();
+                if file.offset > 0 {
+                    position = LineInfo {
+                        line_number: None,
+                        byte_offset: file.offset,
+                    };
+                }
+                Some(file)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            file,
+            parser,
+            tracker: ErrorTracker::default(),
+            utf8_policy: Utf8Policy::default(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            buffer: Vec::new(),
+            start: 0,
+            flush: false,
+            position,
+        })
+    }
+
+    /// Read the lines already in the file before following it.
+    pub fn from_beginning(mut self) -> Self {
+        if let Some(file) = &mut self.file {
+            file.offset = 0;
+        }
+        self.position = LineInfo::new(1, 0);
+        self
+    }
+
+    /// Set the time to wait between checks for new data. The default is 250ms.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// See [`Reader::with_error_policy`](crate::Reader::with_error_policy). As
+    /// a followed file has no end, [`ErrorPolicy::MaxErrorRate`] never fails.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.tracker = ErrorTracker::new(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`](crate::Reader::with_utf8_policy).
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Get the path being followed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get a reference to the underlying parser.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Get the summary of lines rejected so far.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.tracker.summary()
+    }
+
+    /// Get the location of the next line to be read in the current file.
+    ///
+    /// When following from the end of a file, the line number is unknown until
+    /// the file is replaced or truncated.
+    pub fn position(&self) -> LineInfo {
+        self.position
+    }
+
+    /// Read the next entry, waiting for one to be written.
+    ///
+    /// Returns `None` only once the error policy stopped the reader. Entries
+    /// carry their [`LineInfo`] and the followed path as their [`Entry::source`].
+    pub fn read(&mut self) -> Option<Result<Entry>> {
+        // Without a deadline, waiting never times out
+        self.wait(None).flatten()
+    }
+
+    /// Read the next entry, waiting at most `timeout` for one to be written.
+    ///
+    /// Returns `None` if no complete line arrived in time, in which case the
+    /// reader can be read again later. Otherwise returns what
+    /// [`FollowReader::read`] would, so `Some(None)` means the error policy
+    /// stopped the reader.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use rsnx::FollowReader;
+    /// use std::time::Duration;
+    ///
+    /// let mut reader = FollowReader::open("/var/log/nginx/access.log", "$remote_addr $status")?;
+    /// while let Some(Some(entry)) = reader.read_timeout(Duration::from_secs(1)) {
+    ///     println!("{}", entry?.field("status")?);
+    /// }
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn read_timeout(&mut self, timeout: Duration) -> Option<Option<Result<Entry>>> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    /// Read the next entry, polling until one is written or `deadline` passes.
+    fn wait(&mut self, deadline: Option<Instant>) -> Option<Option<Result<Entry>>> {
+        loop {
+            if let Some(result) = self.try_read() {
+                return Some(Some(result));
+            }
+            if self.tracker.is_done() {
+                return Some(None);
+            }
+
+            let mut wait = self.poll_interval;
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return None;
+                }
+                wait = wait.min(remaining);
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    /// Read the next entry if a complete line is available, without waiting.
+    pub fn try_read(&mut self) -> Option<Result<Entry>> {
+        loop {
+            if self.tracker.is_done() {
+                return None;
+            }
+
+            let (range, info) = match self.next_line()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let result = self
+                .utf8_policy
+                .parse(&self.parser, &self.buffer[range])
+                .map(|entry| {
+                    entry
+                        .with_line_info(info)
+                        .with_source(Arc::clone(&self.path))
+                })
+                .map_err(|e| e.with_line_info(info));
+            if let Some(result) = self.tracker.handle(result) {
+                return Some(result);
+            }
+        }
+    }
+
+    /// Find the next non-empty line, reading more of the file as needed.
+    ///
+    /// Returns the range of the line in the buffer, without its line ending, or
+    /// `None` if no complete line is available yet.
+    fn next_line(&mut self) -> Option<Result<(Range<usize>, LineInfo)>> {
+        loop {
+            let Some(mut range) = self.take_line() else {
+                match self.refill() {
+                    Ok(true) => continue,
+                    Ok(false) => return None,
+                    Err(e) => return Some(Err(e)),
+                }
+            };
+
+            let info = self.position;
+            self.position.line_number = info.line_number.map(|n| n + 1);
+            self.position.byte_offset += (range.end - range.start) as u64;
+
+            // Remove trailing newline
+            if self.buffer[..range.end].ends_with(b"\n") {
+                range.end -= 1;
+                if self.buffer[..range.end].ends_with(b"\r") {
+                    range.end -= 1;
+                }
+            }
+
+            // Skip empty lines
+            if !is_blank_line(&self.buffer[range.clone()]) {
+                return Some(Ok((range, info)));
+            }
+        }
+    }
+
+    /// Take the next complete line from the buffer, with its line ending.
+    fn take_line(&mut self) -> Option<Range<usize>> {
+        let unread = &self.buffer[self.start..];
+        let len = match unread.iter().position(|&b| b == b'\n') {
+            Some(newline) => newline + 1,
+            None if self.flush && !unread.is_empty() => unread.len(),
+            None => return None,
+        };
+        self.flush = false;
+
+        let range = self.start..self.start + len;
+        self.start = range.end;
+        Some(range)
+    }
+
+    /// Read more data, or switch files if the path was truncated or replaced.
+    ///
+    /// Returns `false` if there is nothing new to read.
+    fn refill(&mut self) -> Result<bool> {
+        let Some(file) = &mut self.file else {
+            return self.check_file();
+        };
+
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        let len = self.buffer.len();
+        self.buffer.resize(len + CHUNK_SIZE, 0);
+        let read = file
+            .file
+            .seek(SeekFrom::Start(file.offset))
+            .and_then(|_| file.file.read(&mut self.buffer[len..]));
+        self.buffer.truncate(len + *read.as_ref().unwrap_or(&0));
+
+        match read? {
+            0 => self.check_file(),
+            n => {
+                file.offset += n as u64;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Check whether the path was truncated, replaced or created, and start
+    /// reading it from the beginning if so.
+    fn check_file(&mut self) -> Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Removed or renamed away, wait for it to be created again
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let changed = match &self.file {
+            Some(file) => file.id != file_id(&metadata) || metadata.len() < file.offset,
+            None => true,
+        };
+        if !changed {
+            return Ok(false);
+        }
+
+        // Return the partial last line of the old file before moving on
+        if self.start < self.buffer.len() {
+            self.flush = true;
+            return Ok(true);
+        }
+
+        self.file = match FollowedFile::open(&self.path) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        self.buffer.clear();
+        self.start = 0;
+        self.position = LineInfo::new(1, 0);
+        Ok(true)
+    }
+}
+
+impl Iterator for FollowReader {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read()
+    }
+}
+
+impl FusedIterator for FollowReader {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn append(path: &Path, data: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    fn status(result: Option<Result<Entry>>) -> String {
+        result
+            .unwrap()
+            .unwrap()
+            .field("status")
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_partial_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "10.0.0.1 200\n");
+
+        let mut reader = FollowReader::open(&path, "$remote_addr $status").unwrap();
+        assert!(reader.try_read().is_none());
+        assert_eq!(reader.position().line_number, None);
+
+        append(&path, "10.0.0.1 301\n\n\u{a0}\x0b\n10.0.0.1 4");
+        assert_eq!(status(reader.try_read()), "301");
+        assert!(reader.try_read().is_none());
+
+        append(&path, "04\r\n");
+        let entry = reader.try_read().unwrap().unwrap();
+        assert_eq!(entry.field("status").unwrap(), "404");
+        assert_eq!(entry.source(), Some(path.as_path()));
+        assert_eq!(entry.line_info().unwrap().byte_offset, 31);
+
+        let mut reader = FollowReader::open(&path, "$remote_addr $status")
+            .unwrap()
+            .from_beginning();
+        assert_eq!(status(reader.try_read()), "200");
+    }
+
+    #[test]
+    fn test_truncate_and_recreate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+
+        let mut reader = FollowReader::open(&path, "$remote_addr $status").unwrap();
+        assert!(reader.try_read().is_none());
+
+        append(&path, "10.0.0.1 200\n10.0.0.1 201\n");
+        assert_eq!(status(reader.try_read()), "200");
+        assert_eq!(status(reader.try_read()), "201");
+
+        // copytruncate
+        fs::write(&path, "10.0.0.1 202\n").unwrap();
+        let entry = reader.try_read().unwrap().unwrap();
+        assert_eq!(entry.field("status").unwrap(), "202");
+        assert_eq!(entry.line_info(), Some(LineInfo::new(1, 0)));
+        append(&path, "10.0.0.1 203\n");
+        assert_eq!(status(reader.try_read()), "203");
+
+        fs::remove_file(&path).unwrap();
+        assert!(reader.try_read().is_none());
+        append(&path, "10.0.0.1 204\n");
+        assert_eq!(status(reader.try_read()), "204");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rename_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "10.0.0.1 200\n");
+
+        let timeout = Duration::from_millis(20);
+        let mut reader = FollowReader::open(&path, "$remote_addr $status")
+            .unwrap()
+            .with_poll_interval(Duration::from_millis(5));
+        assert!(reader.read_timeout(timeout).is_none());
+
+        // The old file is written to until nginx reopens its logs
+        append(&path, "10.0.0.1 201\n10.0.0.1 2");
+        let rotated = dir.path().join("access.log.1");
+        fs::rename(&path, &rotated).unwrap();
+        append(&rotated, "02");
+        append(&path, "10.0.0.1 300\n");
+
+        let statuses: Vec<_> = std::iter::from_fn(|| reader.read_timeout(timeout).flatten())
+            .map(|e| status(Some(e)))
+            .collect();
+        assert_eq!(statuses, ["201", "202", "300"]);
+    }
+
+    #[test]
+    fn test_fused_after_policy_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        append(&path, "");
+
+        let mut reader = FollowReader::open(&path, "$remote_addr $status")
+            .unwrap()
+            .with_poll_interval(Duration::from_millis(5))
+            .with_error_policy(ErrorPolicy::MaxErrors(0));
+        append(&path, "bogus\n10.0.0.1 200\n");
+
+        assert!(matches!(
+            reader.next(),
+            Some(Err(crate::Error::TooManyErrors { .. }))
+        ));
+        assert!(reader.next().is_none());
+        assert!(reader.next().is_none());
+        assert!(matches!(
+            reader.read_timeout(Duration::from_millis(20)),
+            Some(None)
+        ));
+    }
+}
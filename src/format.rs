@@ -0,0 +1,111 @@
+//! Tokenizing log format strings into literal and field segments.
+
+/// A piece of a log format string.
+///
+/// A format such as `$remote_addr [$time_local]` is made of the segments
+/// `Field("remote_addr")`, `Literal(" [")`, `Field("time_local")` and `Literal("]")`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Text that must appear verbatim in the log line.
+    Literal(String),
+    /// A `$variable` or `${variable}` whose value is captured as a field.
+    Field(String),
+}
+
+impl Segment {
+    /// Check if this segment is a field.
+    pub fn is_field(&self) -> bool {
+        matches!(self, Segment::Field(_))
+    }
+
+    /// Get the variable name if this segment is a field.
+    pub fn field_name(&self) -> Option<&str> {
+        match self {
+            Segment::Field(name) => Some(name),
+            Segment::Literal(_) => None,
+        }
+    }
+}
+
+/// Split a format string into literal and field segments.
+///
+/// A field is a `$` followed by one or more word characters. As in nginx, the
+/// name can be wrapped in braces to separate it from the text that follows, so
+/// `${request_time}ms` is the field `request_time` followed by the literal `ms`.
+/// A `$` that isn't followed by a word character or a complete `{name}` is kept
+/// as literal text. Literal segments are never empty.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::format::{tokenize, Segment};
+///
+/// let segments = tokenize("$remote_addr [$time_local]");
+/// assert_eq!(
+///     segments,
+///     vec![
+///         Segment::Field("remote_addr".to_string()),
+///         Segment::Literal(" [".to_string()),
+///         Segment::Field("time_local".to_string()),
+///         Segment::Literal("]".to_string()),
+///     ]
+/// );
+///
+/// assert_eq!(
+///     tokenize("${request_time}ms"),
+///     vec![
+///         Segment::Field("request_time".to_string()),
+///         Segment::Literal("ms".to_string()),
+///     ]
+/// );
+/// ```
+pub fn tokenize(format: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = format;
+
+    while let Some(dollar) = rest.find('$') {
+        literal.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+
+        let Some((name, remaining)) = braced_name(after).or_else(|| bare_name(after)) else {
+            // A lone `$` is literal text
+            literal.push('$');
+            rest = after;
+            continue;
+        };
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+        }
+        segments.push(Segment::Field(name.to_string()));
+        rest = remaining;
+    }
+
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+
+    segments
+}
+
+/// Split a `name` off the text after a `$`, returning the name and the rest.
+fn bare_name(after: &str) -> Option<(&str, &str)> {
+    let len = after
+        .find(|c: char| !is_word_char(c))
+        .unwrap_or(after.len());
+    (len > 0).then(|| after.split_at(len))
+}
+
+/// Split a `{name}` off the text after a `$`, returning the name and the rest.
+fn braced_name(after: &str) -> Option<(&str, &str)> {
+    let inner = after.strip_prefix('{')?;
+    let (name, remaining) = bare_name(inner)?;
+    Some((name, remaining.strip_prefix('}')?))
+}
+
+/// Check if a character can be part of a variable name.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -0,0 +1,279 @@
+//! Opening log files with transparent decompression.
+//!
+//! Rotated logs are often compressed, such as `access.log.2.gz`. [`Input`]
+//! detects the compression of a stream from its magic bytes rather than the
+//! file name, and decodes it on the fly. Each codec is behind a cargo feature:
+//!
+//! - `gzip`: gzip, as written by logrotate's `compress`
+//! - `bzip2`: bzip2
+//! - `xz`: xz
+//! - `zstd`: Zstandard
+//!
+//! Uncompressed input is always supported and is read as it is.
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// A compression format recognized by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Not compressed.
+    None,
+    /// gzip, starting with `1f 8b`.
+    Gzip,
+    /// bzip2, starting with `BZh`.
+    Bzip2,
+    /// xz, starting with `fd 37 7a 58 5a 00`.
+    Xz,
+    /// Zstandard, starting with `28 b5 2f fd`.
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression of a stream from its first bytes.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::input::Compression;
+    ///
+    /// assert_eq!(Compression::detect(b"\x1f\x8b\x08\x00"), Compression::Gzip);
+    /// assert_eq!(Compression::detect(b"127.0.0.1 - -"), Compression::None);
+    /// ```
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Get the cargo feature that enables decoding this format.
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gzip"),
+            Compression::Bzip2 => Some("bzip2"),
+            Compression::Xz => Some("xz"),
+            Compression::Zstd => Some("zstd"),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Compression::None => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A log input that is decompressed transparently.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::input::{Compression, Input};
+/// use std::io::{Cursor, Read};
+///
+/// let mut input = Input::new(Cursor::new("127.0.0.1 200\n"))?;
+/// assert_eq!(input.compression(), Compression::None);
+///
+/// let mut text = String::new();
+/// input.read_to_string(&mut text)?;
+/// assert_eq!(text, "127.0.0.1 200\n");
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+pub struct Input {
+    inner: Box<dyn Read + Send>,
+    compression: Compression,
+}
+
+impl Input {
+    /// Open a file, detecting its compression.
+    ///
+    /// Returns an error if the file can't be opened, or it is compressed in a
+    /// format whose feature isn't enabled.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::open(path)?)
+    }
+
+    /// Wrap a stream, detecting its compression from the first bytes.
+    pub fn new<R: Read + Send + 'static>(input: R) -> Result<Self> {
+        let mut input = BufReader::new(input);
+        let compression = Compression::detect(input.fill_buf()?);
+        let inner = decoder(input, compression)?;
+        Ok(Self { inner, compression })
+    }
+
+    /// Open a file, skipping the first `offset` bytes of its decoded contents.
+    ///
+    /// Uncompressed files are seeked to the offset, while compressed ones are
+    /// decoded up to it.
+    pub(crate) fn open_at(path: impl AsRef<Path>, offset: u64) -> Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let compression = Compression::detect(input.fill_buf()?);
+        if compression == Compression::None {
+            input.seek(SeekFrom::Start(offset))?;
+            return Ok(Self {
+                inner: Box::new(input),
+                compression,
+            });
+        }
+
+        let mut inner = decoder(input, compression)?;
+        io::copy(&mut inner.by_ref().take(offset), &mut io::sink())?;
+        Ok(Self { inner, compression })
+    }
+
+    /// Get the compression the input was detected to have.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("compression", &self.compression)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Return an error if a file starting with `header` is compressed, for ways of
+/// reading that need the raw lines, such as `"memory-mapped"`.
+#[cfg_attr(not(any(feature = "rayon", feature = "mmap")), allow(dead_code))]
+pub(crate) fn require_uncompressed(header: &[u8], how: &str) -> Result<()> {
+    match Compression::detect(header) {
+        Compression::None => Ok(()),
+        compression => Err(Error::compressed_input(compression, how)),
+    }
+}
+
+/// Wrap a buffered stream in a decoder for its compression.
+fn decoder<R: BufRead + Send + 'static>(
+    input: R,
+    compression: Compression,
+) -> Result<Box<dyn Read + Send>> {
+    match compression {
+        Compression::None => Ok(Box::new(input)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(input))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Box::new(bzip2::bufread::MultiBzDecoder::new(input))),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(input))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(input)?)),
+        #[allow(unreachable_patterns)]
+        compression => Err(Error::unsupported_compression(compression)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(b"BZh91AY&SY"), Compression::Bzip2);
+        assert_eq!(
+            Compression::detect(b"\xfd7zXZ\x00\x00\x04"),
+            Compression::Xz
+        );
+        assert_eq!(
+            Compression::detect(b"\x28\xb5\x2f\xfd\x04"),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::detect(b"\x1f"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let mut input = Input::new(Cursor::new("")).unwrap();
+        let mut text = String::new();
+        input.read_to_string(&mut text).unwrap();
+        assert!(text.is_empty());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        // logrotate appends to compressed files as separate gzip members
+        let mut data = Vec::new();
+        for line in ["127.0.0.1 200\n", "127.0.0.1 404\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(line.as_bytes()).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+
+        let mut input = Input::new(Cursor::new(data)).unwrap();
+        assert_eq!(input.compression(), Compression::Gzip);
+        let mut text = String::new();
+        input.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "127.0.0.1 200\n127.0.0.1 404\n");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_open_at() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("access.log");
+        std::fs::write(&plain, "127.0.0.1 200\n127.0.0.1 404\n").unwrap();
+        let compressed = dir.path().join("access.log.1.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(b"127.0.0.1 200\n127.0.0.1 404\n")
+            .unwrap();
+        std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+
+        for path in [plain, compressed] {
+            let mut text = String::new();
+            Input::open_at(&path, 14)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text, "127.0.0.1 404\n");
+        }
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_unsupported_compression() {
+        let error = Input::new(Cursor::new(b"\x1f\x8b\x08\x00".to_vec())).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnsupportedCompression {
+                compression: Compression::Gzip
+            }
+        ));
+    }
+}
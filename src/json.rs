@@ -0,0 +1,404 @@
+//! Matching JSON log lines against the JSON template of a log format.
+
+use crate::error::Result;
+use crate::escape::EscapeMode;
+use crate::format::{self, Segment};
+use crate::parser::Spans;
+use crate::scanner::Program;
+use crate::schema::Schema;
+use crate::variables::VariableRegistry;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// What the value of a template key is bound to.
+#[derive(Debug, Clone)]
+enum Binding {
+    /// The value is a single field, identified by its position in the schema.
+    Field(usize),
+    /// The value mixes fields and literal text, and is split with a scan program.
+    Composite {
+        program: Program,
+        /// The schema position of each field in the program.
+        fields: Vec<usize>,
+    },
+    /// The value is a nested object.
+    Object(Object),
+}
+
+/// The keys of a template object that are bound to fields.
+#[derive(Debug, Clone, Default)]
+struct Object {
+    keys: HashMap<String, Binding>,
+}
+
+/// A log format whose text is a JSON object, such as
+/// `{"ts":"$time_iso8601","status":$status}`.
+///
+/// Lines are parsed as JSON objects and the value of each key is assigned to the
+/// variables the key was bound to in the template, so keys may appear in any
+/// order. The parser is tolerant of what nginx writes for empty variables, such
+/// as `"bytes":,` for an unquoted `$body_bytes_sent`.
+#[derive(Debug, Clone)]
+pub(crate) struct JsonTemplate {
+    root: Object,
+    /// Number of fields in the schema.
+    len: usize,
+}
+
+/// A parsed template value, before it is bound to the schema.
+enum TemplateValue<'a> {
+    /// The text of a string or bare value.
+    Text(&'a str),
+    /// A nested object.
+    Object(Vec<(&'a str, TemplateValue<'a>)>),
+    /// A value that can't hold fields, such as an array.
+    Other,
+}
+
+impl JsonTemplate {
+    /// Compile a format into a JSON template.
+    ///
+    /// Returns `None` if the format isn't a JSON object.
+    pub(crate) fn compile(
+        format: &str,
+        schema: &Schema,
+        variables: &VariableRegistry,
+        escape: EscapeMode,
+    ) -> Result<Option<Self>> {
+        let mut cursor = Cursor::new(format);
+        let members = match parse_template_object(&mut cursor) {
+            Some(members) if cursor.at_end() => members,
+            _ => return Ok(None),
+        };
+
+        let root = bind_object(members, schema, variables, escape)?;
+        Ok(Some(Self {
+            root,
+            len: schema.len(),
+        }))
+    }
+
+    /// Match a line, storing the byte range of each field in `spans`.
+    ///
+    /// Returns `false` if the line isn't a JSON object or a composite value
+    /// doesn't match its part of the template.
+    pub(crate) fn match_spans(&self, line: &str, spans: &mut Spans) -> bool {
+        spans.clear();
+        spans.resize(self.len, None);
+
+        let mut cursor = Cursor::new(line);
+        match_object(&self.root, &mut cursor, spans) && cursor.at_end()
+    }
+}
+
+/// Parse the members of a template object.
+fn parse_template_object<'a>(cursor: &mut Cursor<'a>) -> Option<Vec<(&'a str, TemplateValue<'a>)>> {
+    let mut members = Vec::new();
+    if !cursor.eat(b'{') {
+        return None;
+    }
+    if cursor.eat(b'}') {
+        return Some(members);
+    }
+
+    loop {
+        cursor.skip_whitespace();
+        let key = cursor.string()?;
+        if !cursor.eat(b':') {
+            return None;
+        }
+        cursor.skip_whitespace();
+
+        let value = match cursor.peek()? {
+            b'"' => TemplateValue::Text(&cursor.text[cursor.string()?]),
+            b'{' => TemplateValue::Object(parse_template_object(cursor)?),
+            b'[' => {
+                cursor.skip_value()?;
+                TemplateValue::Other
+            }
+            _ => TemplateValue::Text(&cursor.text[cursor.bare()]),
+        };
+        members.push((&cursor.text[key], value));
+
+        if cursor.eat(b',') {
+            continue;
+        }
+        return cursor.eat(b'}').then_some(members);
+    }
+}
+
+/// Bind the members of a template object to schema positions.
+fn bind_object(
+    members: Vec<(&str, TemplateValue<'_>)>,
+    schema: &Schema,
+    variables: &VariableRegistry,
+    escape: EscapeMode,
+) -> Result<Object> {
+    let mut object = Object::default();
+
+    for (key, value) in members {
+        let binding = match value {
+            TemplateValue::Text(text) => {
+                let segments = format::tokenize(text);
+                let fields: Vec<usize> = segments
+                    .iter()
+                    .filter_map(|segment| schema.index_of(segment.field_name()?))
+                    .collect();
+
+                match segments.as_slice() {
+                    [] => continue,
+                    [Segment::Field(_)] => Binding::Field(fields[0]),
+                    _ if fields.is_empty() => continue,
+                    _ => Binding::Composite {
+                        program: Program::compile(&segments, variables, escape)?,
+                        fields,
+                    },
+                }
+            }
+            TemplateValue::Object(members) => {
+                Binding::Object(bind_object(members, schema, variables, escape)?)
+            }
+            TemplateValue::Other => continue,
+        };
+        object.keys.insert(key.to_string(), binding);
+    }
+
+    Ok(object)
+}
+
+/// Match a JSON object in a line against a template object.
+fn match_object(object: &Object, cursor: &mut Cursor<'_>, spans: &mut Spans) -> bool {
+    if !cursor.eat(b'{') {
+        return false;
+    }
+    if cursor.eat(b'}') {
+        return true;
+    }
+
+    loop {
+        cursor.skip_whitespace();
+        let Some(key) = cursor.string() else {
+            return false;
+        };
+        if !cursor.eat(b':') {
+            return false;
+        }
+        cursor.skip_whitespace();
+
+        let matched = match object.keys.get(&cursor.text[key]) {
+            Some(Binding::Field(index)) => cursor.value().map(|value| {
+                spans[*index] = Some(value);
+            }),
+            Some(Binding::Composite { program, fields }) => cursor.value().and_then(|value| {
+                let mut parts = Spans::new();
+                if !program.match_spans(&cursor.text[value.clone()], &mut parts) {
+                    return None;
+                }
+                for (index, part) in fields.iter().zip(parts) {
+                    spans[*index] =
+                        part.map(|part| value.start + part.start..value.start + part.end);
+                }
+                Some(())
+            }),
+            Some(Binding::Object(nested)) if cursor.peek() == Some(b'{') => {
+                match_object(nested, cursor, spans).then_some(())
+            }
+            _ => cursor.skip_value(),
+        };
+        if matched.is_none() {
+            return false;
+        }
+
+        if cursor.eat(b',') {
+            continue;
+        }
+        return cursor.eat(b'}');
+    }
+}
+
+/// A position in JSON text.
+struct Cursor<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\r' | b'\n')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Check if only whitespace is left.
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos == self.text.len()
+    }
+
+    /// Skip whitespace and consume `byte` if it comes next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume a string, returning the range of its contents without the quotes.
+    ///
+    /// Escape sequences are kept as they are.
+    fn string(&mut self) -> Option<Range<usize>> {
+        if self.peek() != Some(b'"') {
+            return None;
+        }
+        let start = self.pos + 1;
+        let bytes = self.text.as_bytes();
+        let mut i = start;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => {
+                    self.pos = i + 1;
+                    return Some(start..i);
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    /// Consume an unquoted value such as a number, `true` or `null`.
+    ///
+    /// The value extends up to the next `,`, `}` or `]` and may be empty.
+    fn bare(&mut self) -> Range<usize> {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let len = rest.find([',', '}', ']']).unwrap_or(rest.len());
+        self.pos += len;
+        start..start + rest[..len].trim_end().len()
+    }
+
+    /// Consume any value, returning its range. Strings exclude their quotes.
+    fn value(&mut self) -> Option<Range<usize>> {
+        match self.peek() {
+            Some(b'"') => self.string(),
+            Some(b'{' | b'[') => {
+                let start = self.pos;
+                self.skip_value()?;
+                Some(start..self.pos)
+            }
+            _ => Some(self.bare()),
+        }
+    }
+
+    /// Consume any value, including nested objects and arrays.
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek() {
+            Some(b'"') => self.string().map(|_| ()),
+            Some(b'{' | b'[') => {
+                let mut depth = 0;
+                loop {
+                    match self.peek()? {
+                        b'"' => {
+                            self.string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    self.pos += 1;
+                    if depth == 0 {
+                        return Some(());
+                    }
+                }
+            }
+            _ => {
+                self.bare();
+                Some(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(format: &str, line: &str) -> Option<Vec<Option<String>>> {
+        let segments = format::tokenize(format);
+        let schema = Schema::new(segments.iter().filter_map(Segment::field_name));
+        let template =
+            JsonTemplate::compile(format, &schema, &VariableRegistry::new(), EscapeMode::Json)
+                .unwrap()
+                .unwrap();
+
+        let mut spans = Spans::new();
+        template.match_spans(line, &mut spans).then(|| {
+            spans
+                .into_iter()
+                .map(|span| span.map(|span| line[span].to_string()))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_not_a_template() {
+        let schema = Schema::default();
+        let variables = VariableRegistry::new();
+        for format in ["$remote_addr", r#"{"a":"$a"} trailing"#, r#"{"a" "$a"}"#] {
+            let template = JsonTemplate::compile(format, &schema, &variables, EscapeMode::Json);
+            assert!(template.unwrap().is_none(), "{}", format);
+        }
+    }
+
+    #[test]
+    fn test_match_keys_in_any_order() {
+        let format = r#"{"ts":"$time_iso8601","status":$status,"agent":"$http_user_agent"}"#;
+        assert_eq!(
+            values(
+                format,
+                r#"{ "agent": "say \"hi\"", "extra": [1, {"x": "}"}], "status": 200 }"#
+            ),
+            Some(vec![None, Some("200".into()), Some(r#"say \"hi\""#.into())])
+        );
+        assert_eq!(
+            values(format, r#"{"status":,"ts":"2013-11-08T13:39:18+00:00"}"#),
+            Some(vec![
+                Some("2013-11-08T13:39:18+00:00".into()),
+                Some("".into()),
+                None
+            ])
+        );
+        assert_eq!(values(format, r#"{"status":200"#), None);
+        assert_eq!(values(format, "not json"), None);
+    }
+
+    #[test]
+    fn test_nested_and_composite_values() {
+        let format = r#"{"upstream":{"addr":"$upstream_addr"},"timing":"${request_time}s/$upstream_response_time"}"#;
+        assert_eq!(
+            values(
+                format,
+                r#"{"timing":"0.010s/0.008","upstream":{"addr":"10.0.0.2:80"}}"#
+            ),
+            Some(vec![
+                Some("10.0.0.2:80".into()),
+                Some("0.010".into()),
+                Some("0.008".into())
+            ])
+        );
+        assert_eq!(values(format, r#"{"timing":"0.010"}"#), None);
+    }
+}
@@ -31,23 +31,89 @@
 //! ## Features
 //!
 //! - **Format String Parsing**: Convert nginx log format strings into regex patterns
-//! - **Type-Safe Field Access**: Access fields as strings, integers, or floats with proper error handling
+//! - **Type-Safe Field Access**: Access fields as strings, integers, floats or any `FromStr` type with proper error handling
 //! - **Nginx Config Integration**: Extract log formats directly from nginx configuration files
 //! - **Iterator Interface**: Process log files line by line with Rust's iterator patterns
+//! - **Following Logs**: Keep reading a growing log like `tail -F`, through rotation and truncation
+//! - **Rotated Logs**: Read a log and its rotated files oldest first as one stream
+//! - **Checkpoints**: Save how far a log was read and resume there in the next run, across rotations
+//! - **Compressed Logs**: Open gzip, bzip2, xz and zstd logs transparently with the matching cargo features
 //! - **Error Handling**: Comprehensive error types using `thiserror`
-//! - **Optional Serde Support**: Serialize/deserialize entries when the `serde` feature is enabled
+//! - **Variable Patterns**: Match known nginx variables precisely to split concatenated fields
+//! - **Escape Handling**: Match and decode values written with nginx's `escape=default` or `escape=json`
+//! - **JSON Logs**: Parse `escape=json` JSON access logs by the keys of their template
+//! - **Request Fields**: Method, URI, path, query string and protocol derived from `$request`
+//! - **Upstream Attempts**: Split `$upstream_*` lists into per-attempt values with totals
+//! - **Timestamps**: Parse `$time_local`, `$time_iso8601` and `$msec` into sortable timestamps
+//! - **Time Ranges**: Binary search seekable logs for the entries of a time range, tolerating slightly out-of-order lines
+//! - **Error Policies**: Skip, collect or cap malformed lines instead of aborting on the first one
+//! - **Invalid UTF-8**: Reject, replace or escape the bytes of lines that aren't valid UTF-8
+//! - **Optional Serde Support**: Serialize/deserialize entries, and deserialize them into your own structs, when the `serde` feature is enabled
+//! - **Typed Structs**: Parse lines straight into structs with `#[derive(NginxLog)]` when the `derive` feature is enabled
+//! - **Parallel Parsing**: Split a large file into line-aligned chunks and parse them on all cores when the `rayon` feature is enabled
+//! - **Async Streams**: Read logs from tokio `AsyncRead` inputs as a `Stream` of entries when the `tokio` feature is enabled
+//! - **Memory-Mapped Reading**: Parse lines straight out of a mapped file with `Reader::mmap` when the `mmap` feature is enabled
+//! - **Optional Chrono Support**: Convert timestamps to `chrono` date-times when the `chrono` feature is enabled
 
+pub mod checkpoint;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod diagnostic;
 pub mod entry;
 pub mod error;
+pub mod escape;
+pub mod follow;
+pub mod format;
+pub mod input;
+mod json;
+#[cfg(feature = "mmap")]
+pub mod mmap;
 pub mod nginx;
+#[cfg(feature = "rayon")]
+pub mod parallel;
 pub mod parser;
+pub mod policy;
+pub mod range;
 pub mod reader;
+pub mod request;
+pub mod rotated;
+mod scanner;
+pub mod schema;
+#[cfg(feature = "tokio")]
+pub mod stream;
+pub mod time;
+pub mod typed;
+pub mod upstream;
+pub mod variables;
 
 // Re-export main types for convenience
-pub use entry::{Entry, Fields};
+pub use checkpoint::Checkpoint;
+pub use diagnostic::Diagnostic;
+pub use entry::{Entry, EntryRef, Fields, FieldsMut, LineInfo};
 pub use error::{Error, Result};
-pub use parser::Parser;
+pub use escape::EscapeMode;
+pub use follow::FollowReader;
+#[cfg(feature = "mmap")]
+pub use mmap::MmapReader;
+#[cfg(feature = "rayon")]
+pub use parallel::ParallelReader;
+pub use parser::{Engine, Parser};
+pub use policy::{ErrorPolicy, ErrorRate, ErrorSummary, Utf8Policy};
+pub use range::TimeRange;
 pub use reader::Reader;
+pub use request::RequestLine;
+pub use rotated::RotatedReader;
+pub use schema::Schema;
+#[cfg(feature = "tokio")]
+pub use stream::{AsyncNginxReader, AsyncReader};
+pub use time::Timestamp;
+pub use typed::{LogParser, NginxLog};
+pub use upstream::{Upstream, UpstreamAttempt};
+pub use variables::VariableRegistry;
+
+/// Derive [`NginxLog`] for a struct from a `#[nginx(format = "...")]` attribute.
+#[cfg(feature = "derive")]
+pub use rsnx_derive::NginxLog;
 
 // Re-export nginx-specific functionality
 pub use nginx::NginxReader;
@@ -0,0 +1,342 @@
+//! Reading log files through a memory map.
+//!
+//! [`MmapReader`] parses lines straight out of a mapping of the file, without
+//! copying them into a buffer first. This module requires the `mmap` feature.
+
+use crate::entry::{Entry, EntryRef, LineInfo};
+use crate::error::{Error, Result};
+use crate::input::{self, Input};
+use crate::parser::{Parser, Spans};
+use crate::policy::{ErrorPolicy, ErrorSummary, ErrorTracker, Utf8Policy};
+use crate::reader::{is_blank_line, Reader};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::Path;
+
+/// A reader that parses a memory-mapped log file.
+///
+/// It behaves like a [`Reader`], including skipping empty lines and applying
+/// its [`ErrorPolicy`], but finds lines directly in the mapped file. Together
+/// with [`MmapReader::read_ref`], which borrows values from the mapping,
+/// parsing allocates nothing per line.
+///
+/// The mapping reflects the file as it changes. Lines appended after opening
+/// aren't read, and truncating the file while it is mapped, as logrotate's
+/// `copytruncate` does, may crash the process. Use a [`Reader`] for logs that
+/// are still being written.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rsnx::Reader;
+///
+/// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+/// let mut reader = Reader::mmap("/var/log/nginx/access.log.1", format)?;
+///
+/// let mut not_found = 0;
+/// while let Some(entry) = reader.read_ref() {
+///     if entry?.field("status")? == "404" {
+///         not_found += 1;
+///     }
+/// }
+/// println!("{} not found", not_found);
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct MmapReader {
+    map: Mmap,
+    parser: Parser,
+    /// The error policy and summary of rejected lines.
+    tracker: ErrorTracker,
+    utf8_policy: Utf8Policy,
+    /// The offset in the map of the next line to be read.
+    offset: usize,
+    /// The location of the next line to be read.
+    position: LineInfo,
+    /// Field spans of the current line, reused between reads.
+    spans: Spans,
+    /// The current line, if it had to be decoded under the UTF-8 policy.
+    decoded: String,
+}
+
+impl MmapReader {
+    /// Map a log file for reading.
+    ///
+    /// Returns an error if the file can't be mapped, is compressed, or the
+    /// format string is invalid.
+    pub fn open(path: impl AsRef<Path>, format: &str) -> Result<Self> {
+        Self::with_parser(path, Parser::new(format)?)
+    }
+
+    /// Map a log file for reading with a custom parser.
+    pub fn with_parser(path: impl AsRef<Path>, parser: Parser) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read, and the caveats about the file being
+        // modified while mapped are documented on the type.
+        let map = unsafe { Mmap::map(&file)? };
+        input::require_uncompressed(&map, "memory-mapped")?;
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential)?;
+
+        Ok(Self {
+            map,
+            parser,
+            tracker: ErrorTracker::default(),
+            utf8_policy: Utf8Policy::default(),
+            offset: 0,
+            position: LineInfo::new(1, 0),
+            spans: Spans::new(),
+            decoded: String::new(),
+        })
+    }
+
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// See [`Reader::with_error_policy`].
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.tracker = ErrorTracker::new(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`].
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Get a reference to the underlying parser.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Get the policy for handling lines that don't match the format.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.tracker.policy()
+    }
+
+    /// Get the summary of lines rejected so far.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.tracker.summary()
+    }
+
+    /// Get the location of the next line to be read.
+    pub fn position(&self) -> LineInfo {
+        self.position
+    }
+
+    /// Read the next entry, as [`Reader::read`] does.
+    pub fn read(&mut self) -> Option<Result<Entry>> {
+        loop {
+            if self.tracker.is_done() {
+                return None;
+            }
+
+            let Some((line, info)) = next_line(&self.map, &mut self.offset, &mut self.position)
+            else {
+                return self.tracker.finish().map(Err);
+            };
+            let result = self
+                .utf8_policy
+                .parse(&self.parser, line)
+                .map(|entry| entry.with_line_info(info))
+                .map_err(|e| e.with_line_info(info));
+            if let Some(result) = self.tracker.handle(result) {
+                return Some(result);
+            }
+        }
+    }
+
+    /// Read the next entry, borrowing its values from the mapped file.
+    ///
+    /// See [`Reader::read_ref`].
+    pub fn read_ref(&mut self) -> Option<Result<EntryRef<'_>>> {
+        let (line, info) = loop {
+            if self.tracker.is_done() {
+                return None;
+            }
+
+            let Some((line, info)) = next_line(&self.map, &mut self.offset, &mut self.position)
+            else {
+                return self.tracker.finish().map(Err);
+            };
+            // A line borrowed from the map, or `None` if it was decoded into
+            // the reader's buffer
+            let result = self.utf8_policy.decode(line).and_then(|decoded| {
+                let decoded = match decoded {
+                    Cow::Borrowed(decoded) => Some(decoded),
+                    Cow::Owned(decoded) => {
+                        self.decoded = decoded;
+                        None
+                    }
+                };
+                self.parser
+                    .match_spans(decoded.unwrap_or(&self.decoded), &mut self.spans)
+                    .map(|()| (line, decoded))
+            });
+            match self
+                .tracker
+                .handle(result.map_err(|e| e.with_line_info(info)))
+            {
+                Some(Ok(line)) => break (line, info),
+                Some(Err(e)) => return Some(Err(e)),
+                None => continue,
+            }
+        };
+
+        let entry = match line {
+            (_, Some(decoded)) => self.parser.entry_ref(decoded, &self.spans),
+            (raw, None) => self
+                .parser
+                .entry_ref(&self.decoded, &self.spans)
+                .with_raw_line(raw),
+        };
+        Some(Ok(entry.with_line_info(info)))
+    }
+
+    /// Collect all entries into a vector.
+    ///
+    /// See [`Reader::collect_all`].
+    pub fn collect_all(mut self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        while let Some(result) = self.read() {
+            entries.push(result?);
+        }
+        Ok(entries)
+    }
+
+    /// Process entries with a closure, borrowing each from the mapped file.
+    ///
+    /// See [`Reader::process_entries`].
+    pub fn process_entries<F, E>(&mut self, mut f: F) -> std::result::Result<(), E>
+    where
+        F: FnMut(&EntryRef<'_>) -> std::result::Result<(), E>,
+        E: From<Error>,
+    {
+        while let Some(result) = self.read_ref() {
+            let entry = result?;
+            f(&entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for MmapReader {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read()
+    }
+}
+
+impl Reader<Input> {
+    /// Map a log file for reading, as an [`MmapReader`].
+    ///
+    /// Note that this returns an [`MmapReader`], not a [`Reader`]. It reads
+    /// lines the same way, but has no generic input to seek in or take
+    /// checkpoints from. This requires the `mmap` feature.
+    pub fn mmap(path: impl AsRef<Path>, format: &str) -> Result<MmapReader> {
+        MmapReader::open(path, format)
+    }
+}
+
+/// Find the next non-empty line at `offset`, without its line ending.
+///
+/// Returns `None` at the end of the data.
+fn next_line<'m>(
+    data: &'m [u8],
+    offset: &mut usize,
+    position: &mut LineInfo,
+) -> Option<(&'m [u8], LineInfo)> {
+    loop {
+        let rest = data.get(*offset..).filter(|rest| !rest.is_empty())?;
+        let len = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(rest.len(), |newline| newline + 1);
+        *offset += len;
+
+        let info = *position;
+        position.line_number = info.line_number.map(|n| n + 1);
+        position.byte_offset += len as u64;
+
+        // Remove trailing newline
+        let mut line = &rest[..len];
+        if let Some(stripped) = line.strip_suffix(b"\n") {
+            line = stripped.strip_suffix(b"\r").unwrap_or(stripped);
+        }
+
+        // Skip empty lines
+        if !is_blank_line(line) {
+            return Some((line, info));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const FORMAT: &str = "$remote_addr $status";
+
+    fn mapped(data: &[u8]) -> (tempfile::NamedTempFile, MmapReader) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(data).unwrap();
+        let reader = MmapReader::open(file.path(), FORMAT).unwrap();
+        (file, reader)
+    }
+
+    #[test]
+    fn test_same_as_reader() {
+        // Blank lines include Unicode whitespace, but not invalid UTF-8
+        let data: &[u8] =
+            b"127.0.0.1 200\r\n\n  \n\x0b\t\n\xc2\xa0\xe3\x80\x80\n\xff \ngarbage\n192.168.1.1 404\n10.0.0.1 500";
+        let (_file, reader) = mapped(data);
+        let expected: Vec<_> = Reader::new(Cursor::new(data), FORMAT)
+            .unwrap()
+            .map(|result| result.map_err(|e| e.to_string()))
+            .collect();
+        let actual: Vec<_> = reader
+            .map(|result| result.map_err(|e| e.to_string()))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_read_ref() {
+        let (_file, mut reader) = mapped(b"127.0.0.1 200\n\n192.168.1.1 404\n");
+        let entry = reader.read_ref().unwrap().unwrap();
+        assert_eq!(entry.field("status").unwrap(), "200");
+        let entry = reader.read_ref().unwrap().unwrap();
+        assert_eq!(entry.field("remote_addr").unwrap(), "192.168.1.1");
+        assert_eq!(entry.line_info(), Some(LineInfo::new(3, 15)));
+        assert!(reader.read_ref().is_none());
+        assert_eq!(reader.position(), LineInfo::new(4, 31));
+    }
+
+    #[test]
+    fn test_empty_and_invalid() {
+        let (_file, mut reader) = mapped(b"");
+        assert!(reader.read().is_none());
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"\x1f\x8b\x08\x00\x00\x00").unwrap();
+        assert!(matches!(
+            MmapReader::open(file.path(), FORMAT),
+            Err(Error::CompressedInput { .. })
+        ));
+
+        let (_file, mut reader) = mapped(b"127.0.0.1 \xff\n127.0.0.1 200\n");
+        assert!(matches!(
+            reader.read(),
+            Some(Err(Error::InvalidUtf8 { line_info: Some(info), .. })) if info == LineInfo::new(1, 0)
+        ));
+        assert_eq!(
+            reader.read().unwrap().unwrap().field("status").unwrap(),
+            "200"
+        );
+    }
+}
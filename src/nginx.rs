@@ -1,10 +1,14 @@
 //! Nginx configuration parsing functionality.
 
 use crate::error::{Error, Result};
-use crate::parser::Parser;
+use crate::escape::EscapeMode;
+use crate::input::Input;
+use crate::parser::{Engine, Parser};
+use crate::policy::{ErrorPolicy, ErrorSummary, Utf8Policy};
 use crate::reader::Reader;
 use regex::Regex;
 use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
 
 /// A reader that extracts log formats from nginx configuration files.
 ///
@@ -20,7 +24,8 @@ impl<R: Read> NginxReader<R> {
     /// Create a new nginx reader by extracting the format from nginx configuration.
     ///
     /// This function parses the nginx configuration to find the specified log format
-    /// and uses it to create a reader for parsing log files.
+    /// and uses it to create a reader for parsing log files. The parser is set up
+    /// with [`LogFormat::parser`], so JSON formats are parsed as JSON.
     ///
     /// # Arguments
     ///
@@ -53,13 +58,53 @@ impl<R: Read> NginxReader<R> {
     /// # Ok::<(), rsnx::Error>(())
     /// ```
     pub fn new<C: Read>(log_input: R, nginx_config: C, format_name: &str) -> Result<Self> {
-        let format = extract_nginx_format(nginx_config, format_name)?;
-        let parser = Parser::new(&format)?;
+        let parser = extract_nginx_log_format(nginx_config, format_name)?.parser()?;
         let reader = Reader::with_parser(log_input, parser);
 
         Ok(Self { reader })
     }
 
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// See [`Reader::with_error_policy`] for details.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.reader = self.reader.with_error_policy(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`] for details.
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.reader = self.reader.with_utf8_policy(policy);
+        self
+    }
+
+    /// Enable or disable decoding escape sequences in values.
+    ///
+    /// The escape mode is taken from the `escape` parameter of the `log_format`
+    /// directive. See [`Parser::with_unescape`] for details.
+    pub fn with_unescape(mut self, enabled: bool) -> Self {
+        self.reader = self
+            .reader
+            .map_parser(|parser| parser.with_unescape(enabled));
+        self
+    }
+
+    /// Select the engine used to match lines.
+    ///
+    /// JSON formats use [`Engine::Json`] unless another engine is selected. See
+    /// [`Parser::with_engine`] for details.
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.reader = self.reader.map_parser(|parser| parser.with_engine(engine));
+        self
+    }
+
+    /// Get the summary of lines rejected so far.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.reader.error_summary()
+    }
+
     /// Get a reference to the underlying reader.
     pub fn reader(&self) -> &Reader<R> {
         &self.reader
@@ -79,6 +124,31 @@ impl<R: Read> NginxReader<R> {
     pub fn collect_all(self) -> Result<Vec<crate::entry::Entry>> {
         self.reader.collect_all()
     }
+
+    /// Turn the reader into an iterator that deserializes each entry into `T`.
+    ///
+    /// See [`Reader::deserialize`] for details.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(
+        self,
+    ) -> crate::de::DeserializeEntries<R, T> {
+        self.reader.deserialize()
+    }
+}
+
+impl NginxReader<Input> {
+    /// Open a log file, decompressing it if needed, and parse it with a format
+    /// extracted from nginx configuration.
+    ///
+    /// See [`Reader::open`] for how compression is detected and
+    /// [`NginxReader::new`] for how the format is extracted.
+    pub fn open<C: Read>(
+        log_path: impl AsRef<Path>,
+        nginx_config: C,
+        format_name: &str,
+    ) -> Result<Self> {
+        Self::new(Input::open(log_path)?, nginx_config, format_name)
+    }
 }
 
 impl<R: Read> Iterator for NginxReader<R> {
@@ -89,6 +159,48 @@ impl<R: Read> Iterator for NginxReader<R> {
     }
 }
 
+/// A log format extracted from a `log_format` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFormat {
+    /// The format string.
+    pub format: String,
+    /// The escape mode from the directive's `escape` parameter.
+    pub escape: EscapeMode,
+}
+
+impl LogFormat {
+    /// Create a parser for this log format.
+    ///
+    /// The parser uses the format's escape mode. Formats that are a JSON object
+    /// template, such as `{"ts":"$time_iso8601","status":$status}`, are parsed
+    /// with [`Engine::Json`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::nginx::extract_nginx_log_format;
+    /// use rsnx::parser::StringParser;
+    /// use rsnx::Engine;
+    /// use std::io::Cursor;
+    ///
+    /// let config = r#"log_format json escape=json '{"ts":"$time_iso8601","code":$status}';"#;
+    /// let parser = extract_nginx_log_format(Cursor::new(config), "json")?.parser()?;
+    /// assert_eq!(parser.engine(), Engine::Json);
+    ///
+    /// let entry = parser.parse_string(r#"{"code":200,"ts":"2013-11-08T13:39:18+00:00"}"#)?;
+    /// assert_eq!(entry.field("status")?, "200");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn parser(&self) -> Result<Parser> {
+        let parser = Parser::new(&self.format)?.with_escape(self.escape)?;
+        if parser.is_json() {
+            Ok(parser.with_engine(Engine::Json))
+        } else {
+            Ok(parser)
+        }
+    }
+}
+
 /// Extract a log format from nginx configuration.
 ///
 /// This function parses nginx configuration to find a log_format directive
@@ -103,15 +215,41 @@ impl<R: Read> Iterator for NginxReader<R> {
 ///
 /// The format string, or an error if the format is not found.
 pub fn extract_nginx_format<R: Read>(nginx_config: R, format_name: &str) -> Result<String> {
+    extract_nginx_log_format(nginx_config, format_name).map(|log_format| log_format.format)
+}
+
+/// Extract a log format and its escape mode from nginx configuration.
+///
+/// Like [`extract_nginx_format`], but also returns the escape mode given by the
+/// directive's `escape` parameter, or [`EscapeMode::Default`] if it has none.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::nginx::extract_nginx_log_format;
+/// use rsnx::EscapeMode;
+/// use std::io::Cursor;
+///
+/// let config = r#"log_format json escape=json '{"status":"$status"}';"#;
+/// let log_format = extract_nginx_log_format(Cursor::new(config), "json")?;
+///
+/// assert_eq!(log_format.format, r#"{"status":"$status"}"#);
+/// assert_eq!(log_format.escape, EscapeMode::Json);
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+pub fn extract_nginx_log_format<R: Read>(nginx_config: R, format_name: &str) -> Result<LogFormat> {
     let reader = BufReader::new(nginx_config);
 
-    // Regex to match log_format directive
+    // Regex to match log_format directive, with an optional escape parameter
     let log_format_regex = Regex::new(&format!(
-        r"^\s*log_format\s+{}\s+(.+)",
+        r"^\s*log_format\s+{}\s+(?:escape=(\w+)(?:\s+|$))?(.*)",
         regex::escape(format_name)
     ))
     .unwrap();
 
+    // nginx escapes values unless the directive says otherwise
+    let mut escape = EscapeMode::Default;
+
     let mut format_lines = Vec::new();
     let mut in_format = false;
     let mut brace_count = 0;
@@ -128,7 +266,10 @@ pub fn extract_nginx_format<R: Read>(nginx_config: R, format_name: &str) -> Resu
         if !in_format {
             // Look for the start of our log format
             if let Some(captures) = log_format_regex.captures(trimmed) {
-                let format_part = captures.get(1).unwrap().as_str();
+                if let Some(mode) = captures.get(1) {
+                    escape = mode.as_str().parse()?;
+                }
+                let format_part = captures.get(2).unwrap().as_str();
                 format_lines.push(format_part.to_string());
                 in_format = true;
 
@@ -184,17 +325,20 @@ pub fn extract_nginx_format<R: Read>(nginx_config: R, format_name: &str) -> Resu
     // Simple whitespace cleanup - just normalize spaces
     format = format.split_whitespace().collect::<Vec<_>>().join(" ");
 
-    Ok(format)
+    Ok(LogFormat { format, escape })
 }
 
 /// Count opening and closing braces in a string.
 /// Returns the net count (opening - closing).
+///
+/// Braces inside quoted strings and in `${variable}` references are ignored.
 fn count_braces(s: &str) -> i32 {
     let mut count = 0;
-    let mut in_quotes = false;
+    let mut quote = None;
     let mut escape_next = false;
+    let mut chars = s.chars().peekable();
 
-    for ch in s.chars() {
+    while let Some(ch) = chars.next() {
         if escape_next {
             escape_next = false;
             continue;
@@ -202,9 +346,14 @@ fn count_braces(s: &str) -> i32 {
 
         match ch {
             '\\' => escape_next = true,
-            '"' | '\'' => in_quotes = !in_quotes,
-            '{' if !in_quotes => count += 1,
-            '}' if !in_quotes => count -= 1,
+            '"' | '\'' if quote == Some(ch) => quote = None,
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            '$' if quote.is_none() && chars.peek() == Some(&'{') => {
+                // Skip over a braced variable name
+                chars.by_ref().find(|&c| c == '}');
+            }
+            '{' if quote.is_none() => count += 1,
+            '}' if quote.is_none() => count -= 1,
             _ => {}
         }
     }
@@ -262,6 +411,49 @@ mod tests {
         assert_eq!(format, expected);
     }
 
+    #[test]
+    fn test_extract_braced_variables() {
+        let config = r#"
+        log_format timing '$remote_addr "${request}" ${request_time}ms'
+                          ' ${upstream_addr}x';
+        "#;
+
+        let format = extract_nginx_format(Cursor::new(config), "timing").unwrap();
+        assert_eq!(
+            format,
+            r#"$remote_addr "${request}" ${request_time}ms ${upstream_addr}x"#
+        );
+
+        assert_eq!(count_braces(r#"'"${request}"'"#), 0);
+        assert_eq!(count_braces("${request_time}ms {"), 1);
+    }
+
+    #[test]
+    fn test_extract_escape_mode() {
+        let config = r#"
+        log_format main '$remote_addr "$request"';
+        log_format json escape=json
+                        '{"addr":"$remote_addr","request":"$request"}';
+        log_format raw escape=none '$request';
+        log_format bad escape=html '$request';
+        "#;
+
+        let log_format = extract_nginx_log_format(Cursor::new(config), "main").unwrap();
+        assert_eq!(log_format.escape, EscapeMode::Default);
+
+        let log_format = extract_nginx_log_format(Cursor::new(config), "json").unwrap();
+        assert_eq!(log_format.escape, EscapeMode::Json);
+        assert_eq!(
+            log_format.format,
+            r#"{"addr":"$remote_addr","request":"$request"}"#
+        );
+
+        let format = extract_nginx_format(Cursor::new(config), "raw").unwrap();
+        assert_eq!(format, "$request");
+
+        assert!(extract_nginx_log_format(Cursor::new(config), "bad").is_err());
+    }
+
     #[test]
     fn test_format_not_found() {
         let config = r#"
@@ -295,4 +487,21 @@ mod tests {
         assert_eq!(entry.field("remote_addr").unwrap(), "127.0.0.1");
         assert_eq!(entry.field("status").unwrap(), "200");
     }
+
+    #[test]
+    fn test_nginx_reader_error_policy() {
+        let config = r#"
+        log_format main '$remote_addr [$time_local] $status';
+        "#;
+
+        let log_data = "127.0.0.1 [08/Nov/2013:13:39:18 +0000] 200\nnot a log line\n";
+
+        let mut reader = NginxReader::new(Cursor::new(log_data), Cursor::new(config), "main")
+            .unwrap()
+            .with_error_policy(ErrorPolicy::Skip);
+
+        let entries: Result<Vec<_>> = reader.by_ref().collect();
+        assert_eq!(entries.unwrap().len(), 1);
+        assert_eq!(reader.error_summary().rejected_count(), 1);
+    }
 }
@@ -0,0 +1,337 @@
+//! Parsing a large log file on several threads.
+//!
+//! [`ParallelReader`] splits a file into chunks at line boundaries and parses
+//! them on the [rayon](https://docs.rs/rayon) thread pool, each with a clone of
+//! the parser. This module requires the `rayon` feature.
+
+use crate::entry::{Entry, LineInfo};
+use crate::error::{Error, Result};
+use crate::input;
+use crate::parser::Parser;
+use crate::policy::Utf8Policy;
+use crate::reader::Reader;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Take};
+use std::path::{Path, PathBuf};
+
+/// The default number of bytes in a chunk.
+const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The entries of one chunk, or the error from opening it.
+type ChunkEntries = Box<dyn Iterator<Item = Result<Entry>> + Send>;
+
+/// A reader that parses the chunks of a log file in parallel.
+///
+/// The file is split into chunks of about [`ParallelReader::with_chunk_size`]
+/// bytes, each moved forward to the start of the next line, so every line
+/// belongs to exactly one chunk. Entries can be consumed:
+///
+/// - in the original order with [`ParallelReader::entries`] or
+///   [`ParallelReader::collect_all`]
+/// - in any order, as a rayon parallel iterator from
+///   [`ParallelReader::par_entries`], or with [`ParallelReader::process_entries`]
+///
+/// Malformed lines are returned as errors. Entries carry their byte offset,
+/// but line numbers are only known in the first chunk. The file is read up to
+/// its size when it was opened, and it can't be compressed.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rayon::prelude::*;
+/// use rsnx::ParallelReader;
+///
+/// let format = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent"#;
+/// let reader = ParallelReader::open("/var/log/nginx/access.log", format)?;
+///
+/// let bytes: u64 = reader
+///     .par_entries()
+///     .filter_map(|entry| entry.ok()?.get::<u64>("body_bytes_sent").ok())
+///     .sum();
+/// println!("{} bytes sent", bytes);
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParallelReader {
+    path: PathBuf,
+    /// The size of the file when it was opened.
+    len: u64,
+    parser: Parser,
+    chunk_size: u64,
+    utf8_policy: Utf8Policy,
+}
+
+impl ParallelReader {
+    /// Open a log file for parallel parsing.
+    ///
+    /// Returns an error if the file can't be opened, is compressed, or the
+    /// format string is invalid.
+    pub fn open(path: impl AsRef<Path>, format: &str) -> Result<Self> {
+        Self::with_parser(path, Parser::new(format)?)
+    }
+
+    /// Open a log file for parallel parsing with a custom parser.
+    pub fn with_parser(path: impl AsRef<Path>, parser: Parser) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path)?;
+        let len = file.metadata()?.len();
+
+        let mut header = Vec::new();
+        file.by_ref().take(6).read_to_end(&mut header)?;
+        input::require_uncompressed(&header, "split for parallel parsing")?;
+
+        Ok(Self {
+            path,
+            len,
+            parser,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            utf8_policy: Utf8Policy::default(),
+        })
+    }
+
+    /// Set the number of bytes in a chunk. The default is 8 MiB.
+    ///
+    /// Smaller chunks spread the work more evenly, larger ones have less
+    /// overhead. Lines longer than a chunk are still read whole.
+    pub fn with_chunk_size(mut self, bytes: u64) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`]. Lines rejected as invalid are
+    /// returned as errors.
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Get a reference to the underlying parser.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Get the number of chunks the file is split into.
+    pub fn chunk_count(&self) -> usize {
+        (self.len / self.chunk_size + (self.len % self.chunk_size != 0) as u64) as usize
+    }
+
+    /// Get a parallel iterator over the entries.
+    ///
+    /// Consuming it with `for_each`, `fold` or `reduce` processes entries in
+    /// no particular order, while `collect` keeps the original order.
+    pub fn par_entries(&self) -> impl ParallelIterator<Item = Result<Entry>> + '_ {
+        (0..self.chunk_count())
+            .into_par_iter()
+            .flat_map_iter(|index| self.chunk_entries(index))
+    }
+
+    /// Get an iterator over the entries in their original order.
+    ///
+    /// As many chunks as there are threads in the pool are parsed at a time,
+    /// so only those are held in memory.
+    pub fn entries(&self) -> OrderedEntries<'_> {
+        OrderedEntries {
+            reader: self,
+            next_chunk: 0,
+            batch: Vec::new().into_iter(),
+        }
+    }
+
+    /// Collect all entries into a vector, in their original order.
+    pub fn collect_all(&self) -> Result<Vec<Entry>> {
+        self.par_entries().collect()
+    }
+
+    /// Process entries with a closure on several threads.
+    ///
+    /// This works like [`Reader::process_entries`], except that the closure is
+    /// called from many threads at once, in no particular order. Processing
+    /// stops early at the first error.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use rsnx::ParallelReader;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// let errors = AtomicU64::new(0);
+    /// let reader = ParallelReader::open("/var/log/nginx/access.log", "$remote_addr $status")?;
+    /// reader.process_entries(|entry| -> rsnx::Result<()> {
+    ///     if entry.get::<u16>("status")? >= 500 {
+    ///         errors.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn process_entries<F, E>(&self, f: F) -> std::result::Result<(), E>
+    where
+        F: Fn(&Entry) -> std::result::Result<(), E> + Sync + Send,
+        E: From<Error> + Send,
+    {
+        self.par_entries().try_for_each(|result| f(&result?))
+    }
+
+    /// Get the entries of a chunk.
+    fn chunk_entries(&self, index: usize) -> ChunkEntries {
+        match self.chunk_reader(index) {
+            Ok(reader) => Box::new(reader),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+
+    /// Open a reader for the lines of a chunk.
+    fn chunk_reader(&self, index: usize) -> Result<Reader<Take<File>>> {
+        let mut file = File::open(&self.path)?;
+        let start = self.line_boundary(&mut file, index as u64 * self.chunk_size)?;
+        let end = self.line_boundary(
+            &mut file,
+            (index as u64 + 1).saturating_mul(self.chunk_size),
+        )?;
+        file.seek(SeekFrom::Start(start))?;
+
+        let position = match start {
+            0 => LineInfo::new(1, 0),
+            start => LineInfo {
+                line_number: None,
+                byte_offset: start,
+            },
+        };
+        let input = file.take(end.saturating_sub(start));
+        Ok(Reader::with_parser(input, self.parser.clone())
+            .with_utf8_policy(self.utf8_policy)
+            .with_position(position))
+    }
+
+    /// Find the start of the first line starting at or after `offset`.
+    fn line_boundary(&self, file: &mut File, offset: u64) -> io::Result<u64> {
+        if offset == 0 || offset >= self.len {
+            return Ok(offset.min(self.len));
+        }
+
+        file.seek(SeekFrom::Start(offset - 1))?;
+        let skipped = BufReader::new(file).read_until(b'\n', &mut Vec::new())?;
+        Ok((offset - 1 + skipped as u64).min(self.len))
+    }
+}
+
+/// An iterator over the entries of a [`ParallelReader`] in their original order.
+///
+/// Created by [`ParallelReader::entries`].
+#[derive(Debug)]
+pub struct OrderedEntries<'a> {
+    reader: &'a ParallelReader,
+    next_chunk: usize,
+    /// The parsed entries of the current batch of chunks.
+    batch: std::vec::IntoIter<Result<Entry>>,
+}
+
+impl Iterator for OrderedEntries<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.batch.next() {
+                return Some(result);
+            }
+
+            let count = self.reader.chunk_count();
+            if self.next_chunk >= count {
+                return None;
+            }
+            let end = count.min(self.next_chunk + rayon::current_num_threads());
+            let batch: Vec<_> = (self.next_chunk..end)
+                .into_par_iter()
+                .flat_map_iter(|index| self.reader.chunk_entries(index))
+                .collect();
+            self.next_chunk = end;
+            self.batch = batch.into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const FORMAT: &str = "$remote_addr $status";
+
+    fn log_file() -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for i in 0..500 {
+            writeln!(file, "10.0.{}.{} {}", i / 256, i % 256, 200 + i % 7).unwrap();
+            if i % 50 == 0 {
+                writeln!(file).unwrap();
+            }
+        }
+        write!(file, "10.0.9.9 200").unwrap();
+        file
+    }
+
+    #[test]
+    fn test_same_as_reader() {
+        let file = log_file();
+        let expected = Reader::open(file.path(), FORMAT)
+            .unwrap()
+            .collect_all()
+            .unwrap();
+
+        for chunk_size in [1, 7, 100, 4096, 1 << 20, u64::MAX] {
+            let reader = ParallelReader::open(file.path(), FORMAT)
+                .unwrap()
+                .with_chunk_size(chunk_size);
+            assert_eq!(reader.collect_all().unwrap(), expected, "{}", chunk_size);
+
+            let entries: Vec<_> = reader.entries().map(|entry| entry.unwrap()).collect();
+            assert_eq!(entries, expected, "{}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let file = log_file();
+        let reader = ParallelReader::open(file.path(), FORMAT)
+            .unwrap()
+            .with_chunk_size(1000);
+        let expected: Vec<_> = Reader::open(file.path(), FORMAT)
+            .unwrap()
+            .map(|entry| entry.unwrap().line_info().unwrap().byte_offset)
+            .collect();
+
+        let entries = reader.collect_all().unwrap();
+        let offsets: Vec<_> = entries
+            .iter()
+            .map(|entry| entry.line_info().unwrap().byte_offset)
+            .collect();
+        assert_eq!(offsets, expected);
+        assert_eq!(entries[1].line_info(), Some(LineInfo::new(3, 14)));
+        assert_eq!(
+            entries.last().unwrap().line_info().unwrap().line_number,
+            None
+        );
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let reader = ParallelReader::open(file.path(), FORMAT).unwrap();
+        assert_eq!(reader.chunk_count(), 0);
+        assert!(reader.collect_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_compressed_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"\x28\xb5\x2f\xfd\x00\x00").unwrap();
+        let error = ParallelReader::open(file.path(), FORMAT).unwrap_err();
+        assert!(matches!(error, Error::CompressedInput { .. }));
+        assert_eq!(
+            error.to_string(),
+            "zstd compressed files can't be split for parallel parsing"
+        );
+    }
+}
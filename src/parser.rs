@@ -1,9 +1,21 @@
 //! Log format parsing and regex generation.
 
-use crate::entry::Entry;
+use crate::diagnostic::Diagnostic;
+use crate::entry::{Entry, EntryRef};
 use crate::error::{Error, Result};
+use crate::escape::EscapeMode;
+use crate::format::{self, Segment};
+use crate::json::JsonTemplate;
+use crate::scanner::Program;
+use crate::schema::Schema;
+use crate::variables::VariableRegistry;
 use regex::Regex;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Byte ranges of each field's value within a log line, in field order.
+pub(crate) type Spans = Vec<Option<Range<usize>>>;
 
 /// Trait for parsing log lines into entries.
 pub trait StringParser {
@@ -11,6 +23,42 @@ pub trait StringParser {
     fn parse_string(&self, line: &str) -> Result<Entry>;
 }
 
+/// The matching engine a [`Parser`] uses to split lines into fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Match lines with a regex generated from the format. This is the default.
+    #[default]
+    Regex,
+    /// Scan lines segment by segment, searching for each field's delimiter.
+    ///
+    /// The format is compiled into a sequence of literal and field steps that are
+    /// matched left to right. A field ends at the first character of the literal
+    /// that follows it, or at the end of the line. For concatenated fields such
+    /// as `$host$request_uri`, `$host` takes the longest run of hostname
+    /// characters and any other field takes as little as the next field allows.
+    /// Fields for registered variables take the match of their pattern, and are
+    /// retried with other lengths if the rest of the line doesn't match, so this
+    /// gives the same results as the regex engine.
+    Scanner,
+    /// Parse lines as JSON objects, for formats that are a JSON object template.
+    ///
+    /// A format such as `{"ts":"$time_iso8601","status":$status}` binds each key
+    /// to the variables in its value. Lines are parsed as JSON and the value of
+    /// each key is assigned to those variables, so `field("status")` works
+    /// whatever the key is called and keys may appear in any order. Values that
+    /// combine several variables, such as `"$request_time/$upstream_response_time"`,
+    /// are split according to the template, and nested objects are supported.
+    /// Unknown keys are ignored, and variables whose keys are missing from a line
+    /// are absent from its entry.
+    ///
+    /// String values keep their escape sequences unless [`Parser::with_unescape`]
+    /// is enabled. Values are then decoded as JSON strings, so `\"` and `\u0022`
+    /// become `"`, unless the escape mode is [`EscapeMode::Default`], whose `\xHH`
+    /// sequences are decoded instead. If the format isn't a JSON object template,
+    /// see [`Parser::is_json`], no lines match.
+    Json,
+}
+
 /// A parser that converts log format strings into regex patterns for parsing log lines.
 ///
 /// The parser takes format strings like `$remote_addr [$time_local] "$request"` and
@@ -21,12 +69,34 @@ pub struct Parser {
     format: String,
     /// The compiled regular expression for parsing.
     regex: Regex,
+    /// The index of each field's capture group in the regex, in field order.
+    groups: Vec<usize>,
+    /// The literal and field segments of the format.
+    segments: Vec<Segment>,
+    /// The field names, in the order they appear in the format.
+    schema: Arc<Schema>,
+    /// The scan program used by [`Engine::Scanner`].
+    program: Program,
+    /// The JSON template used by [`Engine::Json`], if the format is a JSON object.
+    json: Option<JsonTemplate>,
+    /// The engine used to match lines.
+    engine: Engine,
+    /// Whether to attach a [`Diagnostic`] to format mismatch errors.
+    diagnostics: bool,
+    /// The patterns used for registered variables.
+    variables: VariableRegistry,
+    /// How values in the log were escaped.
+    escape: EscapeMode,
+    /// Whether to decode escape sequences in values.
+    unescape: bool,
 }
 
 impl Parser {
     /// Create a new parser from a format string.
     ///
-    /// Format strings use `$field_name` syntax to define extractable fields.
+    /// Format strings use `$field_name` syntax to define extractable fields. The
+    /// braced form `${field_name}` separates a field from word characters that
+    /// follow it, as in `${request_time}ms`.
     /// The parser automatically handles field boundaries and generates appropriate
     /// regex patterns.
     ///
@@ -47,15 +117,187 @@ impl Parser {
     /// # Ok::<(), rsnx::Error>(())
     /// ```
     pub fn new(format: &str) -> Result<Self> {
-        let regex_pattern = Self::format_to_regex(format)?;
-        let regex = Regex::new(&regex_pattern).map_err(|e| Error::invalid_format(format, e))?;
+        Self::with_variables(format, &VariableRegistry::new())
+    }
+
+    /// Create a new parser that matches registered variables with their patterns.
+    ///
+    /// Fields for variables in the registry only match the variable's pattern,
+    /// while other fields extend up to the delimiter that follows them as usual.
+    /// See [`VariableRegistry`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::parser::StringParser;
+    /// use rsnx::{Parser, VariableRegistry};
+    ///
+    /// let variables = VariableRegistry::builtin();
+    /// let parser = Parser::with_variables("$request_uri$status $body_bytes_sent", &variables)?;
+    /// let entry = parser.parse_string("/index.html200 612")?;
+    ///
+    /// assert_eq!(entry.field("request_uri")?, "/index.html");
+    /// assert_eq!(entry.field("status")?, "200");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_variables(format: &str, variables: &VariableRegistry) -> Result<Self> {
+        let segments = format::tokenize(format);
+        let schema = Arc::new(Schema::new(segments.iter().filter_map(Segment::field_name)));
+        let (regex, groups, program, json) =
+            Self::compile(format, &segments, &schema, variables, EscapeMode::default())?;
 
         Ok(Self {
             format: format.to_string(),
             regex,
+            groups,
+            segments,
+            schema,
+            program,
+            json,
+            engine: Engine::default(),
+            diagnostics: false,
+            variables: variables.clone(),
+            escape: EscapeMode::default(),
+            unescape: false,
         })
     }
 
+    /// Set how values in the log were escaped.
+    ///
+    /// Parsers don't interpret escape sequences unless an escape mode is set, as
+    /// the default is [`EscapeMode::None`]. With [`EscapeMode::Default`] or
+    /// [`EscapeMode::Json`], a backslash in a value always starts an escape
+    /// sequence, so an escaped delimiter such as `\"` inside `"$http_user_agent"`
+    /// doesn't end the field. Values keep their escape sequences unless
+    /// [`Parser::with_unescape`] is enabled.
+    ///
+    /// The format is compiled again for the new escape mode, which returns an
+    /// error if the resulting pattern can't be built.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::parser::StringParser;
+    /// use rsnx::{EscapeMode, Parser};
+    ///
+    /// let parser = Parser::new(r#""$http_user_agent" $status"#)?.with_escape(EscapeMode::Json)?;
+    /// let entry = parser.parse_string(r#""say \"hi\"" 200"#)?;
+    ///
+    /// assert_eq!(entry.field("http_user_agent")?, r#"say \"hi\""#);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_escape(mut self, escape: EscapeMode) -> Result<Self> {
+        let (regex, groups, program, json) = Self::compile(
+            &self.format,
+            &self.segments,
+            &self.schema,
+            &self.variables,
+            escape,
+        )?;
+        self.regex = regex;
+        self.groups = groups;
+        self.program = program;
+        self.json = json;
+        self.escape = escape;
+        Ok(self)
+    }
+
+    /// Get how values in the log were escaped.
+    pub fn escape(&self) -> EscapeMode {
+        self.escape
+    }
+
+    /// Enable or disable decoding escape sequences in values.
+    ///
+    /// Values that contain escape sequences are copied when decoded, so
+    /// [`Parser::parse_ref`] only borrows values that don't need decoding.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::parser::StringParser;
+    /// use rsnx::{EscapeMode, Parser};
+    ///
+    /// let parser = Parser::new(r#""$request""#)?
+    ///     .with_escape(EscapeMode::Default)?
+    ///     .with_unescape(true);
+    /// let entry = parser.parse_string(r#""GET /?q=\x22a\x22 HTTP/1.1""#)?;
+    ///
+    /// assert_eq!(entry.field("request")?, r#"GET /?q="a" HTTP/1.1"#);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_unescape(mut self, enabled: bool) -> Self {
+        self.unescape = enabled;
+        self
+    }
+
+    /// Enable or disable mismatch diagnostics.
+    ///
+    /// When enabled, [`Error::LineFormatMismatch`] errors carry a [`Diagnostic`]
+    /// describing where the line diverged from the format. This costs an extra
+    /// pass over each line that fails to match.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::parser::{Parser, StringParser};
+    ///
+    /// let parser = Parser::new("$remote_addr [$time_local] $status")?.with_diagnostics(true);
+    /// let error = parser.parse_string("127.0.0.1 [08/Nov/2013:13:39:18 +0000]").unwrap_err();
+    ///
+    /// let diagnostic = error.diagnostic().unwrap();
+    /// assert_eq!(diagnostic.expected(), Some("] "));
+    /// assert_eq!(diagnostic.found(), "]");
+    /// assert_eq!(diagnostic.column(), 38);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_diagnostics(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled;
+        self
+    }
+
+    /// Select the engine used to match lines.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::parser::{Engine, Parser, StringParser};
+    ///
+    /// let parser = Parser::new("$remote_addr [$time_local]")?.with_engine(Engine::Scanner);
+    /// let entry = parser.parse_string("127.0.0.1 [08/Nov/2013:13:39:18 +0000]")?;
+    ///
+    /// assert_eq!(entry.field("time_local")?, "08/Nov/2013:13:39:18 +0000");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Get the engine used to match lines.
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Check if the format is a JSON object template that [`Engine::Json`] can parse.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::parser::{Engine, Parser, StringParser};
+    ///
+    /// let parser = Parser::new(r#"{"code":$status,"ua":"$http_user_agent"}"#)?;
+    /// assert!(parser.is_json());
+    ///
+    /// let parser = parser.with_engine(Engine::Json);
+    /// let entry = parser.parse_string(r#"{"ua":"curl/8.0","code":404}"#)?;
+    /// assert_eq!(entry.field("status")?, "404");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn is_json(&self) -> bool {
+        self.json.is_some()
+    }
+
     /// Get the original format string.
     pub fn format(&self) -> &str {
         &self.format
@@ -66,11 +308,180 @@ impl Parser {
         &self.regex
     }
 
+    /// Get the literal and field segments of the format.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Get the schema of the fields produced by this parser.
+    ///
+    /// The schema is shared with every entry the parser produces.
+    pub fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+
+    /// Get the field names, in the order they appear in the format.
+    pub fn field_names(&self) -> &[String] {
+        self.schema.names()
+    }
+
+    /// Parse a log line into an entry that borrows its values from the line.
+    ///
+    /// This avoids allocating a string for every field. Use
+    /// [`EntryRef::to_owned`] to turn the result into an [`Entry`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::Parser;
+    ///
+    /// let parser = Parser::new("$remote_addr $status")?;
+    /// let entry = parser.parse_ref("127.0.0.1 200")?;
+    ///
+    /// assert_eq!(entry.field("status")?, "200");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn parse_ref<'a>(&'a self, line: &'a str) -> Result<EntryRef<'a>> {
+        let mut spans = Spans::new();
+        self.match_spans(line, &mut spans)?;
+        Ok(self.entry_ref(line, &spans))
+    }
+
+    /// Match a line against the format, storing the byte range of each field in `spans`.
+    pub(crate) fn match_spans(&self, line: &str, spans: &mut Spans) -> Result<()> {
+        if self.find_spans(line, spans) {
+            Ok(())
+        } else {
+            Err(self.mismatch(line))
+        }
+    }
+
+    /// Match a line with the selected engine, returning `false` if it doesn't match.
+    fn find_spans(&self, line: &str, spans: &mut Spans) -> bool {
+        match self.engine {
+            Engine::Regex => {
+                let mut locations = self.regex.capture_locations();
+                if self.regex.captures_read(&mut locations, line).is_none() {
+                    return false;
+                }
+
+                spans.clear();
+                spans.extend(
+                    self.groups
+                        .iter()
+                        .map(|&i| locations.get(i).map(|(start, end)| start..end)),
+                );
+                true
+            }
+            Engine::Scanner => self.program.match_spans(line, spans),
+            Engine::Json => self
+                .json
+                .as_ref()
+                .is_some_and(|json| json.match_spans(line, spans)),
+        }
+    }
+
+    /// Build a borrowed entry from the spans produced by [`Parser::match_spans`].
+    pub(crate) fn entry_ref<'a>(&'a self, line: &'a str, spans: &Spans) -> EntryRef<'a> {
+        let escape = self.value_escape();
+        let values = spans
+            .iter()
+            .map(|span| {
+                span.clone().map(|range| match self.unescape {
+                    true => escape.unescape(&line[range]),
+                    false => Cow::Borrowed(&line[range]),
+                })
+            })
+            .collect();
+        EntryRef::new(&self.schema, values)
+    }
+
+    /// Get how the values of matched lines are decoded when unescaping.
+    ///
+    /// JSON string values use JSON escapes even without an escape mode, so the
+    /// JSON engine decodes them as such unless nginx's `\xHH` escapes were used.
+    fn value_escape(&self) -> EscapeMode {
+        match (self.engine, self.escape) {
+            (Engine::Json, EscapeMode::None) => EscapeMode::Json,
+            (_, escape) => escape,
+        }
+    }
+
+    /// Build the error for a line that doesn't match the format.
+    fn mismatch(&self, line: &str) -> Error {
+        let error = Error::line_format_mismatch(line, &self.format);
+        match self.diagnostics.then(|| self.diagnose(line)).flatten() {
+            Some(diagnostic) => error.with_diagnostic(diagnostic),
+            None => error,
+        }
+    }
+
+    /// Explain where a log line diverges from the format.
+    ///
+    /// Matches the line with the same per-field patterns and escape mode the
+    /// parser compiled, and reports the literal or field that got furthest into
+    /// the line before failing, or trailing text after the last segment. Returns
+    /// `None` if the line matches or no mismatch could be located.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::Parser;
+    ///
+    /// let parser = Parser::new(r#"$remote_addr [$time_local] "$request""#)?;
+    /// let diagnostic = parser
+    ///     .diagnose(r#"127.0.0.1 - [08/Nov/2013:13:39:18 +0000] "GET / HTTP/1.1""#)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(diagnostic.column(), 10);
+    /// assert_eq!(diagnostic.expected(), Some(" ["));
+    /// assert_eq!(diagnostic.found(), " -");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn diagnose(&self, line: &str) -> Option<Diagnostic> {
+        // JSON keys may appear in any order, so the segments can't be walked in order
+        if self.engine == Engine::Json || self.find_spans(line, &mut Spans::new()) {
+            return None;
+        }
+        let failure = self.program.diagnose(line)?;
+        Some(Diagnostic::from_failure(&self.segments, line, failure))
+    }
+
+    /// Compile the regex, its field group indices, the scan program and the JSON
+    /// template for a format.
+    fn compile(
+        format: &str,
+        segments: &[Segment],
+        schema: &Schema,
+        variables: &VariableRegistry,
+        escape: EscapeMode,
+    ) -> Result<(Regex, Vec<usize>, Program, Option<JsonTemplate>)> {
+        let regex_pattern = Self::format_to_regex(format, variables, escape)?;
+        let regex = Regex::new(&regex_pattern).map_err(|e| Error::invalid_format(format, e))?;
+        let groups = schema
+            .names()
+            .iter()
+            .map(|name| {
+                regex
+                    .capture_names()
+                    .position(|group| group == Some(name.as_str()))
+                    .expect("every field has a named group")
+            })
+            .collect();
+        let program = Program::compile(segments, variables, escape)?;
+        let json = JsonTemplate::compile(format, schema, variables, escape)?;
+        Ok((regex, groups, program, json))
+    }
+
     /// Convert a format string to a regex pattern.
     ///
     /// This method handles the complex transformation from nginx-style format strings
     /// to regex patterns with named capture groups.
-    fn format_to_regex(format: &str) -> Result<String> {
+    fn format_to_regex(
+        format: &str,
+        variables: &VariableRegistry,
+        escape: EscapeMode,
+    ) -> Result<String> {
         let mut result = format.to_string();
 
         // Step 1: Handle concatenated fields by inserting temporary placeholders
@@ -78,7 +489,7 @@ impl Parser {
         result = Self::handle_concatenated_fields(&result);
 
         // Step 2: Convert field tokens to named regex groups
-        result = Self::convert_fields_to_groups(&result)?;
+        result = Self::convert_fields_to_groups(&result, variables, escape)?;
 
         // Step 3: Clean up temporary placeholders
         result = result.replace("@RSNX@", "");
@@ -93,7 +504,7 @@ impl Parser {
     /// we need to insert temporary placeholders to ensure proper field boundaries.
     fn handle_concatenated_fields(format: &str) -> String {
         // Find patterns where one field immediately follows another
-        let field_pattern = Regex::new(r"\$\w+").unwrap();
+        let field_pattern = Regex::new(r"\$(?:\{\w+\}|\w+)").unwrap();
         let mut result = String::new();
         let mut last_end = 0;
 
@@ -121,15 +532,22 @@ impl Parser {
 
     /// Convert field tokens to named regex groups.
     ///
-    /// This converts `$field_name` tokens to `(?P<field_name>[^delimiter]*)` patterns.
-    fn convert_fields_to_groups(format: &str) -> Result<String> {
-        let field_pattern = Regex::new(r"\$(\w+)").unwrap();
+    /// This converts `$field_name` and `${field_name}` tokens to
+    /// `(?P<field_name>[^delimiter]*)` patterns. Registered variables match their
+    /// pattern instead. When the escape mode escapes backslashes, a delimiter
+    /// preceded by a backslash doesn't end a field.
+    fn convert_fields_to_groups(
+        format: &str,
+        variables: &VariableRegistry,
+        escape: EscapeMode,
+    ) -> Result<String> {
+        let field_pattern = Regex::new(r"\$(?:\{(\w+)\}|(\w+))").unwrap();
         let mut result = String::new();
         let mut last_end = 0;
 
         for captures in field_pattern.captures_iter(format) {
             let full_match = captures.get(0).unwrap();
-            let field_name = captures.get(1).unwrap().as_str();
+            let field_name = captures.get(1).or(captures.get(2)).unwrap().as_str();
 
             // Add text before this field
             result.push_str(&format[last_end..full_match.start()]);
@@ -138,7 +556,10 @@ impl Parser {
             let delimiter = Self::determine_delimiter(format, full_match.end());
 
             // Create the named capture group
-            let group = if delimiter.is_empty() {
+            let group = if let Some(pattern) = variables.value_regex(field_name) {
+                // Registered variables match their own pattern
+                format!("(?P<{}>{})", field_name, pattern)
+            } else if delimiter.is_empty() {
                 // No delimiter, match everything to end of line
                 format!("(?P<{}>.*)", field_name)
             } else {
@@ -152,11 +573,11 @@ impl Parser {
                         format!("(?P<{}>[a-zA-Z0-9.-]+)", field_name)
                     } else {
                         // For other fields, use non-greedy matching
-                        format!("(?P<{}>[^{}]*?)", field_name, regex::escape(&delimiter))
+                        format!("(?P<{}>{}*?)", field_name, value_chars(&delimiter, escape))
                     }
                 } else {
                     // Normal field with delimiter
-                    format!("(?P<{}>[^{}]*)", field_name, regex::escape(&delimiter))
+                    format!("(?P<{}>{}*)", field_name, value_chars(&delimiter, escape))
                 }
             };
 
@@ -182,7 +603,7 @@ impl Parser {
         // Skip placeholder if present and look for the next delimiter
         if let Some(after_placeholder) = remaining.strip_prefix("@RSNX@") {
             // Find the next field and look past it for the delimiter
-            let field_pattern = Regex::new(r"\$\w+").unwrap();
+            let field_pattern = Regex::new(r"\$(?:\{\w+\}|\w+)").unwrap();
             if let Some(next_field_match) = field_pattern.find(after_placeholder) {
                 let after_next_field = &after_placeholder[next_field_match.end()..];
                 if after_next_field.is_empty() {
@@ -203,47 +624,79 @@ impl Parser {
     }
 
     /// Escape special regex characters in non-field parts of the format.
+    ///
+    /// Field groups can contain nested groups and character classes, so each one
+    /// extends to its matching closing parenthesis.
     fn escape_non_field_parts(format: &str) -> String {
-        let group_pattern = Regex::new(r"\(\?P<\w+>[^)]*\)").unwrap();
         let mut result = String::new();
-        let mut last_end = 0;
+        let mut rest = format;
 
-        for m in group_pattern.find_iter(format) {
+        while let Some(start) = rest.find("(?P<") {
             // Escape the text before this group
-            let before = &format[last_end..m.start()];
-            result.push_str(&regex::escape(before));
+            result.push_str(&regex::escape(&rest[..start]));
 
             // Add the group as-is
-            result.push_str(m.as_str());
+            let end = start + group_len(&rest[start..]);
+            result.push_str(&rest[start..end]);
 
-            last_end = m.end();
+            rest = &rest[end..];
         }
 
         // Escape remaining text
-        let remaining = &format[last_end..];
-        result.push_str(&regex::escape(remaining));
+        result.push_str(&regex::escape(rest));
 
         result
     }
 }
 
-impl StringParser for Parser {
-    /// Parse a log line into an entry using the compiled regex.
-    fn parse_string(&self, line: &str) -> Result<Entry> {
-        let captures = self
-            .regex
-            .captures(line)
-            .ok_or_else(|| Error::line_format_mismatch(line, &self.format))?;
-
-        let mut fields = HashMap::new();
+/// Build a regex matching one character, or one escape sequence, of a value that
+/// ends at `delimiter`.
+fn value_chars(delimiter: &str, escape: EscapeMode) -> String {
+    let delimiter = regex::escape(delimiter);
+    if escape.escapes_backslash() {
+        format!(r"(?:[^{}\\]|\\.)", delimiter)
+    } else {
+        format!("[^{}]", delimiter)
+    }
+}
 
-        // Extract all named capture groups
-        for name in self.regex.capture_names().flatten() {
-            if let Some(value) = captures.name(name) {
-                fields.insert(name.to_string(), value.as_str().to_string());
+/// Get the length of the regex group at the start of `pattern`, up to and
+/// including its closing parenthesis.
+fn group_len(pattern: &str) -> usize {
+    let mut depth = 0;
+    let mut class_depth = 0;
+    let mut bytes = pattern.bytes().enumerate().peekable();
+
+    while let Some((i, byte)) = bytes.next() {
+        match byte {
+            b'\\' => {
+                bytes.next();
             }
+            b'[' => {
+                // A `]` at the start of a class is a literal
+                class_depth += 1;
+                bytes.next_if(|&(_, byte)| byte == b'^');
+                bytes.next_if(|&(_, byte)| byte == b']');
+            }
+            b']' if class_depth > 0 => class_depth -= 1,
+            _ if class_depth > 0 => {}
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
         }
+    }
 
-        Ok(Entry::from_fields(fields))
+    pattern.len()
+}
+
+impl StringParser for Parser {
+    /// Parse a log line into an entry using the compiled regex.
+    fn parse_string(&self, line: &str) -> Result<Entry> {
+        self.parse_ref(line).map(Entry::from)
     }
 }
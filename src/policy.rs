@@ -0,0 +1,295 @@
+//! Error policies for handling malformed log lines.
+
+use crate::entry::Entry;
+use crate::error::{Error, Result};
+use crate::parser::{Parser, StringParser};
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+
+/// How a reader reacts to log lines that don't match the expected format.
+///
+/// I/O errors are never affected by the policy and are always returned to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorPolicy {
+    /// Return every malformed line as an error. This is the default.
+    #[default]
+    FailFast,
+    /// Silently skip malformed lines, only counting them in the summary.
+    Skip,
+    /// Skip malformed lines and keep their errors in the summary.
+    SkipAndCollect,
+    /// Skip malformed lines until more than the given number have been rejected,
+    /// then fail with [`Error::TooManyErrors`].
+    MaxErrors(u64),
+    /// Skip malformed lines, failing with [`Error::TooManyErrors`] at the end of
+    /// the input if more than the given fraction of lines were rejected.
+    ///
+    /// Create it with [`ErrorPolicy::max_error_rate`].
+    MaxErrorRate(ErrorRate),
+}
+
+impl ErrorPolicy {
+    /// Create a [`ErrorPolicy::MaxErrorRate`] policy.
+    ///
+    /// Returns [`Error::InvalidErrorRate`] if the rate isn't between `0.0` and
+    /// `1.0`, or is NaN.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::ErrorPolicy;
+    ///
+    /// let policy = ErrorPolicy::max_error_rate(0.01)?;
+    /// assert!(matches!(policy, ErrorPolicy::MaxErrorRate(rate) if rate.get() == 0.01));
+    /// assert!(ErrorPolicy::max_error_rate(1.5).is_err());
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn max_error_rate(rate: f64) -> Result<Self> {
+        ErrorRate::new(rate).map(ErrorPolicy::MaxErrorRate)
+    }
+}
+
+/// The fraction of lines [`ErrorPolicy::MaxErrorRate`] allows to be rejected.
+///
+/// It is always between `0.0` and `1.0`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct ErrorRate(f64);
+
+impl ErrorRate {
+    /// Create an error rate from a fraction of lines.
+    ///
+    /// Returns [`Error::InvalidErrorRate`] if the rate isn't between `0.0` and
+    /// `1.0`, or is NaN.
+    pub fn new(rate: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(Error::invalid_error_rate(rate));
+        }
+        Ok(Self(rate))
+    }
+
+    /// Get the fraction of lines.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+/// How a reader handles log lines that aren't valid UTF-8.
+///
+/// nginx writes variables such as `$request` and `$http_user_agent` byte for
+/// byte when the format uses `escape=none`, so a request with Latin-1 or binary
+/// data puts invalid UTF-8 in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Policy {
+    /// Reject the line with an [`Error::InvalidUtf8`] carrying its location,
+    /// which the reader's [`ErrorPolicy`] handles like any malformed line.
+    /// This is the default.
+    #[default]
+    Reject,
+    /// Replace invalid bytes with U+FFFD, as [`String::from_utf8_lossy`] does.
+    ///
+    /// The original bytes are kept in [`Entry::raw_line`].
+    Lossy,
+    /// Write each invalid byte as `\xHH`, the way nginx's `escape=default`
+    /// writes bytes outside printable ASCII.
+    ///
+    /// The escapes can't be told apart from the same text in the log, so use
+    /// [`Entry::raw_line`] to get the original bytes.
+    Escape,
+}
+
+impl Utf8Policy {
+    /// Decode a line according to the policy.
+    ///
+    /// Valid lines are returned without copying.
+    pub(crate) fn decode<'a>(&self, line: &'a [u8]) -> Result<Cow<'a, str>> {
+        let mut error = match std::str::from_utf8(line) {
+            Ok(line) => return Ok(Cow::Borrowed(line)),
+            Err(error) => error,
+        };
+        if *self == Utf8Policy::Reject {
+            return Err(Error::invalid_utf8(line, error.valid_up_to()));
+        }
+
+        let mut decoded = String::with_capacity(line.len() + 8);
+        let mut rest = line;
+        loop {
+            // Each error splits off a valid prefix and one invalid sequence
+            let (valid, invalid) = rest.split_at(error.valid_up_to());
+            let (invalid, after) = invalid.split_at(error.error_len().unwrap_or(invalid.len()));
+            decoded.push_str(std::str::from_utf8(valid).unwrap_or_default());
+            match self {
+                Utf8Policy::Reject => unreachable!("invalid lines are rejected above"),
+                Utf8Policy::Lossy => decoded.push(char::REPLACEMENT_CHARACTER),
+                Utf8Policy::Escape => {
+                    for byte in invalid {
+                        let _ = write!(decoded, "\\x{:02X}", byte);
+                    }
+                }
+            }
+
+            rest = after;
+            error = match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    decoded.push_str(valid);
+                    return Ok(Cow::Owned(decoded));
+                }
+                Err(error) => error,
+            };
+        }
+    }
+
+    /// Decode and parse a line, keeping its bytes on the entry if it had to be decoded.
+    pub(crate) fn parse(&self, parser: &Parser, line: &[u8]) -> Result<Entry> {
+        match self.decode(line)? {
+            Cow::Borrowed(line) => parser.parse_string(line),
+            Cow::Owned(decoded) => parser
+                .parse_string(&decoded)
+                .map(|entry| entry.with_raw_line(line)),
+        }
+    }
+}
+
+/// Summary of the lines rejected by a reader.
+#[derive(Debug, Default)]
+pub struct ErrorSummary {
+    /// Number of non-empty lines seen.
+    total_lines: u64,
+    /// Number of lines rejected as malformed.
+    rejected_count: u64,
+    /// Errors for rejected lines, kept by [`ErrorPolicy::SkipAndCollect`].
+    rejected: Vec<Error>,
+}
+
+impl ErrorSummary {
+    /// Get the number of non-empty lines seen so far.
+    pub fn total_lines(&self) -> u64 {
+        self.total_lines
+    }
+
+    /// Get the number of lines rejected as malformed.
+    pub fn rejected_count(&self) -> u64 {
+        self.rejected_count
+    }
+
+    /// Get the fraction of lines that were rejected, between `0.0` and `1.0`.
+    pub fn error_rate(&self) -> f64 {
+        if self.total_lines == 0 {
+            0.0
+        } else {
+            self.rejected_count as f64 / self.total_lines as f64
+        }
+    }
+
+    /// Get the errors for the rejected lines.
+    ///
+    /// This is only populated when using [`ErrorPolicy::SkipAndCollect`].
+    pub fn rejected(&self) -> &[Error] {
+        &self.rejected
+    }
+
+    /// Check if no lines were rejected.
+    pub fn is_clean(&self) -> bool {
+        self.rejected_count == 0
+    }
+}
+
+impl fmt::Display for ErrorSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rejected {} of {} lines ({:.2}%)",
+            self.rejected_count,
+            self.total_lines,
+            self.error_rate() * 100.0
+        )
+    }
+}
+
+/// Applies an [`ErrorPolicy`] to parse results and keeps the [`ErrorSummary`].
+#[derive(Debug, Default)]
+pub(crate) struct ErrorTracker {
+    policy: ErrorPolicy,
+    summary: ErrorSummary,
+    /// Set once a [`Error::TooManyErrors`] has been returned for too many rejected lines.
+    failed: bool,
+    /// Set once the end of input was handled.
+    finished: bool,
+}
+
+impl ErrorTracker {
+    pub(crate) fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    pub(crate) fn summary(&self) -> &ErrorSummary {
+        &self.summary
+    }
+
+    /// Check if the reader should stop producing entries.
+    pub(crate) fn is_done(&self) -> bool {
+        self.failed || self.finished
+    }
+
+    /// Forget that the end of input was reached, after the reader seeks back
+    /// into the input.
+    pub(crate) fn rewind(&mut self) {
+        self.finished = false;
+    }
+
+    /// Handle the parse result for one line.
+    ///
+    /// Returns `None` if the line should be skipped.
+    pub(crate) fn handle<T>(&mut self, result: Result<T>) -> Option<Result<T>> {
+        self.summary.total_lines += 1;
+
+        let error = match result {
+            Ok(value) => return Some(Ok(value)),
+            Err(error) if error.is_line_error() => error,
+            Err(error) => return Some(Err(error)),
+        };
+
+        self.summary.rejected_count += 1;
+        match self.policy {
+            ErrorPolicy::FailFast => Some(Err(error)),
+            ErrorPolicy::Skip | ErrorPolicy::MaxErrorRate(_) => None,
+            ErrorPolicy::SkipAndCollect => {
+                self.summary.rejected.push(error);
+                None
+            }
+            ErrorPolicy::MaxErrors(max) => {
+                if self.summary.rejected_count > max {
+                    self.failed = true;
+                    Some(Err(self.too_many_errors()))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Handle the end of the input, checking error rate limits.
+    pub(crate) fn finish(&mut self) -> Option<Error> {
+        if self.is_done() {
+            return None;
+        }
+        self.finished = true;
+
+        match self.policy {
+            ErrorPolicy::MaxErrorRate(rate) if self.summary.error_rate() > rate.get() => {
+                Some(self.too_many_errors())
+            }
+            _ => None,
+        }
+    }
+
+    fn too_many_errors(&self) -> Error {
+        Error::too_many_errors(self.summary.rejected_count, self.summary.total_lines)
+    }
+}
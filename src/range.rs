@@ -0,0 +1,180 @@
+//! Reading the entries of a time range from seekable input.
+
+use crate::entry::Entry;
+use crate::error::Result;
+use crate::reader::Reader;
+use crate::time::Timestamp;
+use std::io::Read;
+use std::ops::{Bound, RangeBounds};
+use std::time::Duration;
+
+/// An iterator over the entries of a reader that were logged within a time range.
+///
+/// Created by [`Reader::time_range`]. Entries outside the range are skipped,
+/// and iteration ends once an entry is later than the end of the range by more
+/// than the tolerance. Entries whose time can't be read are returned as errors.
+#[derive(Debug)]
+pub struct TimeRange<R: Read> {
+    reader: Reader<R>,
+    range: (Bound<Timestamp>, Bound<Timestamp>),
+    /// Reading stops at an entry logged at or after this time.
+    stop: Option<Timestamp>,
+    done: bool,
+}
+
+impl<R: Read> TimeRange<R> {
+    pub(crate) fn new(
+        reader: Reader<R>,
+        range: (Bound<Timestamp>, Bound<Timestamp>),
+        tolerance: Duration,
+    ) -> Self {
+        let stop = match range.1 {
+            Bound::Included(end) => end.checked_add(tolerance + Duration::from_nanos(1)),
+            Bound::Excluded(end) => end.checked_add(tolerance),
+            Bound::Unbounded => None,
+        };
+        Self {
+            reader,
+            range,
+            stop,
+            done: false,
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn reader(&self) -> &Reader<R> {
+        &self.reader
+    }
+
+    /// Get the underlying reader, positioned after the last entry read.
+    pub fn into_reader(self) -> Reader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for TimeRange<R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let entry = match self.reader.read()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            let time = match entry.timestamp() {
+                Ok(time) => time,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if self.stop.is_some_and(|stop| time >= stop) {
+                self.done = true;
+            } else if self.range.contains(&time) {
+                return Some(Ok(entry));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FORMAT: &str = "$remote_addr $msec";
+
+    fn time(msec: &str) -> Timestamp {
+        msec.parse().unwrap()
+    }
+
+    fn addrs(entries: impl Iterator<Item = Result<Entry>>) -> Vec<String> {
+        entries
+            .map(|entry| entry.unwrap().field("remote_addr").unwrap().to_string())
+            .collect()
+    }
+
+    /// A log of `count` lines one second apart, with a few malformed lines.
+    fn log(count: u64) -> String {
+        let mut log = String::new();
+        for i in 0..count {
+            log.push_str(&format!("10.0.0.{} {}.000\n", i % 256, 1000 + i));
+            if i % 97 == 0 {
+                log.push_str("garbage\n\n");
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_seek_time() {
+        let log = log(1000);
+        let mut reader = Reader::new(Cursor::new(log.as_str()), FORMAT).unwrap();
+
+        for second in [1000, 1001, 1500, 1999] {
+            reader.seek_time(time(&second.to_string())).unwrap();
+            let entry = reader.find_map(|entry| entry.ok()).unwrap();
+            assert_eq!(entry.timestamp().unwrap(), time(&second.to_string()));
+        }
+
+        reader.seek_time(time("999")).unwrap();
+        assert_eq!(reader.position(), crate::LineInfo::new(1, 0));
+        reader.seek_time(time("2000")).unwrap();
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_time_range() {
+        let reader = Reader::new(Cursor::new(log(1000)), FORMAT).unwrap();
+        let entries = reader
+            .time_range(time("1500")..time("1503"), Duration::ZERO)
+            .unwrap();
+        assert_eq!(addrs(entries), ["10.0.0.244", "10.0.0.245", "10.0.0.246"]);
+
+        let reader = Reader::new(Cursor::new(log(10)), FORMAT).unwrap();
+        let entries = reader
+            .time_range(time("1008")..=time("1100"), Duration::ZERO)
+            .unwrap();
+        assert_eq!(addrs(entries), ["10.0.0.8", "10.0.0.9"]);
+    }
+
+    #[test]
+    fn test_seek_after_end() {
+        let log: String = (0..10)
+            .map(|i| format!("10.0.0.{} {}.000\n", i, 1000 + i))
+            .collect();
+        let mut reader = Reader::new(Cursor::new(log.as_str()), FORMAT).unwrap();
+        assert_eq!(reader.by_ref().count(), 10);
+        assert!(reader.next().is_none());
+
+        reader.seek_time(time("1008")).unwrap();
+        assert_eq!(addrs(reader.by_ref()), ["10.0.0.8", "10.0.0.9"]);
+
+        let entries = reader
+            .time_range(time("1001")..time("1003"), Duration::ZERO)
+            .unwrap();
+        assert_eq!(addrs(entries), ["10.0.0.1", "10.0.0.2"]);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let log = "\
+            10.0.0.1 1000.000\n\
+            10.0.0.2 1003.000\n\
+            10.0.0.3 1001.000\n\
+            10.0.0.4 1004.000\n\
+            10.0.0.5 1002.000\n\
+            10.0.0.6 1010.000\n";
+
+        let reader = Reader::new(Cursor::new(log), FORMAT).unwrap();
+        let entries = reader
+            .time_range(time("1001")..time("1003"), Duration::ZERO)
+            .unwrap();
+        assert!(addrs(entries).is_empty());
+
+        let reader = Reader::new(Cursor::new(log), FORMAT).unwrap();
+        let entries = reader
+            .time_range(time("1001")..time("1003"), Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(addrs(entries), ["10.0.0.3", "10.0.0.5"]);
+    }
+}
@@ -1,20 +1,56 @@
 //! Log file reading and iteration functionality.
 
-use crate::entry::Entry;
+use crate::checkpoint::{Checkpoint, FileIdentity};
+use crate::entry::{Entry, EntryRef, LineInfo};
 use crate::error::{Error, Result};
-use crate::parser::{Parser, StringParser};
-use std::io::{BufRead, BufReader, Read};
+use crate::input::Input;
+use crate::parser::{Parser, Spans, StringParser};
+use crate::policy::{ErrorPolicy, ErrorSummary, ErrorTracker, Utf8Policy};
+use crate::range::TimeRange;
+use crate::time::Timestamp;
+use std::borrow::Cow;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// A reader that parses log files line by line using a specified format.
 ///
 /// The reader implements the Iterator trait, allowing you to process log entries
 /// using standard Rust iterator patterns.
+///
+/// Lines that are empty or only hold whitespace are skipped, by this and every
+/// other reader in the crate.
+///
+/// With the `mmap` feature, [`Reader::mmap`] opens a local file through a memory
+/// map instead. It returns an [`MmapReader`](crate::mmap::MmapReader) rather
+/// than a `Reader`, which has the same iterator behavior but no generic
+/// input and none of the methods that need one, such as seeking or checkpoints.
 #[derive(Debug)]
 pub struct Reader<R: Read> {
     /// The underlying buffered reader.
     reader: BufReader<R>,
     /// The parser for converting lines to entries.
     parser: Parser,
+    /// The error policy and summary of rejected lines.
+    tracker: ErrorTracker,
+    /// The location of the next line to be read.
+    position: LineInfo,
+    /// Whether a last line without a line ending is left unread.
+    hold_partial: bool,
+    /// How lines that aren't valid UTF-8 are handled.
+    utf8_policy: Utf8Policy,
+    /// Buffer holding the raw bytes of the current line, reused between reads.
+    bytes: Vec<u8>,
+    /// Buffer holding the current line, reused between reads.
+    line: String,
+    /// Whether the current line had to be decoded, because it isn't valid UTF-8.
+    decoded: bool,
+    /// Field spans of the current line, reused between reads.
+    spans: Spans,
+    /// The file being read and its identity, for readers that take checkpoints.
+    file: Option<(Arc<Path>, FileIdentity)>,
 }
 
 impl<R: Read> Reader<R> {
@@ -44,10 +80,7 @@ impl<R: Read> Reader<R> {
     /// ```
     pub fn new(input: R, format: &str) -> Result<Self> {
         let parser = Parser::new(format)?;
-        Ok(Self {
-            reader: BufReader::new(input),
-            parser,
-        })
+        Ok(Self::with_parser(input, parser))
     }
 
     /// Create a new reader with a custom parser.
@@ -62,18 +95,129 @@ impl<R: Read> Reader<R> {
         Self {
             reader: BufReader::new(input),
             parser,
+            tracker: ErrorTracker::default(),
+            position: LineInfo::new(1, 0),
+            hold_partial: false,
+            utf8_policy: Utf8Policy::default(),
+            bytes: Vec::new(),
+            decoded: false,
+            line: String::new(),
+            spans: Spans::new(),
+            file: None,
         }
     }
 
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// By default every malformed line is returned as an error. See [`ErrorPolicy`]
+    /// for the available alternatives.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::{ErrorPolicy, Reader};
+    /// use std::io::Cursor;
+    ///
+    /// let log_data = "127.0.0.1 200\ngarbage\n192.168.1.1 404";
+    /// let mut reader = Reader::new(Cursor::new(log_data), "$remote_addr $status")?
+    ///     .with_error_policy(ErrorPolicy::SkipAndCollect);
+    ///
+    /// let entries: Vec<_> = reader.by_ref().collect::<Result<_, _>>()?;
+    /// assert_eq!(entries.len(), 2);
+    /// assert_eq!(reader.error_summary().rejected_count(), 1);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.tracker = ErrorTracker::new(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// By default such a line is rejected with [`Error::InvalidUtf8`], which the
+    /// [`ErrorPolicy`] handles like a line that doesn't match the format.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::{Reader, Utf8Policy};
+    /// use std::io::Cursor;
+    ///
+    /// let log_data: &[u8] = b"127.0.0.1 \"Mozilla/5.0 (\xe9t\xe9)\"";
+    /// let mut reader = Reader::new(Cursor::new(log_data), r#"$remote_addr "$http_user_agent""#)?
+    ///     .with_utf8_policy(Utf8Policy::Escape);
+    ///
+    /// let entry = reader.read().unwrap()?;
+    /// assert_eq!(entry.field("http_user_agent")?, r"Mozilla/5.0 (\xE9t\xE9)");
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Get the policy for handling lines that aren't valid UTF-8.
+    pub fn utf8_policy(&self) -> Utf8Policy {
+        self.utf8_policy
+    }
+
     /// Get a reference to the underlying parser.
     pub fn parser(&self) -> &Parser {
         &self.parser
     }
 
+    /// Replace the parser with one derived from it.
+    pub(crate) fn map_parser(self, f: impl FnOnce(Parser) -> Parser) -> Self {
+        Self {
+            parser: f(self.parser),
+            ..self
+        }
+    }
+
+    /// Get the policy for handling lines that don't match the format.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.tracker.policy()
+    }
+
+    /// Get the summary of lines rejected so far.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.tracker.summary()
+    }
+
+    /// Get the location of the next line to be read.
+    ///
+    /// The line number counts every line, including skipped empty lines, and the
+    /// byte offset is the number of bytes consumed from the input so far.
+    pub fn position(&self) -> LineInfo {
+        self.position
+    }
+
+    /// Set the location of the first line, for input that starts partway
+    /// through a file.
+    pub(crate) fn with_position(mut self, position: LineInfo) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Leave a last line without a line ending unread instead of parsing it.
+    ///
+    /// This is off by default, so every line of the input is read. When reading
+    /// a log that nginx is still writing to, the last line may be incomplete;
+    /// holding it back keeps the reader's position at the start of the line, so
+    /// a [`Reader::checkpoint`] taken afterwards resumes there and the line is
+    /// read in full in the next run.
+    pub fn with_hold_partial(mut self, enabled: bool) -> Self {
+        self.hold_partial = enabled;
+        self
+    }
+
     /// Read the next entry from the log file.
     ///
     /// This method reads one line from the input and parses it into an Entry.
-    /// It returns `None` when the end of the file is reached.
+    /// It returns `None` when the end of the file is reached. Malformed lines are
+    /// handled according to the reader's [`ErrorPolicy`].
+    ///
+    /// Both entries and line errors carry the [`LineInfo`] of the line they came from.
     ///
     /// # Returns
     ///
@@ -82,30 +226,167 @@ impl<R: Read> Reader<R> {
     /// - `Some(Ok(entry))` indicates a successfully parsed entry
     /// - `Some(Err(error))` indicates a parsing or I/O error
     pub fn read(&mut self) -> Option<Result<Entry>> {
-        let mut line = String::new();
-
-        match self.reader.read_line(&mut line) {
-            Ok(0) => None, // EOF
-            Ok(_) => {
-                // Remove trailing newline
-                if line.ends_with('\n') {
-                    line.pop();
-                    if line.ends_with('\r') {
-                        line.pop();
-                    }
-                }
+        loop {
+            let result = match self.next_line()? {
+                Ok(info) => self
+                    .parser
+                    .parse_string(&self.line)
+                    .map(|entry| {
+                        if self.decoded {
+                            entry.with_raw_line(&*self.bytes)
+                        } else {
+                            entry
+                        }
+                    })
+                    .map(|entry| entry.with_line_info(info))
+                    .map_err(|e| e.with_line_info(info)),
+                Err(e) if e.is_line_error() => Err(e),
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(result) = self.tracker.handle(result) {
+                return Some(result);
+            }
+        }
+    }
 
-                // Skip empty lines
-                if line.trim().is_empty() {
-                    return self.read(); // Recursively read next line
+    /// Read the next entry, borrowing its values from the reader's line buffer.
+    ///
+    /// This works like [`Reader::read`] but reuses one buffer for every line and
+    /// returns an [`EntryRef`] instead of allocating an [`Entry`]. The entry is
+    /// only valid until the next call, so this method can't back an iterator.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::Reader;
+    /// use std::io::Cursor;
+    ///
+    /// let log_data = "127.0.0.1 200\n192.168.1.1 404";
+    /// let mut reader = Reader::new(Cursor::new(log_data), "$remote_addr $status")?;
+    ///
+    /// let mut errors = 0;
+    /// while let Some(entry) = reader.read_ref() {
+    ///     if entry?.field("status")? != "200" {
+    ///         errors += 1;
+    ///     }
+    /// }
+    /// assert_eq!(errors, 1);
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn read_ref(&mut self) -> Option<Result<EntryRef<'_>>> {
+        let info = loop {
+            let result = match self.next_line()? {
+                Ok(info) => self
+                    .parser
+                    .match_spans(&self.line, &mut self.spans)
+                    .map(|()| info)
+                    .map_err(|e| e.with_line_info(info)),
+                Err(e) if e.is_line_error() => Err(e),
+                Err(e) => return Some(Err(e)),
+            };
+            match self.tracker.handle(result) {
+                Some(Ok(info)) => break info,
+                Some(Err(e)) => return Some(Err(e)),
+                None => continue,
+            }
+        };
+
+        let mut entry = self.parser.entry_ref(&self.line, &self.spans);
+        if self.decoded {
+            entry = entry.with_raw_line(&self.bytes);
+        }
+        Some(Ok(entry.with_line_info(info)))
+    }
+
+    /// Turn the reader into an iterator that deserializes each entry into `T`.
+    ///
+    /// See the [`de`](crate::de) module for how field values are converted.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::Reader;
+    /// use serde::Deserialize;
+    /// use std::io::Cursor;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Hit {
+    ///     remote_addr: String,
+    ///     status: u16,
+    /// }
+    ///
+    /// let log_data = "127.0.0.1 200\n192.168.1.1 404";
+    /// let reader = Reader::new(Cursor::new(log_data), "$remote_addr $status")?;
+    ///
+    /// for hit in reader.deserialize::<Hit>() {
+    ///     let hit = hit?;
+    ///     println!("{} {}", hit.remote_addr, hit.status);
+    /// }
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(
+        self,
+    ) -> crate::de::DeserializeEntries<R, T> {
+        crate::de::DeserializeEntries::new(self)
+    }
+
+    /// Read the next non-empty line into the line buffer, without its line ending.
+    ///
+    /// Returns `None` at the end of the input, or an error from the error policy
+    /// when the input ends with too many rejected lines. Lines that can't be
+    /// decoded under the UTF-8 policy are returned as line errors.
+    fn next_line(&mut self) -> Option<Result<LineInfo>> {
+        loop {
+            if self.tracker.is_done() {
+                return None;
+            }
+
+            self.bytes.clear();
+            match self.reader.read_until(b'\n', &mut self.bytes) {
+                Ok(0) => return self.tracker.finish().map(Err), // EOF
+                Ok(_) if self.hold_partial && !self.bytes.ends_with(b"\n") => {
+                    // The line may still be being written
+                    return self.tracker.finish().map(Err);
                 }
+                Ok(len) => {
+                    let info = self.advance(len);
+
+                    // Remove trailing newline
+                    if self.bytes.ends_with(b"\n") {
+                        self.bytes.pop();
+                        if self.bytes.ends_with(b"\r") {
+                            self.bytes.pop();
+                        }
+                    }
 
-                Some(self.parser.parse_string(&line))
+                    self.line.clear();
+                    match self.utf8_policy.decode(&self.bytes) {
+                        Ok(line) => {
+                            self.decoded = matches!(line, Cow::Owned(_));
+                            self.line.push_str(&line);
+                        }
+                        Err(e) => return Some(Err(e.with_line_info(info))),
+                    }
+
+                    // Skip empty lines
+                    if !is_blank_line(self.line.as_bytes()) {
+                        return Some(Ok(info));
+                    }
+                }
+                Err(e) => return Some(Err(Error::Io { source: e })),
             }
-            Err(e) => Some(Err(Error::Io { source: e })),
         }
     }
 
+    /// Advance past a line of `len` bytes, returning the line's location.
+    fn advance(&mut self, len: usize) -> LineInfo {
+        let info = self.position;
+        self.position.line_number = info.line_number.map(|n| n + 1);
+        self.position.byte_offset += len as u64;
+        info
+    }
+
     /// Collect all entries into a vector.
     ///
     /// This is a convenience method that reads all entries from the log file
@@ -114,7 +395,8 @@ impl<R: Read> Reader<R> {
     ///
     /// # Returns
     ///
-    /// A vector of all entries, or an error if any line fails to parse.
+    /// A vector of all entries, or an error if any line fails to parse and the
+    /// error policy doesn't allow skipping it.
     pub fn collect_all(mut self) -> Result<Vec<Entry>> {
         let mut entries = Vec::new();
 
@@ -169,6 +451,231 @@ impl<R: Read> Reader<R> {
     }
 }
 
+impl Reader<Input> {
+    /// Open a log file, decompressing it if needed.
+    ///
+    /// The compression is detected from the file's magic bytes, so rotated logs
+    /// such as `access.log.2.gz` can be read like plain ones. See the
+    /// [`input`](crate::input) module for the supported formats and the features
+    /// that enable them.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use rsnx::Reader;
+    ///
+    /// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+    /// for entry in Reader::open("/var/log/nginx/access.log.2.gz", format)? {
+    ///     println!("{}", entry?.field("status")?);
+    /// }
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn open(path: impl AsRef<Path>, format: &str) -> Result<Self> {
+        Self::new(Input::open(path)?, format)
+    }
+
+    /// Open a log file to be read across runs with checkpoints.
+    ///
+    /// This works like [`Reader::open`], and also records which file was opened,
+    /// so that [`Reader::checkpoint`] can save how far it was read and
+    /// [`Reader::resume_from`] can continue there in the next run. To carry on
+    /// through rotations, use [`RotatedReader`](crate::RotatedReader).
+    ///
+    /// If nginx may still be writing the file, enable
+    /// [`Reader::with_hold_partial`] so that a last line without a line ending
+    /// is left for the next run instead of being parsed while incomplete.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use rsnx::{Checkpoint, Reader};
+    ///
+    /// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+    /// let mut reader =
+    ///     Reader::open_resumable("/var/log/nginx/access.log", format)?.with_hold_partial(true);
+    /// if let Some(checkpoint) = Checkpoint::load("access.checkpoint")? {
+    ///     reader = reader.resume_from(&checkpoint)?;
+    /// }
+    ///
+    /// for entry in reader.by_ref() {
+    ///     println!("{}", entry?.field("request")?);
+    /// }
+    ///
+    /// if let Some(checkpoint) = reader.checkpoint() {
+    ///     checkpoint.save("access.checkpoint")?;
+    /// }
+    /// # Ok::<(), rsnx::Error>(())
+    /// ```
+    pub fn open_resumable(path: impl AsRef<Path>, format: &str) -> Result<Self> {
+        Self::open_resumable_with_parser(path, Parser::new(format)?)
+    }
+
+    /// Open a log file to be read across runs, with a custom parser.
+    ///
+    /// See [`Reader::open_resumable`].
+    pub fn open_resumable_with_parser(path: impl AsRef<Path>, parser: Parser) -> Result<Self> {
+        let path = path.as_ref();
+        let identity = FileIdentity::of(path)?;
+        let mut reader = Self::with_parser(Input::open(path)?, parser);
+        reader.file = Some((Arc::from(path), identity));
+        Ok(reader)
+    }
+
+    /// Skip the entries read before a checkpoint was taken.
+    ///
+    /// If the checkpoint was taken in the file being read, reading starts at the
+    /// checkpoint's offset, and line numbers are unknown from there. Otherwise,
+    /// such as when the log has been rotated since, the whole file is read. Call
+    /// this before reading any entries.
+    ///
+    /// Returns an error if the reader wasn't opened with
+    /// [`Reader::open_resumable`] or the file can't be reopened.
+    pub fn resume_from(mut self, checkpoint: &Checkpoint) -> Result<Self> {
+        let (path, identity) = self.file.as_ref().ok_or_else(|| {
+            Error::invalid_checkpoint("the reader wasn't opened with Reader::open_resumable")
+        })?;
+
+        if !checkpoint.is_same_file(identity) {
+            return Ok(self);
+        }
+
+        let offset = checkpoint.offset();
+        self.reader = BufReader::new(Input::open_at(path, offset)?);
+        Ok(self.with_position(LineInfo {
+            line_number: (offset == 0).then_some(1),
+            byte_offset: offset,
+        }))
+    }
+
+    /// Get a checkpoint just past the last line read, or `None` if the reader
+    /// wasn't opened with [`Reader::open_resumable`].
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.file
+            .as_ref()
+            .map(|(path, identity)| Checkpoint::new(path, identity, self.position.byte_offset))
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Seek to the first line logged at or after a time.
+    ///
+    /// The position is found by binary search on byte offsets: each probe skips
+    /// to the next line boundary and reads the time of the first line there, as
+    /// with [`Entry::timestamp`]. Lines without a readable time are passed over
+    /// while searching. This assumes the input is ordered by time; see
+    /// [`Reader::time_range`] for input that is only nearly ordered.
+    ///
+    /// After seeking, line numbers are unknown unless the reader is back at the
+    /// start of the input.
+    pub fn seek_time(&mut self, time: Timestamp) -> Result<()> {
+        let (mut low, mut high) = (0, self.reader.seek(SeekFrom::End(0))?);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.probe(mid)? {
+                Some((start, timestamp)) if timestamp < time => low = start + 1,
+                _ => high = mid,
+            }
+        }
+        self.seek_line(low)?;
+        Ok(())
+    }
+
+    /// Read only the entries logged within a time range.
+    ///
+    /// The reader seeks to the start of the range with [`Reader::seek_time`],
+    /// and stops once it is past the end. As nginx writes entries nearly but not
+    /// exactly in time order, such as when logs are buffered per worker, entries
+    /// up to `tolerance` out of order are still found: the search starts that
+    /// much before the range, and reading stops only once an entry is that much
+    /// past it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rsnx::{Reader, Timestamp};
+    /// use std::io::Cursor;
+    /// use std::time::Duration;
+    ///
+    /// let log_data = "\
+    /// 10.0.0.1 [08/Nov/2013:13:59:58 +0000] 200
+    /// 10.0.0.2 [08/Nov/2013:14:00:03 +0000] 200
+    /// 10.0.0.3 [08/Nov/2013:14:00:01 +0000] 404
+    /// 10.0.0.4 [08/Nov/2013:14:05:00 +0000] 200
+    /// ";
+    /// let reader = Reader::new(Cursor::new(log_data), "$remote_addr [$time_local] $status")?;
+    ///
+    /// let start = Timestamp::parse_time_local("08/Nov/2013:14:00:00 +0000")?;
+    /// let end = Timestamp::parse_time_local("08/Nov/2013:14:05:00 +0000")?;
+    /// let entries = reader
+    ///     .time_range(start..end, Duration::from_secs(5))?
+    ///     .collect::<Result<Vec<_>, _>>()?;
+    ///
+    /// let addrs: Vec<_> = entries.iter().map(|e| e.field("remote_addr").unwrap()).collect();
+    /// assert_eq!(addrs, ["10.0.0.2", "10.0.0.3"]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn time_range(
+        mut self,
+        range: impl RangeBounds<Timestamp>,
+        tolerance: Duration,
+    ) -> Result<TimeRange<R>> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let search_start = match range.0 {
+            Bound::Included(start) | Bound::Excluded(start) => start.checked_sub(tolerance),
+            Bound::Unbounded => None,
+        };
+        match search_start {
+            Some(time) => self.seek_time(time)?,
+            None => {
+                self.seek_line(0)?;
+            }
+        }
+        Ok(TimeRange::new(self, range, tolerance))
+    }
+
+    /// Find the first line starting at or after `offset` that has a readable
+    /// time, returning where it starts and its time.
+    fn probe(&mut self, offset: u64) -> Result<Option<(u64, Timestamp)>> {
+        let mut start = self.seek_line(offset)?;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let len = self.reader.read_until(b'\n', &mut line)?;
+            if len == 0 {
+                return Ok(None);
+            }
+
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\n', '\r']);
+            if let Ok(timestamp) = self.parser.parse_string(text).and_then(|e| e.timestamp()) {
+                return Ok(Some((start, timestamp)));
+            }
+            start += len as u64;
+        }
+    }
+
+    /// Seek to the first line starting at or after `offset`, returning its offset.
+    fn seek_line(&mut self, offset: u64) -> Result<u64> {
+        let start = match offset {
+            0 => self.reader.seek(SeekFrom::Start(0))?,
+            offset => {
+                // Skip the rest of the line the offset falls in, unless the
+                // offset is right after a line ending
+                self.reader.seek(SeekFrom::Start(offset - 1))?;
+                self.bytes.clear();
+                offset - 1 + self.reader.read_until(b'\n', &mut self.bytes)? as u64
+            }
+        };
+
+        self.position = LineInfo {
+            line_number: (start == 0).then_some(1),
+            byte_offset: start,
+        };
+        self.tracker.rewind();
+        Ok(start)
+    }
+}
+
 impl<R: Read> Iterator for Reader<R> {
     type Item = Result<Entry>;
 
@@ -177,6 +684,17 @@ impl<R: Read> Iterator for Reader<R> {
     }
 }
 
+/// Check if a line is empty or only holds whitespace, so readers skip it.
+///
+/// Lines that aren't valid UTF-8 are never blank, and are left to the UTF-8
+/// policy.
+pub(crate) fn is_blank_line(line: &[u8]) -> bool {
+    match line.first() {
+        Some(&byte) if byte.is_ascii() && !char::from(byte).is_whitespace() => false,
+        _ => std::str::from_utf8(line).is_ok_and(|line| line.trim().is_empty()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,4 +762,229 @@ mod tests {
         let entries = reader.collect_all().unwrap();
         assert_eq!(entries.len(), 2);
     }
+
+    const MIXED_LOG: &str = r#"127.0.0.1 [08/Nov/2013:13:39:18 +0000] "GET /api/foo HTTP/1.1" 200 612
+this line is truncated
+192.168.1.1 [08/Nov/2013:13:40:18 +0000] "POST /api/bar HTTP/1.1" 404 0
+another bad line
+10.0.0.1 [08/Nov/2013:13:41:18 +0000] "GET /api/baz HTTP/1.1" 200 128"#;
+
+    const MIXED_FORMAT: &str = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent"#;
+
+    #[test]
+    fn test_error_policy_fail_fast() {
+        let reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT).unwrap();
+
+        let result = reader.collect_all();
+        assert!(matches!(result, Err(Error::LineFormatMismatch { .. })));
+    }
+
+    #[test]
+    fn test_error_policy_skip() {
+        let mut reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::Skip);
+
+        let entries: Result<Vec<_>> = reader.by_ref().collect();
+        assert_eq!(entries.unwrap().len(), 3);
+
+        let summary = reader.error_summary();
+        assert_eq!(summary.total_lines(), 5);
+        assert_eq!(summary.rejected_count(), 2);
+        assert!(summary.rejected().is_empty());
+    }
+
+    #[test]
+    fn test_error_policy_skip_and_collect() {
+        let mut reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::SkipAndCollect);
+
+        let mut count = 0;
+        reader
+            .process_entries(|_| -> Result<()> {
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, 3);
+
+        let rejected = reader.error_summary().rejected();
+        assert_eq!(rejected.len(), 2);
+        assert!(matches!(
+            &rejected[0],
+            Error::LineFormatMismatch { line, .. } if line == "this line is truncated"
+        ));
+    }
+
+    #[test]
+    fn test_error_policy_max_errors() {
+        let reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::MaxErrors(2));
+        assert_eq!(reader.collect_all().unwrap().len(), 3);
+
+        let mut reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::MaxErrors(1));
+        let results: Vec<_> = reader.by_ref().collect();
+
+        // The second bad line exceeds the limit and ends iteration
+        assert_eq!(results.len(), 3);
+        assert!(matches!(
+            results[2],
+            Err(Error::TooManyErrors {
+                rejected: 2,
+                total: 4
+            })
+        ));
+        assert!(reader.read().is_none());
+    }
+
+    #[test]
+    fn test_error_policy_max_error_rate() {
+        let reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::max_error_rate(0.5).unwrap());
+        assert_eq!(reader.collect_all().unwrap().len(), 3);
+
+        let reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::max_error_rate(0.25).unwrap());
+        assert!(matches!(
+            reader.collect_all(),
+            Err(Error::TooManyErrors {
+                rejected: 2,
+                total: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_error_policy_rejects_invalid_rate() {
+        for rate in [0.0, 1.0] {
+            let policy = ErrorPolicy::max_error_rate(rate).unwrap();
+            let reader = Reader::new(Cursor::new(MIXED_LOG), MIXED_FORMAT)
+                .unwrap()
+                .with_error_policy(policy);
+            assert_eq!(reader.error_policy(), policy);
+        }
+
+        for rate in [f64::NAN, -0.1, 1.5] {
+            assert!(
+                matches!(
+                    ErrorPolicy::max_error_rate(rate),
+                    Err(Error::InvalidErrorRate { .. })
+                ),
+                "rate {} was accepted",
+                rate
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_info() {
+        let log_data = "127.0.0.1 200\r\n\n192.168.1.1 404\nbad\n";
+        let mut reader = Reader::new(Cursor::new(log_data), "$remote_addr $status").unwrap();
+
+        let entry = reader.read().unwrap().unwrap();
+        assert_eq!(entry.line_info(), Some(LineInfo::new(1, 0)));
+
+        // The empty line still counts towards line numbers and offsets
+        let entry = reader.read().unwrap().unwrap();
+        assert_eq!(entry.line_info(), Some(LineInfo::new(3, 16)));
+
+        let error = reader.read().unwrap().unwrap_err();
+        assert_eq!(error.line_info(), Some(LineInfo::new(4, 32)));
+        assert!(error.to_string().contains("at line 4, byte 32"));
+
+        assert!(reader.read().is_none());
+        assert_eq!(reader.position(), LineInfo::new(5, 36));
+    }
+
+    #[test]
+    fn test_read_ref() {
+        let log_data = "127.0.0.1 200\n\nbad\n192.168.1.1 404\n";
+        let mut reader = Reader::new(Cursor::new(log_data), "$remote_addr $status")
+            .unwrap()
+            .with_error_policy(ErrorPolicy::Skip);
+
+        let entry = reader.read_ref().unwrap().unwrap();
+        assert_eq!(entry.field("remote_addr").unwrap(), "127.0.0.1");
+        assert_eq!(entry.line_info(), Some(LineInfo::new(1, 0)));
+
+        let entry = reader.read_ref().unwrap().unwrap();
+        assert_eq!(entry.field("status").unwrap(), "404");
+        assert_eq!(entry.line_info(), Some(LineInfo::new(4, 19)));
+
+        let owned = entry.to_owned();
+        assert_eq!(owned.field("remote_addr").unwrap(), "192.168.1.1");
+        assert_eq!(owned.line_info(), Some(LineInfo::new(4, 19)));
+
+        assert!(reader.read_ref().is_none());
+        assert_eq!(reader.error_summary().rejected_count(), 1);
+    }
+
+    #[test]
+    fn test_utf8_policy_reject() {
+        let log_data: &[u8] = b"127.0.0.1 200\n10.0.0.\xff 404\n192.168.1.1 500\n";
+        let mut reader = Reader::new(Cursor::new(log_data), "$remote_addr $status").unwrap();
+
+        reader.read().unwrap().unwrap();
+        let error = reader.read().unwrap().unwrap_err();
+        assert!(matches!(error, Error::InvalidUtf8 { valid_up_to: 7, .. }));
+        assert_eq!(error.line_info(), Some(LineInfo::new(2, 14)));
+        assert!(error
+            .to_string()
+            .contains("'10.0.0.\u{FFFD} 404' at line 2, byte 14"));
+
+        // Reading continues with the next line
+        let entry = reader.read().unwrap().unwrap();
+        assert_eq!(entry.line_info(), Some(LineInfo::new(3, 27)));
+
+        // Invalid lines are skipped like any malformed line
+        let mut reader = Reader::new(Cursor::new(log_data), "$remote_addr $status")
+            .unwrap()
+            .with_error_policy(ErrorPolicy::Skip);
+        let entries = reader.by_ref().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(reader.error_summary().rejected_count(), 1);
+    }
+
+    #[test]
+    fn test_utf8_policy_decode() {
+        let log_data: &[u8] = b"127.0.0.1 \"caf\xe9 \xf0\x9f\x98\"\n";
+        let format = r#"$remote_addr "$http_user_agent""#;
+
+        let mut reader = Reader::new(Cursor::new(log_data), format)
+            .unwrap()
+            .with_utf8_policy(Utf8Policy::Lossy);
+        let entry = reader.read().unwrap().unwrap();
+        assert_eq!(
+            entry.field("http_user_agent").unwrap(),
+            "caf\u{FFFD} \u{FFFD}"
+        );
+        assert_eq!(entry.raw_line(), log_data.strip_suffix(b"\n"));
+
+        let mut reader = Reader::new(Cursor::new(log_data), format)
+            .unwrap()
+            .with_utf8_policy(Utf8Policy::Escape);
+        let entry = reader.read_ref().unwrap().unwrap();
+        assert_eq!(
+            entry.field("http_user_agent").unwrap(),
+            r"caf\xE9 \xF0\x9F\x98"
+        );
+        assert_eq!(
+            crate::EscapeMode::Default
+                .unescape(entry.field("http_user_agent").unwrap())
+                .as_bytes(),
+            "caf\u{FFFD} \u{FFFD}".as_bytes()
+        );
+        assert_eq!(entry.raw_line(), log_data.strip_suffix(b"\n"));
+
+        let mut reader = Reader::new(Cursor::new(r#"127.0.0.1 "caf\xE9""#), format)
+            .unwrap()
+            .with_utf8_policy(Utf8Policy::Escape);
+        assert_eq!(reader.read().unwrap().unwrap().raw_line(), None);
+    }
 }
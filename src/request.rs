@@ -0,0 +1,191 @@
+//! Splitting the `$request` line into its method, URI and protocol.
+
+/// The names of the fields that can be derived from `$request`.
+pub const DERIVED_FIELDS: [&str; 5] = [
+    "request_method",
+    "request_uri",
+    "uri_path",
+    "query_string",
+    "server_protocol",
+];
+
+/// The parts of an HTTP request line, such as `GET /search?q=rust HTTP/1.1`.
+///
+/// [`Entry::field`](crate::Entry::field) uses this to provide `request_method`,
+/// `request_uri`, `uri_path`, `query_string` and `server_protocol` for entries
+/// that have a `request` field but didn't log those variables directly.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::RequestLine;
+///
+/// let request = RequestLine::parse("GET /search?q=rust HTTP/1.1").unwrap();
+/// assert_eq!(request.method(), "GET");
+/// assert_eq!(request.uri(), "/search?q=rust");
+/// assert_eq!(request.path(), "/search");
+/// assert_eq!(request.query(), Some("q=rust"));
+/// assert_eq!(request.protocol(), Some("HTTP/1.1"));
+///
+/// assert_eq!(RequestLine::parse("-"), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLine<'a> {
+    method: &'a str,
+    uri: &'a str,
+    protocol: Option<&'a str>,
+}
+
+impl<'a> RequestLine<'a> {
+    /// Parse a request line.
+    ///
+    /// A request line is a method, a URI and an optional protocol separated by
+    /// single spaces. The method must consist of uppercase ASCII letters, `_` or
+    /// `-`, the URI must not contain control characters, and the protocol must
+    /// look like `HTTP/1.1`. Anything else, such as the `-` nginx logs for an
+    /// empty request, or the escaped bytes of a TLS handshake sent to a plain
+    /// HTTP port, returns `None`.
+    pub fn parse(request: &'a str) -> Option<Self> {
+        let mut parts = request.split(' ');
+        let method = parts.next()?;
+        let uri = parts.next()?;
+        let protocol = parts.next();
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let valid = is_method(method)
+            && !uri.is_empty()
+            && !uri.chars().any(char::is_control)
+            && protocol.map_or(true, is_protocol);
+        valid.then_some(Self {
+            method,
+            uri,
+            protocol,
+        })
+    }
+
+    /// Get the request method, such as `GET`.
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// Get the request URI, including the query string.
+    pub fn uri(&self) -> &'a str {
+        self.uri
+    }
+
+    /// Get the path of the request URI, without the query string.
+    pub fn path(&self) -> &'a str {
+        self.uri.split_once('?').map_or(self.uri, |(path, _)| path)
+    }
+
+    /// Get the query string of the request URI, without the `?`.
+    ///
+    /// Returns `None` if the URI has no `?`.
+    pub fn query(&self) -> Option<&'a str> {
+        self.uri.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Get the protocol, such as `HTTP/1.1`.
+    ///
+    /// Returns `None` for HTTP/0.9 style requests, which have no protocol.
+    pub fn protocol(&self) -> Option<&'a str> {
+        self.protocol
+    }
+
+    /// Get a derived field by name.
+    ///
+    /// A missing query string or protocol is returned as an empty string, as
+    /// nginx does for `$query_string` and `$server_protocol`. Returns `None` if
+    /// the name isn't one of [`DERIVED_FIELDS`].
+    pub fn field(&self, name: &str) -> Option<&'a str> {
+        match name {
+            "request_method" => Some(self.method),
+            "request_uri" => Some(self.uri),
+            "uri_path" => Some(self.path()),
+            "query_string" => Some(self.query().unwrap_or_default()),
+            "server_protocol" => Some(self.protocol.unwrap_or_default()),
+            _ => None,
+        }
+    }
+}
+
+/// Look up a field derived from a `$request` value.
+///
+/// Returns `None` if the name isn't a derived field or the request is malformed.
+pub(crate) fn derived_field<'a>(request: Option<&'a str>, name: &str) -> Option<&'a str> {
+    if !DERIVED_FIELDS.contains(&name) {
+        return None;
+    }
+    RequestLine::parse(request?)?.field(name)
+}
+
+/// Check if a string is a valid request method.
+fn is_method(method: &str) -> bool {
+    !method.is_empty()
+        && method
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b == b'_' || b == b'-')
+}
+
+/// Check if a string is an HTTP protocol version, such as `HTTP/1.1` or `HTTP/2.0`.
+fn is_protocol(protocol: &str) -> bool {
+    let Some(version) = protocol.strip_prefix("HTTP/") else {
+        return false;
+    };
+    let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+    [major, minor]
+        .iter()
+        .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_lines() {
+        let request = RequestLine::parse("PRI * HTTP/2.0").unwrap();
+        assert_eq!(request.method(), "PRI");
+        assert_eq!(request.path(), "*");
+
+        let request = RequestLine::parse("GET /index.html").unwrap();
+        assert_eq!(request.protocol(), None);
+        assert_eq!(request.field("server_protocol"), Some(""));
+        assert_eq!(request.field("query_string"), Some(""));
+
+        let request = RequestLine::parse("GET /?a=1?b HTTP/1.1").unwrap();
+        assert_eq!(request.path(), "/");
+        assert_eq!(request.query(), Some("a=1?b"));
+        assert_eq!(request.field("status"), None);
+    }
+
+    #[test]
+    fn test_malformed_request_lines() {
+        for request in [
+            "-",
+            "",
+            "GET",
+            "GET  / HTTP/1.1",
+            "get / HTTP/1.1",
+            "GET / HTTP/1.1 extra",
+            "GET / FTP/1.0",
+            "GET / HTTP/",
+            "GET /\u{1} HTTP/1.1",
+            r"\x16\x03\x01\x02\x00\x01\x00\x01\xFC\x03\x03",
+            "\u{16}\u{3}\u{1}",
+        ] {
+            assert_eq!(RequestLine::parse(request), None, "{:?}", request);
+        }
+    }
+
+    #[test]
+    fn test_derived_field() {
+        let request = Some("POST /api?x=1 HTTP/1.1");
+        assert_eq!(derived_field(request, "uri_path"), Some("/api"));
+        assert_eq!(derived_field(request, "request"), None);
+        assert_eq!(derived_field(Some("-"), "request_method"), None);
+        assert_eq!(derived_field(None, "request_method"), None);
+    }
+}
@@ -0,0 +1,455 @@
+//! Reading a set of rotated log files as one stream.
+
+use crate::checkpoint::{Checkpoint, FileIdentity};
+use crate::entry::Entry;
+use crate::error::{Error, Result};
+use crate::input::Input;
+use crate::parser::Parser;
+use crate::policy::{ErrorPolicy, ErrorSummary, ErrorTracker, Utf8Policy};
+use crate::reader::Reader;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Extensions of compressed rotated files.
+const COMPRESSED_EXTENSIONS: [&str; 6] = [".gz", ".bz2", ".xz", ".zst", ".zstd", ".Z"];
+
+/// A reader that reads a log file and its rotated predecessors, oldest first.
+///
+/// Given the path of a log such as `/var/log/nginx/access.log`, the reader
+/// finds the rotated files next to it and reads them in chronological order as
+/// one stream. Files are ordered by their logrotate suffix:
+///
+/// - numbered: `access.log.3.gz`, `access.log.2.gz`, `access.log.1`, where a
+///   higher number is older
+/// - dated (`dateext`): `access.log-20240101.gz`, `access.log-20240102`, where
+///   an earlier date is older
+/// - the live file `access.log` itself comes last
+///
+/// Compressed files are decoded as by [`Reader::open`]. Files with other
+/// suffixes, such as `access.log.bak`, are ignored. Each entry carries the file
+/// it was read from in [`Entry::source`].
+///
+/// To read only what was logged since the last run, save a
+/// [`RotatedReader::checkpoint`] when done and pass it to
+/// [`RotatedReader::resume_from`] next time.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rsnx::RotatedReader;
+///
+/// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+/// for entry in RotatedReader::open("/var/log/nginx/access.log*", format)? {
+///     let entry = entry?;
+///     println!("{}: {}", entry.source().unwrap().display(), entry.field("status")?);
+/// }
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct RotatedReader {
+    /// All files of the set, oldest first.
+    files: Vec<PathBuf>,
+    /// The files that haven't been opened yet.
+    pending: VecDeque<PathBuf>,
+    /// The file being read, kept after the last file ends for its checkpoint.
+    current: Option<CurrentFile>,
+    /// The checkpoint to resume the next file at.
+    resume: Option<Checkpoint>,
+    parser: Parser,
+    /// The error policy and summary of rejected lines, across all files.
+    tracker: ErrorTracker,
+    utf8_policy: Utf8Policy,
+    /// Whether a last line without a line ending in the newest file is left unread.
+    hold_partial: bool,
+}
+
+/// A file of the set that is being read.
+#[derive(Debug)]
+struct CurrentFile {
+    path: Arc<Path>,
+    reader: Reader<Input>,
+}
+
+impl RotatedReader {
+    /// Open the rotated set of a log file.
+    ///
+    /// `path` is the path of the live log, optionally followed by a single `*`,
+    /// such as `/var/log/nginx/access.log*`. The set is always found from the
+    /// file name as described above; other glob patterns aren't supported. Returns
+    /// an error if no file of the set exists or the format string is invalid.
+    pub fn open(path: impl AsRef<Path>, format: &str) -> Result<Self> {
+        Self::with_parser(path, Parser::new(format)?)
+    }
+
+    /// Open the rotated set of a log file with a custom parser.
+    pub fn with_parser(path: impl AsRef<Path>, parser: Parser) -> Result<Self> {
+        let files = rotated_files(path)?;
+        Ok(Self {
+            pending: files.iter().cloned().collect(),
+            files,
+            current: None,
+            resume: None,
+            parser,
+            tracker: ErrorTracker::default(),
+            utf8_policy: Utf8Policy::default(),
+            hold_partial: false,
+        })
+    }
+
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// The policy applies to the set as a whole, so limits count the lines of
+    /// every file. See [`Reader::with_error_policy`] for details.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.tracker = ErrorTracker::new(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`] for details.
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Leave a last line without a line ending in the newest file unread.
+    ///
+    /// Enable this when saving checkpoints of a log nginx is still writing to.
+    /// Older files are complete, so all their lines are read. See
+    /// [`Reader::with_hold_partial`] for details.
+    pub fn with_hold_partial(mut self, enabled: bool) -> Self {
+        self.hold_partial = enabled;
+        self
+    }
+
+    /// Skip the entries read before a checkpoint was taken.
+    ///
+    /// The file the checkpoint was taken in is looked up in the set, even if it
+    /// has been rotated and compressed since, and reading starts at the
+    /// checkpoint's offset in it. Older files are skipped. If the file is no
+    /// longer part of the set, the whole set is read.
+    ///
+    /// Returns an error if a file of the set can't be read.
+    pub fn resume_from(mut self, checkpoint: &Checkpoint) -> Result<Self> {
+        for (i, path) in self.files.iter().enumerate().rev() {
+            if checkpoint.is_same_file(&FileIdentity::of(path)?) {
+                self.pending = self.files[i..].iter().cloned().collect();
+                self.current = None;
+                self.resume = Some(checkpoint.clone());
+                break;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Get a checkpoint just past the last line read, or `None` if no file has
+    /// been opened yet.
+    ///
+    /// With [`RotatedReader::with_hold_partial`], a last line without a line
+    /// ending in the newest file isn't returned, and is read in full when
+    /// resuming.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.current
+            .as_ref()
+            .and_then(|current| current.reader.checkpoint())
+    }
+
+    /// Get the error policy.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.tracker.policy()
+    }
+
+    /// Get the summary of lines rejected so far, across all files.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.tracker.summary()
+    }
+
+    /// Get all files of the set, oldest first.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Get the file being read, if any.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|current| &*current.path)
+    }
+
+    /// Read the next entry, moving on to the next file at the end of each one.
+    pub fn read(&mut self) -> Option<Result<Entry>> {
+        loop {
+            if self.tracker.is_done() {
+                return None;
+            }
+
+            if let Some(current) = &mut self.current {
+                match current.reader.read() {
+                    Some(Err(e)) if !e.is_line_error() => return Some(Err(e)),
+                    Some(result) => {
+                        let path = Arc::clone(&current.path);
+                        let result = result.map(|entry| entry.with_source(path));
+                        match self.tracker.handle(result) {
+                            Some(result) => return Some(result),
+                            None => continue,
+                        }
+                    }
+                    None if self.pending.is_empty() => return self.tracker.finish().map(Err),
+                    None => {}
+                }
+            }
+
+            let path = self.pending.pop_front()?;
+            match self.open_file(path) {
+                Ok(current) => self.current = Some(current),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Open a file of the set, resuming at the checkpoint for the first one.
+    ///
+    /// The file's reader returns every malformed line, so that the set's error
+    /// policy sees them all.
+    fn open_file(&mut self, path: PathBuf) -> Result<CurrentFile> {
+        // Only the newest file may still be being written
+        let mut reader = Reader::open_resumable_with_parser(&path, self.parser.clone())?
+            .with_utf8_policy(self.utf8_policy)
+            .with_hold_partial(self.hold_partial && self.pending.is_empty());
+        if let Some(checkpoint) = self.resume.take() {
+            reader = reader.resume_from(&checkpoint)?;
+        }
+
+        Ok(CurrentFile {
+            path: Arc::from(path),
+            reader,
+        })
+    }
+
+    /// Collect all entries into a vector.
+    pub fn collect_all(mut self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        while let Some(result) = self.read() {
+            entries.push(result?);
+        }
+        Ok(entries)
+    }
+}
+
+impl Iterator for RotatedReader {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read()
+    }
+}
+
+/// Find the rotated set of a log file, ordered oldest first.
+///
+/// `path` is the path of the live log, optionally followed by a single `*`. The
+/// live log doesn't have to exist. Returns an error if the file name holds any
+/// other glob characters, the directory can't be read or no file of the set
+/// exists.
+///
+/// # Example
+///
+/// ```rust
+/// # let dir = tempfile::tempdir()?;
+/// # for name in ["access.log", "access.log.1", "access.log.2.gz", "error.log"] {
+/// #     std::fs::write(dir.path().join(name), "")?;
+/// # }
+/// use rsnx::rotated::rotated_files;
+///
+/// let files = rotated_files(dir.path().join("access.log"))?;
+/// let names: Vec<_> = files.iter().map(|f| f.file_name().unwrap()).collect();
+/// assert_eq!(names, ["access.log.2.gz", "access.log.1", "access.log"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn rotated_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    let base = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.strip_suffix('*').unwrap_or(name))
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| not_found(path))?;
+    if base.contains(['*', '?', '[']) {
+        return Err(unsupported_pattern(path));
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut files = Vec::new();
+    for dir_entry in fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let Some(name) = dir_entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let Some(rotation) = name.strip_prefix(base).and_then(Rotation::parse) else {
+            continue;
+        };
+        if dir_entry.file_type()?.is_file() {
+            files.push((rotation, dir_entry.path()));
+        }
+    }
+
+    if files.is_empty() {
+        return Err(not_found(path));
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+fn not_found(path: &Path) -> Error {
+    let message = format!("no log files found for '{}'", path.display());
+    Error::Io {
+        source: io::Error::new(io::ErrorKind::NotFound, message),
+    }
+}
+
+fn unsupported_pattern(path: &Path) -> Error {
+    let message = format!(
+        "'{}' isn't a log path with an optional trailing '*', other glob patterns aren't supported",
+        path.display()
+    );
+    Error::Io {
+        source: io::Error::new(io::ErrorKind::InvalidInput, message),
+    }
+}
+
+/// The position of a file in a rotated set, ordered oldest first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Rotation {
+    /// A `dateext` suffix, with the digits of the date.
+    Dated(String),
+    /// A numbered suffix, where higher numbers are older.
+    Numbered(Reverse<u64>),
+    /// The live file, without a suffix.
+    Current,
+}
+
+impl Rotation {
+    /// Parse the suffix of a file name after the base name.
+    fn parse(suffix: &str) -> Option<Self> {
+        if suffix.is_empty() {
+            return Some(Rotation::Current);
+        }
+
+        let suffix = COMPRESSED_EXTENSIONS
+            .iter()
+            .find_map(|ext| suffix.strip_suffix(ext))
+            .unwrap_or(suffix);
+        let rotation = suffix.strip_prefix(['.', '-'])?;
+        if rotation.is_empty() || !rotation.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        if rotation.bytes().all(|b| b.is_ascii_digit()) && rotation.len() < 8 {
+            return rotation
+                .parse()
+                .ok()
+                .map(|n| Rotation::Numbered(Reverse(n)));
+        }
+
+        // Dates such as `20240115`, `2024011517` or `2024-01-15`
+        let digits: String = rotation.chars().filter(char::is_ascii_digit).collect();
+        let is_date = digits.len() >= 8
+            && rotation
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '-' || c == '_');
+        is_date.then_some(Rotation::Dated(digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rotation() {
+        assert_eq!(Rotation::parse(""), Some(Rotation::Current));
+        assert_eq!(Rotation::parse(".1"), Some(Rotation::Numbered(Reverse(1))));
+        assert_eq!(
+            Rotation::parse(".12.gz"),
+            Some(Rotation::Numbered(Reverse(12)))
+        );
+        assert_eq!(
+            Rotation::parse("-20240115.bz2"),
+            Some(Rotation::Dated("20240115".into()))
+        );
+        assert_eq!(
+            Rotation::parse("-2024-01-15"),
+            Some(Rotation::Dated("20240115".into()))
+        );
+        for suffix in [".gz", ".bak", "_ssl", ".1.bak", "-old", ".2024-1", "1"] {
+            assert_eq!(Rotation::parse(suffix), None, "{:?}", suffix);
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let mut rotations = vec![
+            Rotation::Current,
+            Rotation::parse(".1").unwrap(),
+            Rotation::parse(".10.gz").unwrap(),
+            Rotation::parse(".2.gz").unwrap(),
+        ];
+        rotations.sort();
+        assert_eq!(
+            rotations,
+            [
+                Rotation::Numbered(Reverse(10)),
+                Rotation::Numbered(Reverse(2)),
+                Rotation::Numbered(Reverse(1)),
+                Rotation::Current,
+            ]
+        );
+
+        let mut rotations = [
+            Rotation::parse("-20240102").unwrap(),
+            Rotation::Current,
+            Rotation::parse("-20231231.gz").unwrap(),
+        ];
+        rotations.sort();
+        assert_eq!(rotations[0], Rotation::Dated("20231231".into()));
+        assert_eq!(rotations[2], Rotation::Current);
+    }
+
+    #[test]
+    fn test_rotated_files_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["access.log", "access.log.1", "other.log"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let names = |pattern: &str| {
+            rotated_files(dir.path().join(pattern)).map(|files| {
+                files
+                    .iter()
+                    .map(|file| file.file_name().unwrap().to_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(names("access.log").unwrap(), ["access.log.1", "access.log"]);
+        assert_eq!(
+            names("access.log*").unwrap(),
+            ["access.log.1", "access.log"]
+        );
+
+        for pattern in ["access.log**", "*.log", "access.lo?", "access.[l]og*"] {
+            let error = names(pattern).unwrap_err();
+            assert!(
+                error.to_string().contains("optional trailing '*'"),
+                "{}: {}",
+                pattern,
+                error
+            );
+        }
+        assert!(names("error.log*").is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! A matching engine that scans log lines segment by segment instead of using a regex.
+
+use crate::error::Result;
+use crate::escape::EscapeMode;
+use crate::format::Segment;
+use crate::parser::Spans;
+use crate::variables::VariableRegistry;
+use regex::Regex;
+use std::ops::Range;
+
+/// How a field decides where its value ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FieldMatcher {
+    /// The value extends to the end of the line, giving back characters if the
+    /// steps that follow need them.
+    ToEnd,
+    /// The value extends up to the first occurrence of the delimiter.
+    Until(char),
+    /// The value is as short as possible, leaving the rest to the next field. It
+    /// never contains the delimiter, if there is one.
+    Lazy(Option<char>),
+    /// The value is the longest non-empty run of hostname characters that lets
+    /// the steps that follow match.
+    Host,
+}
+
+impl FieldMatcher {
+    /// Choose the matcher for the field at `index` based on the segments that follow it.
+    ///
+    /// A field followed by a literal ends at the literal's first character. When
+    /// fields are concatenated, `$host` takes hostname characters and any other
+    /// field takes as little as the next field allows, unless the run of fields
+    /// ends the format.
+    pub(crate) fn for_field(segments: &[Segment], index: usize) -> Self {
+        let is_host = matches!(&segments[index], Segment::Field(name) if name == "host");
+
+        match segments.get(index + 1) {
+            None => Self::ToEnd,
+            Some(Segment::Literal(literal)) => {
+                Self::Until(literal.chars().next().unwrap_or_default())
+            }
+            Some(Segment::Field(_)) => match segments.get(index + 2) {
+                None => Self::ToEnd,
+                Some(_) if is_host => Self::Host,
+                Some(Segment::Literal(literal)) => Self::Lazy(literal.chars().next()),
+                Some(Segment::Field(_)) => Self::Lazy(None),
+            },
+        }
+    }
+}
+
+/// A step of a compiled scan program.
+#[derive(Debug, Clone)]
+enum Step {
+    /// Match literal text exactly.
+    Literal(String),
+    /// Capture a field value.
+    Field(FieldMatcher),
+    /// Capture a field value matching a registered variable pattern.
+    Pattern {
+        /// The pattern anchored at the current position, giving the preferred match.
+        prefix: Regex,
+        /// The pattern anchored at both ends, checking other lengths of the value.
+        whole: Regex,
+    },
+}
+
+/// How far a line that didn't match got, reported by [`Program::diagnose`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Failure {
+    /// The index of the segment that failed, or `None` if the line has text
+    /// left after the last one.
+    pub(crate) segment_index: Option<usize>,
+    /// The byte offset in the line where it failed.
+    pub(crate) byte_offset: usize,
+}
+
+impl Failure {
+    /// Keep whichever of the failures got furthest into the line, or the
+    /// earlier one of a tie.
+    fn update(furthest: &mut Option<Self>, failure: Self) {
+        if furthest.map_or(true, |furthest| failure.byte_offset > furthest.byte_offset) {
+            *furthest = Some(failure);
+        }
+    }
+}
+
+/// A log format compiled into a sequence of literal and field steps.
+///
+/// Lines are matched left to right, and field boundaries are found with plain
+/// character searches, which is faster than the regex engine and makes the
+/// handling of adjacent fields easy to predict. Fields whose length isn't fixed
+/// by a delimiter, such as concatenated fields and registered variables, are
+/// retried with other lengths when the steps that follow them fail, in the same
+/// order the regex engine tries them.
+#[derive(Debug, Clone)]
+pub(crate) struct Program {
+    steps: Vec<Step>,
+    /// Whether a backslash in a value escapes the character after it.
+    escaped: bool,
+}
+
+impl Program {
+    /// Compile format segments into a scan program.
+    ///
+    /// Fields for variables in the registry match the variable's pattern. When the
+    /// escape mode escapes backslashes, delimiters preceded by a backslash are
+    /// skipped.
+    pub(crate) fn compile(
+        segments: &[Segment],
+        variables: &VariableRegistry,
+        escape: EscapeMode,
+    ) -> Result<Self> {
+        let steps = segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| match segment {
+                Segment::Literal(literal) => Ok(Step::Literal(literal.clone())),
+                Segment::Field(name) => match variables.value_regex(name) {
+                    Some(pattern) => Ok(Step::Pattern {
+                        prefix: Regex::new(&format!("^(?:{})", pattern))?,
+                        whole: Regex::new(&format!("^(?:{})$", pattern))?,
+                    }),
+                    None => Ok(Step::Field(FieldMatcher::for_field(segments, i))),
+                },
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            steps,
+            escaped: escape.escapes_backslash(),
+        })
+    }
+
+    /// Match a line, storing the byte range of each field in `spans`.
+    ///
+    /// Returns `false` if the line doesn't match.
+    pub(crate) fn match_spans(&self, line: &str, spans: &mut Spans) -> bool {
+        spans.clear();
+        self.match_steps(&self.steps, line, 0, spans, &mut None)
+    }
+
+    /// Find where a line that doesn't match the program fails.
+    ///
+    /// Fields are tried with the same lengths as when matching, and the failure
+    /// that got furthest into the line is reported. Returns `None` if the line
+    /// matches.
+    pub(crate) fn diagnose(&self, line: &str) -> Option<Failure> {
+        let mut furthest = None;
+        if self.match_steps(&self.steps, line, 0, &mut Spans::new(), &mut furthest) {
+            return None;
+        }
+        furthest
+    }
+
+    /// Match `steps` against the line from `pos` to its end, pushing the span of
+    /// each field onto `spans` and keeping the furthest failure in `furthest`.
+    fn match_steps(
+        &self,
+        steps: &[Step],
+        line: &str,
+        pos: usize,
+        spans: &mut Spans,
+        furthest: &mut Option<Failure>,
+    ) -> bool {
+        let fail = |furthest: &mut Option<Failure>, segment_index| {
+            let failure = Failure {
+                segment_index,
+                byte_offset: pos,
+            };
+            Failure::update(furthest, failure);
+            false
+        };
+        let index = self.steps.len() - steps.len();
+        let Some((step, next)) = steps.split_first() else {
+            return pos == line.len() || fail(furthest, None);
+        };
+        let rest = &line[pos..];
+
+        match step {
+            Step::Literal(literal) => {
+                if !rest.starts_with(literal.as_str()) {
+                    return fail(furthest, Some(index));
+                }
+                self.match_steps(next, line, pos + literal.len(), spans, furthest)
+            }
+            Step::Field(FieldMatcher::ToEnd) => (0..=rest.len())
+                .rev()
+                .filter(|&len| rest.is_char_boundary(len))
+                .any(|len| self.capture(next, line, pos..pos + len, spans, furthest)),
+            Step::Field(FieldMatcher::Until(delimiter)) => {
+                let len = match self.escaped {
+                    true => find_unescaped(rest, *delimiter),
+                    false => rest.find(*delimiter),
+                };
+                let span = pos..pos + len.unwrap_or(rest.len());
+                self.capture(next, line, span, spans, furthest)
+            }
+            Step::Field(FieldMatcher::Lazy(delimiter)) => {
+                let mut chars = rest.char_indices();
+                let mut len = 0;
+                loop {
+                    if self.capture(next, line, pos..pos + len, spans, furthest) {
+                        return true;
+                    }
+                    let Some((i, c)) = chars.next() else {
+                        return false;
+                    };
+                    if Some(c) == *delimiter {
+                        return false;
+                    }
+                    len = i + c.len_utf8();
+                    if c == '\\' && self.escaped {
+                        let Some((i, c)) = chars.next() else {
+                            return false;
+                        };
+                        len = i + c.len_utf8();
+                    }
+                }
+            }
+            Step::Field(FieldMatcher::Host) => {
+                let max = rest.find(|c| !is_host_char(c)).unwrap_or(rest.len());
+                if max == 0 {
+                    return fail(furthest, Some(index));
+                }
+                (1..=max)
+                    .rev()
+                    .any(|len| self.capture(next, line, pos..pos + len, spans, furthest))
+            }
+            Step::Pattern { prefix, whole } => {
+                let Some(preferred) = prefix.find(rest).map(|found| found.end()) else {
+                    return fail(furthest, Some(index));
+                };
+                self.capture(next, line, pos..pos + preferred, spans, furthest)
+                    || (0..=rest.len())
+                        .rev()
+                        .filter(|&len| len != preferred && rest.is_char_boundary(len))
+                        .filter(|&len| whole.is_match(&rest[..len]))
+                        .any(|len| self.capture(next, line, pos..pos + len, spans, furthest))
+            }
+        }
+    }
+
+    /// Capture `span` as the value of the current field and match the steps after it.
+    ///
+    /// The span is removed again if the remaining steps don't match.
+    fn capture(
+        &self,
+        steps: &[Step],
+        line: &str,
+        span: Range<usize>,
+        spans: &mut Spans,
+        furthest: &mut Option<Failure>,
+    ) -> bool {
+        let end = span.end;
+        spans.push(Some(span));
+        if self.match_steps(steps, line, end, spans, furthest) {
+            return true;
+        }
+        spans.pop();
+        false
+    }
+}
+
+/// Find the first occurrence of `delimiter` that isn't part of an escape sequence.
+fn find_unescaped(s: &str, delimiter: char) -> Option<usize> {
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == delimiter {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Check if a character can appear in a hostname.
+fn is_host_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '.' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::tokenize;
+
+    fn scan(format: &str, line: &str) -> Option<Vec<String>> {
+        let program = Program::compile(
+            &tokenize(format),
+            &VariableRegistry::new(),
+            EscapeMode::None,
+        )
+        .unwrap();
+        let mut spans = Spans::new();
+        program.match_spans(line, &mut spans).then(|| {
+            spans
+                .into_iter()
+                .map(|span| line[span.unwrap()].to_string())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_field_matchers() {
+        let segments = tokenize("$host$request_uri $a$b");
+        assert_eq!(FieldMatcher::for_field(&segments, 0), FieldMatcher::Host);
+        assert_eq!(
+            FieldMatcher::for_field(&segments, 1),
+            FieldMatcher::Until(' ')
+        );
+        assert_eq!(FieldMatcher::for_field(&segments, 3), FieldMatcher::ToEnd);
+        assert_eq!(FieldMatcher::for_field(&segments, 4), FieldMatcher::ToEnd);
+
+        let segments = tokenize("$a$b|");
+        assert_eq!(
+            FieldMatcher::for_field(&segments, 0),
+            FieldMatcher::Lazy(Some('|'))
+        );
+    }
+
+    #[test]
+    fn test_scan_delimited_fields() {
+        assert_eq!(
+            scan(
+                r#"$remote_addr [$time_local] "$request""#,
+                r#"1.2.3.4 [now] "GET / HTTP/1.1""#
+            ),
+            Some(vec![
+                "1.2.3.4".into(),
+                "now".into(),
+                "GET / HTTP/1.1".into()
+            ])
+        );
+        assert_eq!(scan("$a [$b]", "x [y"), None);
+        assert_eq!(scan("[$a]", "[x] trailing"), None);
+    }
+
+    #[test]
+    fn test_scan_concatenated_fields() {
+        assert_eq!(
+            scan("$host$request_uri $status", "example.com/path?q=1 200"),
+            Some(vec!["example.com".into(), "/path?q=1".into(), "200".into()])
+        );
+        assert_eq!(scan("$a$b|", "left|"), Some(vec!["".into(), "left".into()]));
+        assert_eq!(scan("$host$uri|", "/path|"), None);
+    }
+
+    #[test]
+    fn test_scan_variable_patterns() {
+        let program = Program::compile(
+            &tokenize("$time_local $status$body_bytes_sent"),
+            &VariableRegistry::builtin(),
+            EscapeMode::None,
+        )
+        .unwrap();
+        let line = "08/Nov/2013:13:39:18 +0000 404-";
+        let mut spans = Spans::new();
+
+        assert!(program.match_spans(line, &mut spans));
+        let values: Vec<_> = spans
+            .iter()
+            .map(|span| &line[span.clone().unwrap()])
+            .collect();
+        assert_eq!(values, ["08/Nov/2013:13:39:18 +0000", "404", "-"]);
+
+        assert!(!program.match_spans("08/Nov/2013:13:39:18 +0000 OK12", &mut spans));
+    }
+
+    #[test]
+    fn test_scan_escaped_delimiters() {
+        let segments = tokenize(r#""$http_user_agent" $status"#);
+        let line = r#""a \"quoted\" \\" 200"#;
+        let mut spans = Spans::new();
+
+        let program =
+            Program::compile(&segments, &VariableRegistry::new(), EscapeMode::Json).unwrap();
+        assert!(program.match_spans(line, &mut spans));
+        assert_eq!(&line[spans[0].clone().unwrap()], r#"a \"quoted\" \\"#);
+
+        let program =
+            Program::compile(&segments, &VariableRegistry::new(), EscapeMode::None).unwrap();
+        assert!(!program.match_spans(line, &mut spans));
+    }
+}
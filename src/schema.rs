@@ -0,0 +1,76 @@
+//! Field layouts shared between a parser and the entries it produces.
+
+use std::collections::HashMap;
+
+/// The ordered set of field names produced by a log format.
+///
+/// A parser builds its schema once and shares it with every entry through an
+/// `Arc`, so entries only store their values, indexed by position.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::Schema;
+///
+/// let schema = Schema::new(["remote_addr", "status"]);
+/// assert_eq!(schema.index_of("status"), Some(1));
+/// assert_eq!(schema.names(), ["remote_addr", "status"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    /// The field names in format order.
+    names: Vec<String>,
+    /// Map from field name to its position in `names`.
+    index: HashMap<String, usize>,
+}
+
+impl Schema {
+    /// Create a schema from field names.
+    ///
+    /// Names keep the given order. Repeated names are only added once.
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut schema = Self::default();
+        for name in names {
+            let name = name.into();
+            if !schema.index.contains_key(&name) {
+                schema.index.insert(name.clone(), schema.names.len());
+                schema.names.push(name);
+            }
+        }
+        schema
+    }
+
+    /// Get the field names in format order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Get the position of a field, or `None` if the schema doesn't contain it.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Get the name of the field at a position.
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index).map(String::as_str)
+    }
+
+    /// Check if the schema contains a field.
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// Get the number of fields in the schema.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Check if the schema has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
@@ -0,0 +1,417 @@
+//! Reading logs asynchronously as a [`Stream`].
+//!
+//! [`AsyncReader`] and [`AsyncNginxReader`] are the async counterparts of
+//! [`Reader`](crate::Reader) and [`NginxReader`](crate::NginxReader). They read
+//! from any tokio [`AsyncRead`], so logs can be parsed from files, sockets or
+//! pipes inside a tokio service without `spawn_blocking`. This module requires
+//! the `tokio` feature.
+
+use crate::entry::{Entry, LineInfo};
+use crate::error::{Error, Result};
+use crate::nginx::extract_nginx_log_format;
+use crate::parser::Parser;
+use crate::policy::{ErrorPolicy, ErrorSummary, ErrorTracker, Utf8Policy};
+use crate::reader::is_blank_line;
+use futures_core::Stream;
+use std::future;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, BufReader};
+
+/// A reader that parses log lines from async input.
+///
+/// It behaves like a [`Reader`](crate::Reader): empty lines are skipped,
+/// entries and errors carry their [`LineInfo`], and malformed lines are
+/// handled by its [`ErrorPolicy`]. Entries are read with
+/// [`AsyncReader::read`], or through its [`Stream`] implementation, which
+/// works with the combinators of `futures` and `tokio-stream`.
+///
+/// The input must be [`Unpin`]; pin other inputs with [`Box::pin`] first.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rsnx::AsyncReader;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let format = r#"$remote_addr [$time_local] "$request" $status"#;
+/// let file = tokio::fs::File::open("/var/log/nginx/access.log").await?;
+/// let mut reader = AsyncReader::new(file, format)?;
+///
+/// while let Some(entry) = reader.read().await {
+///     println!("{}", entry?.field("request")?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncReader<R: AsyncRead> {
+    /// The underlying buffered reader.
+    reader: BufReader<R>,
+    /// The parser for converting lines to entries.
+    parser: Parser,
+    /// The error policy and summary of rejected lines.
+    tracker: ErrorTracker,
+    utf8_policy: Utf8Policy,
+    /// The location of the next line to be read.
+    position: LineInfo,
+    /// Buffer holding the current line. Between reads it holds the start of a
+    /// line whose end hasn't arrived yet.
+    line: Vec<u8>,
+}
+
+impl<R: AsyncRead> AsyncReader<R> {
+    /// Create a new reader with the specified input source and format string.
+    ///
+    /// Returns an error if the format string is invalid.
+    pub fn new(input: R, format: &str) -> Result<Self> {
+        Ok(Self::with_parser(input, Parser::new(format)?))
+    }
+
+    /// Create a new reader with a custom parser.
+    pub fn with_parser(input: R, parser: Parser) -> Self {
+        Self {
+            reader: BufReader::new(input),
+            parser,
+            tracker: ErrorTracker::default(),
+            utf8_policy: Utf8Policy::default(),
+            position: LineInfo::new(1, 0),
+            line: Vec::new(),
+        }
+    }
+
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// See [`Reader::with_error_policy`](crate::Reader::with_error_policy).
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.tracker = ErrorTracker::new(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`](crate::Reader::with_utf8_policy).
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Get a reference to the underlying parser.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Replace the parser with one derived from it.
+    pub(crate) fn map_parser(self, f: impl FnOnce(Parser) -> Parser) -> Self {
+        Self {
+            parser: f(self.parser),
+            ..self
+        }
+    }
+
+    /// Get the policy for handling lines that don't match the format.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.tracker.policy()
+    }
+
+    /// Get the summary of lines rejected so far.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.tracker.summary()
+    }
+
+    /// Get the location of the next line to be read.
+    pub fn position(&self) -> LineInfo {
+        self.position
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncReader<R> {
+    /// Read the next entry, as [`Reader::read`](crate::Reader::read) does.
+    ///
+    /// Returns `None` at the end of the input. If the future is dropped before
+    /// it completes, no entry is lost: the partly read line is kept for the
+    /// next call.
+    pub async fn read(&mut self) -> Option<Result<Entry>> {
+        future::poll_fn(|cx| self.poll_read(cx)).await
+    }
+
+    /// Collect all entries into a vector.
+    ///
+    /// See [`Reader::collect_all`](crate::Reader::collect_all).
+    pub async fn collect_all(mut self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        while let Some(result) = self.read().await {
+            entries.push(result?);
+        }
+        Ok(entries)
+    }
+
+    /// Poll for the next entry.
+    fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Entry>>> {
+        loop {
+            if self.tracker.is_done() {
+                return Poll::Ready(None);
+            }
+
+            let info = match ready!(self.poll_next_line(cx)) {
+                Some(Ok(info)) => info,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(self.tracker.finish().map(Err)),
+            };
+            let result = self
+                .utf8_policy
+                .parse(&self.parser, &self.line)
+                .map(|entry| entry.with_line_info(info))
+                .map_err(|e| e.with_line_info(info));
+            self.line.clear();
+            if let Some(result) = self.tracker.handle(result) {
+                return Poll::Ready(Some(result));
+            }
+        }
+    }
+
+    /// Poll for the next non-empty line, leaving it in the line buffer without
+    /// its line ending.
+    ///
+    /// Returns `None` at the end of the input.
+    fn poll_next_line(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<LineInfo>>> {
+        loop {
+            if let Err(e) = ready!(self.poll_fill_line(cx)) {
+                return Poll::Ready(Some(Err(Error::Io { source: e })));
+            }
+            if self.line.is_empty() {
+                return Poll::Ready(None); // EOF
+            }
+
+            let info = self.position;
+            self.position.line_number = info.line_number.map(|n| n + 1);
+            self.position.byte_offset += self.line.len() as u64;
+
+            // Remove trailing newline
+            if self.line.last() == Some(&b'\n') {
+                self.line.pop();
+                if self.line.last() == Some(&b'\r') {
+                    self.line.pop();
+                }
+            }
+
+            // Skip empty lines
+            if !is_blank_line(&self.line) {
+                return Poll::Ready(Some(Ok(info)));
+            }
+            self.line.clear();
+        }
+    }
+
+    /// Poll until the line buffer holds a whole line, or the rest of the input.
+    ///
+    /// Bytes are moved into the buffer as they arrive, so nothing is lost if
+    /// the input isn't ready and the caller gives up.
+    fn poll_fill_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let available = ready!(Pin::new(&mut self.reader).poll_fill_buf(cx))?;
+            if available.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let (used, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(newline) => (newline + 1, true),
+                None => (available.len(), false),
+            };
+            self.line.extend_from_slice(&available[..used]);
+            Pin::new(&mut self.reader).consume(used);
+            if done {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncReader<R> {
+    type Item = Result<Entry>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_read(cx)
+    }
+}
+
+/// An async reader that parses logs with a format from nginx configuration.
+///
+/// This is the async counterpart of [`NginxReader`](crate::NginxReader). The
+/// configuration is small and read synchronously; the log is read
+/// asynchronously.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::AsyncNginxReader;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> rsnx::Result<()> {
+/// let config = r#"log_format main '$remote_addr "$request" $status';"#;
+/// let log: &[u8] = b"127.0.0.1 \"GET / HTTP/1.1\" 200\n";
+///
+/// let reader = AsyncNginxReader::new(log, config.as_bytes(), "main")?;
+/// let entries = reader.collect_all().await?;
+/// assert_eq!(entries[0].field("status")?, "200");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncNginxReader<R: AsyncRead> {
+    /// The underlying reader.
+    reader: AsyncReader<R>,
+}
+
+impl<R: AsyncRead> AsyncNginxReader<R> {
+    /// Create a new reader by extracting the format from nginx configuration.
+    ///
+    /// See [`NginxReader::new`](crate::NginxReader::new).
+    pub fn new<C: Read>(log_input: R, nginx_config: C, format_name: &str) -> Result<Self> {
+        let parser = extract_nginx_log_format(nginx_config, format_name)?.parser()?;
+        let reader = AsyncReader::with_parser(log_input, parser);
+
+        Ok(Self { reader })
+    }
+
+    /// Set the policy for handling lines that don't match the format.
+    ///
+    /// See [`Reader::with_error_policy`](crate::Reader::with_error_policy).
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.reader = self.reader.with_error_policy(policy);
+        self
+    }
+
+    /// Set the policy for handling lines that aren't valid UTF-8.
+    ///
+    /// See [`Reader::with_utf8_policy`](crate::Reader::with_utf8_policy).
+    pub fn with_utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.reader = self.reader.with_utf8_policy(policy);
+        self
+    }
+
+    /// Enable or disable decoding escape sequences in values.
+    ///
+    /// See [`NginxReader::with_unescape`](crate::NginxReader::with_unescape).
+    pub fn with_unescape(mut self, enabled: bool) -> Self {
+        self.reader = self
+            .reader
+            .map_parser(|parser| parser.with_unescape(enabled));
+        self
+    }
+
+    /// Get the summary of lines rejected so far.
+    pub fn error_summary(&self) -> &ErrorSummary {
+        self.reader.error_summary()
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn reader(&self) -> &AsyncReader<R> {
+        &self.reader
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    pub fn reader_mut(&mut self) -> &mut AsyncReader<R> {
+        &mut self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncNginxReader<R> {
+    /// Read the next entry from the log.
+    pub async fn read(&mut self) -> Option<Result<Entry>> {
+        self.reader.read().await
+    }
+
+    /// Collect all entries into a vector.
+    pub async fn collect_all(self) -> Result<Vec<Entry>> {
+        self.reader.collect_all().await
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncNginxReader<R> {
+    type Item = Result<Entry>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().reader.poll_read(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+    use std::io::Cursor;
+    use tokio::io::AsyncWriteExt;
+
+    const FORMAT: &str = "$remote_addr $status";
+
+    fn strings(results: Vec<Result<Entry>>) -> Vec<std::result::Result<Entry, String>> {
+        results
+            .into_iter()
+            .map(|result| result.map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_same_as_reader() {
+        let data: &[u8] =
+            b"127.0.0.1 200\r\n\n  \n\x0b\t\n\xc2\xa0\xe3\x80\x80\n\xff \ngarbage\n192.168.1.1 404\n10.0.0.1 500";
+        let expected: Vec<_> = Reader::new(Cursor::new(data), FORMAT).unwrap().collect();
+
+        let mut reader = AsyncReader::new(data, FORMAT).unwrap();
+        let mut actual = Vec::new();
+        while let Some(result) = reader.read().await {
+            actual.push(result);
+        }
+        assert_eq!(strings(actual), strings(expected));
+        assert_eq!(reader.position(), LineInfo::new(10, 67));
+
+        let mut reader = AsyncReader::new(&b"127.0.0.1 \xff\n127.0.0.1 200"[..], FORMAT).unwrap();
+        assert!(matches!(
+            reader.read().await,
+            Some(Err(Error::InvalidUtf8 { .. }))
+        ));
+        assert!(reader.read().await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_partial_lines() {
+        let (mut writer, input) = tokio::io::duplex(4);
+        let mut reader = AsyncReader::new(input, FORMAT)
+            .unwrap()
+            .with_error_policy(ErrorPolicy::SkipAndCollect);
+
+        let write = tokio::spawn(async move {
+            for chunk in ["127.0.", "0.1 200\ngarb", "age\n\n192.168.1.1 4", "04"] {
+                writer.write_all(chunk.as_bytes()).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let entry = reader.read().await.unwrap().unwrap();
+        assert_eq!(entry.line_info(), Some(LineInfo::new(1, 0)));
+        let entry = reader.read().await.unwrap().unwrap();
+        assert_eq!(entry.field("remote_addr").unwrap(), "192.168.1.1");
+        assert_eq!(entry.field("status").unwrap(), "404");
+        assert_eq!(entry.line_info(), Some(LineInfo::new(4, 23)));
+        assert!(reader.read().await.is_none());
+        assert_eq!(reader.error_summary().rejected_count(), 1);
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_nginx_reader() {
+        let config = r#"log_format main escape=json '$remote_addr "$http_user_agent"';"#;
+        let log: &[u8] = b"127.0.0.1 \"curl \\\"7\\\"\"\n";
+
+        let mut reader = AsyncNginxReader::new(log, config.as_bytes(), "main")
+            .unwrap()
+            .with_unescape(true);
+        let entry = reader.read().await.unwrap().unwrap();
+        assert_eq!(entry.field("http_user_agent").unwrap(), "curl \"7\"");
+        assert!(reader.read().await.is_none());
+
+        assert!(AsyncNginxReader::new(log, config.as_bytes(), "missing").is_err());
+    }
+}
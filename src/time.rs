@@ -0,0 +1,563 @@
+//! Timestamps parsed from nginx time variables.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Month abbreviations used by `$time_local`.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// The range of seconds since the Unix epoch covered by the years 0000 to 9999.
+const SECONDS_RANGE: std::ops::RangeInclusive<i64> = -62_167_219_200..=253_402_300_799;
+
+/// A point in time with the UTC offset it was logged in.
+///
+/// Parsed from the values of nginx's time variables:
+///
+/// - `$time_local`, such as `08/Nov/2013:13:39:18 +0000`
+/// - `$time_iso8601`, such as `2013-11-08T13:39:18+00:00`
+/// - `$msec`, such as `1383917958.123`, which is always UTC
+///
+/// Timestamps compare by the instant they represent, so timestamps logged in
+/// different time zones can be sorted and filtered together. With the `chrono`
+/// feature, timestamps convert to `chrono::DateTime`, and date-times within the
+/// years 0000 to 9999 convert back with `TryFrom`.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::Timestamp;
+///
+/// let local = Timestamp::parse_time_local("08/Nov/2013:14:39:18 +0100")?;
+/// let iso = Timestamp::parse_iso8601("2013-11-08T13:39:18+00:00")?;
+/// assert_eq!(local, iso);
+/// assert_eq!(local.unix_timestamp(), 1383917958);
+/// assert_eq!(local.offset_seconds(), 3600);
+/// assert_eq!(local.to_string(), "2013-11-08T14:39:18+01:00");
+///
+/// let msec: Timestamp = "1383917958.500".parse()?;
+/// assert!(msec > iso);
+/// # Ok::<(), rsnx::time::ParseTimestampError>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp {
+    /// Seconds since the Unix epoch.
+    seconds: i64,
+    /// Nanoseconds within the second.
+    nanos: u32,
+    /// Offset from UTC in seconds.
+    offset: i32,
+}
+
+/// Error returned when a string isn't a timestamp in a supported format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTimestampError {
+    value: String,
+}
+
+impl fmt::Display for ParseTimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid timestamp '{}'", self.value)
+    }
+}
+
+impl std::error::Error for ParseTimestampError {}
+
+/// Error returned when a `chrono::DateTime` can't be a [`Timestamp`], because it
+/// is outside the years 0000 to 9999 or falls on a leap second.
+#[cfg(feature = "chrono")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampRangeError {
+    value: String,
+}
+
+#[cfg(feature = "chrono")]
+impl fmt::Display for TimestampRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "date-time '{}' is out of range for a timestamp",
+            self.value
+        )
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl std::error::Error for TimestampRangeError {}
+
+impl Timestamp {
+    /// Create a timestamp from seconds and nanoseconds since the Unix epoch and a
+    /// UTC offset in seconds.
+    ///
+    /// Returns `None` if the time is outside the years 0000 to 9999, `nanos`
+    /// isn't less than one second or the offset is a day or more.
+    pub fn new(seconds: i64, nanos: u32, offset_seconds: i32) -> Option<Self> {
+        if !SECONDS_RANGE.contains(&seconds)
+            || nanos >= 1_000_000_000
+            || i64::from(offset_seconds).abs() >= SECONDS_PER_DAY
+        {
+            return None;
+        }
+
+        Some(Self {
+            seconds,
+            nanos,
+            offset: offset_seconds,
+        })
+    }
+
+    /// Parse a `$time_local` value, such as `08/Nov/2013:13:39:18 +0000`.
+    pub fn parse_time_local(value: &str) -> Result<Self, ParseTimestampError> {
+        parse_time_local(value).ok_or_else(|| invalid(value))
+    }
+
+    /// Parse a `$time_iso8601` value, such as `2013-11-08T13:39:18+00:00`.
+    ///
+    /// Fractional seconds and a `Z` suffix are accepted as well.
+    pub fn parse_iso8601(value: &str) -> Result<Self, ParseTimestampError> {
+        parse_iso8601(value).ok_or_else(|| invalid(value))
+    }
+
+    /// Parse a `$msec` value, such as `1383917958.123`.
+    pub fn parse_msec(value: &str) -> Result<Self, ParseTimestampError> {
+        parse_msec(value).ok_or_else(|| invalid(value))
+    }
+
+    /// Get the number of whole seconds since the Unix epoch.
+    pub fn unix_timestamp(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Get the number of milliseconds since the Unix epoch.
+    pub fn unix_millis(&self) -> i64 {
+        self.seconds * 1000 + i64::from(self.nanos / 1_000_000)
+    }
+
+    /// Get the nanoseconds within the second.
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Get the offset from UTC in seconds that the time was logged in.
+    pub fn offset_seconds(&self) -> i32 {
+        self.offset
+    }
+
+    /// Add a duration, keeping the UTC offset.
+    ///
+    /// Returns `None` if the result is after the year 9999.
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let mut seconds = self
+            .seconds
+            .checked_add(i64::try_from(duration.as_secs()).ok()?)?;
+        let mut nanos = self.nanos + duration.subsec_nanos();
+        if nanos >= 1_000_000_000 {
+            nanos -= 1_000_000_000;
+            seconds = seconds.checked_add(1)?;
+        }
+        Self::new(seconds, nanos, self.offset)
+    }
+
+    /// Subtract a duration, keeping the UTC offset.
+    ///
+    /// Returns `None` if the result is before the year 0000.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        let mut seconds = self
+            .seconds
+            .checked_sub(i64::try_from(duration.as_secs()).ok()?)?;
+        let nanos = match self.nanos.checked_sub(duration.subsec_nanos()) {
+            Some(nanos) => nanos,
+            None => {
+                seconds = seconds.checked_sub(1)?;
+                self.nanos + 1_000_000_000 - duration.subsec_nanos()
+            }
+        };
+        Self::new(seconds, nanos, self.offset)
+    }
+}
+
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.seconds, self.nanos).cmp(&(other.seconds, other.nanos))
+    }
+}
+
+impl Hash for Timestamp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.seconds, self.nanos).hash(state);
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = ParseTimestampError;
+
+    /// Parse a value of any of the supported time variables.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_time_local(s)
+            .or_else(|| parse_iso8601(s))
+            .or_else(|| parse_msec(s))
+            .ok_or_else(|| invalid(s))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// Format the timestamp in ISO 8601 in its own UTC offset, with milliseconds
+    /// if it has a fractional part.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let local = self.seconds + i64::from(self.offset);
+        let (year, month, day) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let time = local.rem_euclid(SECONDS_PER_DAY);
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        )?;
+        if self.nanos != 0 {
+            write!(f, ".{:03}", self.nanos / 1_000_000)?;
+        }
+
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset.unsigned_abs();
+        write!(f, "{}{:02}:{:02}", sign, offset / 3600, offset / 60 % 60)
+    }
+}
+
+/// Timestamps serialize as ISO 8601 strings in their own UTC offset.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Timestamps deserialize from strings in any of the formats [`FromStr`] accepts.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TimestampVisitor;
+
+        impl serde::de::Visitor<'_> for TimestampVisitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an nginx $time_local, $time_iso8601 or $msec value")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Timestamp, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(TimestampVisitor)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for chrono::DateTime<chrono::FixedOffset> {
+    fn from(timestamp: Timestamp) -> Self {
+        let offset = chrono::FixedOffset::east_opt(timestamp.offset)
+            .expect("timestamp offsets are less than a day");
+        chrono::DateTime::from_timestamp(timestamp.seconds, timestamp.nanos)
+            .expect("timestamp within the range of chrono")
+            .with_timezone(&offset)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> TryFrom<chrono::DateTime<Tz>> for Timestamp {
+    type Error = TimestampRangeError;
+
+    fn try_from(datetime: chrono::DateTime<Tz>) -> Result<Self, Self::Error> {
+        use chrono::Offset;
+
+        let offset = datetime.offset().fix();
+        Timestamp::new(
+            datetime.timestamp(),
+            datetime.timestamp_subsec_nanos(),
+            offset.local_minus_utc(),
+        )
+        .ok_or_else(|| TimestampRangeError {
+            value: datetime.with_timezone(&offset).to_rfc3339(),
+        })
+    }
+}
+
+fn invalid(value: &str) -> ParseTimestampError {
+    ParseTimestampError {
+        value: value.to_string(),
+    }
+}
+
+/// Parse `dd/Mon/yyyy:HH:MM:SS +zzzz`.
+fn parse_time_local(value: &str) -> Option<Timestamp> {
+    let (date, offset) = value.split_once(' ')?;
+    let (day, rest) = date.split_once('/')?;
+    let (month, rest) = rest.split_once('/')?;
+    let (year, time) = rest.split_once(':')?;
+
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let days = days_from_date(number(year, 4)?, month, number(day, 2)?)?;
+    let time = parse_time(time)?;
+    let offset = parse_offset(offset)?;
+
+    Timestamp::new(days * SECONDS_PER_DAY + time - i64::from(offset), 0, offset)
+}
+
+/// Parse `yyyy-mm-ddTHH:MM:SS[.fff][Z|+hh:mm]`.
+fn parse_iso8601(value: &str) -> Option<Timestamp> {
+    let (date, rest) = value.split_once('T')?;
+    let mut parts = date.split('-');
+    let year = number(parts.next()?, 4)?;
+    let month = number(parts.next()?, 2)?;
+    let day = number(parts.next()?, 2)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let days = days_from_date(year, month, day)?;
+
+    let zone_start = rest.find(['Z', '+', '-'])?;
+    let (time, zone) = rest.split_at(zone_start);
+    let (time, nanos) = match time.split_once('.') {
+        Some((time, fraction)) => (time, parse_fraction(fraction)?),
+        None => (time, 0),
+    };
+    let time = parse_time(time)?;
+    let offset = if zone == "Z" { 0 } else { parse_offset(zone)? };
+
+    Timestamp::new(
+        days * SECONDS_PER_DAY + time - i64::from(offset),
+        nanos,
+        offset,
+    )
+}
+
+/// Parse `seconds.fraction`.
+fn parse_msec(value: &str) -> Option<Timestamp> {
+    let (seconds, fraction) = match value.split_once('.') {
+        Some((seconds, fraction)) => (seconds, Some(fraction)),
+        None => (value, None),
+    };
+    if seconds.is_empty() || !seconds.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = match fraction {
+        Some(fraction) => parse_fraction(fraction)?,
+        None => 0,
+    };
+    Timestamp::new(seconds.parse().ok()?, nanos, 0)
+}
+
+/// Parse `HH:MM:SS` into seconds since midnight.
+fn parse_time(time: &str) -> Option<i64> {
+    let mut parts = time.split(':');
+    let hour = number(parts.next()?, 2)?;
+    let minute = number(parts.next()?, 2)?;
+    let second = number(parts.next()?, 2)?;
+    if parts.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(i64::from(hour * 3600 + minute * 60 + second))
+}
+
+/// Parse a UTC offset such as `+0100` or `-05:30` into seconds.
+fn parse_offset(offset: &str) -> Option<i32> {
+    let sign = match offset.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = offset[1..].replacen(':', "", 1);
+    let hours = number(digits.get(..2)?, 2)?;
+    let minutes = number(digits.get(2..)?, 2)?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60) as i32)
+}
+
+/// Parse the digits after a decimal point into nanoseconds.
+fn parse_fraction(fraction: &str) -> Option<u32> {
+    if fraction.is_empty() || fraction.len() > 9 {
+        return None;
+    }
+    let value = number(fraction, fraction.len())?;
+    Some(value * 10u32.pow(9 - fraction.len() as u32))
+}
+
+/// Parse a number with exactly `len` ASCII digits.
+fn number(s: &str, len: usize) -> Option<u32> {
+    (s.len() == len && s.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| s.parse().ok())
+        .flatten()
+}
+
+/// Get the number of days since the Unix epoch of a date, validating it.
+fn days_from_date(year: u32, month: u32, day: u32) -> Option<i64> {
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(i64::from(year), month, day))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Convert a proleptic Gregorian date to days since the Unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Convert days since the Unix epoch to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let local = Timestamp::parse_time_local("29/Feb/2024:23:59:59 -0530").unwrap();
+        assert_eq!(local.unix_timestamp(), 1709270999);
+        assert_eq!(local.offset_seconds(), -19800);
+        assert_eq!(local.to_string(), "2024-02-29T23:59:59-05:30");
+
+        let iso = Timestamp::parse_iso8601("2024-03-01T05:29:59.250Z").unwrap();
+        assert_eq!(iso.unix_millis(), 1709270999250);
+        assert_eq!(iso.to_string(), "2024-03-01T05:29:59.250+00:00");
+        assert!(iso > local);
+
+        let msec = Timestamp::parse_msec("1709270999.250").unwrap();
+        assert_eq!(msec, iso);
+        assert_eq!(
+            Timestamp::parse_msec("0").unwrap().to_string(),
+            "1970-01-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let time = Timestamp::parse_msec("1383917958.750").unwrap();
+        let later = time.checked_add(Duration::from_millis(1500)).unwrap();
+        assert_eq!(later.unix_millis(), 1383917960250);
+        assert_eq!(
+            later.checked_sub(Duration::from_millis(1500)).unwrap(),
+            time
+        );
+        assert_eq!(
+            time.checked_sub(Duration::from_millis(800))
+                .unwrap()
+                .unix_millis(),
+            1383917957950
+        );
+        assert_eq!(time.checked_add(Duration::MAX), None);
+        assert_eq!(time.checked_sub(Duration::from_secs(1 << 40)), None);
+    }
+
+    #[test]
+    fn test_invalid_timestamps() {
+        for value in [
+            "",
+            "-",
+            "30/Feb/2024:00:00:00 +0000",
+            "08/Nov/2013:24:00:00 +0000",
+            "08/nov/2013:13:39:18 +0000",
+            "08/Nov/2013:13:39:18",
+            "2013-11-08 13:39:18+00:00",
+            "2013-11-08T13:39:18",
+            "1383917958.",
+            "1383917958.1234567890",
+            "99999999999999999999",
+            "253402300800",
+        ] {
+            assert!(value.parse::<Timestamp>().is_err(), "{:?}", value);
+        }
+        assert_eq!(
+            Timestamp::parse_time_local("now").unwrap_err().to_string(),
+            "invalid timestamp 'now'"
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_conversions() {
+        use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+
+        let time = Timestamp::parse_iso8601("2013-11-08T14:39:18.500+01:00").unwrap();
+        let datetime = DateTime::<FixedOffset>::from(time);
+        assert_eq!(datetime.to_rfc3339(), "2013-11-08T14:39:18.500+01:00");
+        let back = Timestamp::try_from(datetime).unwrap();
+        assert_eq!(back, time);
+        assert_eq!(back.offset_seconds(), 3600);
+
+        let future = Utc.with_ymd_and_hms(10000, 1, 1, 0, 0, 0).unwrap();
+        assert!(Timestamp::try_from(future).is_err());
+
+        let leap_second = NaiveDate::from_ymd_opt(2016, 12, 31)
+            .unwrap()
+            .and_hms_milli_opt(23, 59, 59, 1500)
+            .unwrap()
+            .and_utc();
+        assert_eq!(
+            Timestamp::try_from(leap_second).unwrap_err().to_string(),
+            "date-time '2016-12-31T23:59:60.500+00:00' is out of range for a timestamp"
+        );
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        for days in [-719_468, -1, 0, 1, 59, 365, 11_016, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
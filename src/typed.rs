@@ -0,0 +1,240 @@
+//! Parsing log lines directly into typed structs.
+//!
+//! A type that implements [`NginxLog`] knows its log format and which variable
+//! each of its fields is read from. It is usually derived with
+//! `#[derive(NginxLog)]` from the `derive` feature, which checks at compile
+//! time that every field has a `$variable` in the format:
+//!
+//! ```rust
+//! # #[cfg(feature = "derive")]
+//! # {
+//! use rsnx::NginxLog;
+//!
+//! #[derive(NginxLog)]
+//! #[nginx(format = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent"#)]
+//! struct Hit {
+//!     remote_addr: String,
+//!     status: u16,
+//!     #[nginx(variable = "body_bytes_sent")]
+//!     bytes: Option<u64>,
+//! }
+//!
+//! let hit = Hit::parse(r#"10.0.0.1 [08/Nov/2013:13:39:18 +0000] "GET / HTTP/1.1" 200 -"#)?;
+//! assert_eq!(hit.status, 200);
+//! assert_eq!(hit.bytes, None);
+//! # }
+//! # Ok::<(), rsnx::Error>(())
+//! ```
+
+use crate::entry;
+use crate::error::{Error, Result};
+use crate::parser::Parser;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// A type that is parsed from log lines of a known format.
+///
+/// Implementations are normally generated by `#[derive(NginxLog)]`.
+pub trait NginxLog: Sized + 'static {
+    /// The log format lines are parsed with.
+    const FORMAT: &'static str;
+
+    /// The variables the type is built from, in the order
+    /// [`NginxLog::from_values`] expects them.
+    const VARIABLES: &'static [&'static str];
+
+    /// Build a value from the values of [`NginxLog::VARIABLES`], in the same
+    /// order. A variable without a value is `None`.
+    fn from_values(values: &[Option<&str>]) -> Result<Self>;
+
+    /// Get the parser shared by all values of this type.
+    ///
+    /// Returns an error if [`NginxLog::FORMAT`] doesn't compile.
+    fn log_parser() -> Result<&'static LogParser<Self>>;
+
+    /// Parse a log line.
+    fn parse(line: &str) -> Result<Self> {
+        Self::log_parser()?.parse(line)
+    }
+}
+
+/// A parser that produces values of an [`NginxLog`] type.
+///
+/// The position of each variable in the parser's schema is looked up once, so
+/// parsing a line reads the values straight from the matched line.
+pub struct LogParser<T> {
+    parser: Parser,
+    /// The schema position of each of `T::VARIABLES`.
+    indices: Vec<usize>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: NginxLog> LogParser<T> {
+    /// Create a parser for [`NginxLog::FORMAT`].
+    ///
+    /// Like [`Parser::new`], the parser doesn't interpret escape sequences; use
+    /// [`LogParser::with_parser`] to set an escape mode.
+    pub fn new() -> Result<Self> {
+        Self::with_parser(Parser::new(T::FORMAT)?)
+    }
+
+    /// Create a typed parser from a configured parser, such as one with a
+    /// [`VariableRegistry`](crate::VariableRegistry) or an escape mode.
+    ///
+    /// Returns an error if the parser's format lacks any of [`NginxLog::VARIABLES`].
+    pub fn with_parser(parser: Parser) -> Result<Self> {
+        let indices = T::VARIABLES
+            .iter()
+            .map(|name| {
+                parser
+                    .schema()
+                    .index_of(name)
+                    .ok_or_else(|| Error::field_not_found(*name))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            parser,
+            indices,
+            marker: PhantomData,
+        })
+    }
+
+    /// Get the underlying parser.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Parse a log line.
+    pub fn parse(&self, line: &str) -> Result<T> {
+        let entry = self.parser.parse_ref(line)?;
+        let values: Vec<Option<&str>> = self
+            .indices
+            .iter()
+            .map(|&index| entry.field_by_index(index).ok())
+            .collect();
+        T::from_values(&values)
+    }
+}
+
+impl<T> fmt::Debug for LogParser<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogParser")
+            .field("parser", &self.parser)
+            .field("indices", &self.indices)
+            .finish()
+    }
+}
+
+/// Parse the value of a required field.
+///
+/// As with [`Entry::get`](crate::Entry::get), `-` counts as absent.
+#[doc(hidden)]
+pub fn required<T>(value: Option<&str>, name: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    optional(value, name)?.ok_or_else(|| Error::field_not_found(name))
+}
+
+/// Parse the value of an `Option` field, which is `None` if absent or `-`.
+#[doc(hidden)]
+pub fn optional<T>(value: Option<&str>, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    entry::parse_value(name, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    #[derive(Debug, PartialEq)]
+    struct Hit {
+        status: u16,
+        bytes: Option<u64>,
+    }
+
+    impl NginxLog for Hit {
+        const FORMAT: &'static str = "$remote_addr $status $body_bytes_sent";
+        const VARIABLES: &'static [&'static str] = &["status", "body_bytes_sent"];
+
+        fn from_values(values: &[Option<&str>]) -> Result<Self> {
+            Ok(Self {
+                status: required(values[0], "status")?,
+                bytes: optional(values[1], "body_bytes_sent")?,
+            })
+        }
+
+        fn log_parser() -> Result<&'static LogParser<Self>> {
+            static PARSER: OnceLock<LogParser<Hit>> = OnceLock::new();
+            if let Some(parser) = PARSER.get() {
+                return Ok(parser);
+            }
+            let parser = LogParser::new()?;
+            Ok(PARSER.get_or_init(|| parser))
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Hit::parse("10.0.0.1 200 612").unwrap(),
+            Hit {
+                status: 200,
+                bytes: Some(612)
+            }
+        );
+        assert_eq!(Hit::parse("10.0.0.1 304 -").unwrap().bytes, None);
+        assert!(matches!(
+            Hit::parse("10.0.0.1 - 0"),
+            Err(Error::FieldNotFound { .. })
+        ));
+        assert!(matches!(
+            Hit::parse("10.0.0.1 200 lots"),
+            Err(Error::FieldParseError { .. })
+        ));
+    }
+
+    struct Broken;
+
+    impl NginxLog for Broken {
+        const FORMAT: &'static str = "$status $status";
+        const VARIABLES: &'static [&'static str] = &["status"];
+
+        fn from_values(_: &[Option<&str>]) -> Result<Self> {
+            Ok(Self)
+        }
+
+        fn log_parser() -> Result<&'static LogParser<Self>> {
+            static PARSER: OnceLock<LogParser<Broken>> = OnceLock::new();
+            if let Some(parser) = PARSER.get() {
+                return Ok(parser);
+            }
+            let parser = LogParser::new()?;
+            Ok(PARSER.get_or_init(|| parser))
+        }
+    }
+
+    #[test]
+    fn test_invalid_format() {
+        assert!(matches!(
+            Broken::parse("200 200"),
+            Err(Error::InvalidFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_variable() {
+        let parser = Parser::new("$remote_addr $status").unwrap();
+        assert!(matches!(
+            LogParser::<Hit>::with_parser(parser),
+            Err(Error::FieldNotFound { .. })
+        ));
+    }
+}
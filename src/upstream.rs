@@ -0,0 +1,287 @@
+//! Parsing the lists nginx logs for `$upstream_*` variables.
+//!
+//! When nginx tries more than one server for a request, each upstream variable
+//! holds one value per attempt, separated by `, `. When the request is passed
+//! to another upstream group, such as after an internal redirect, the values of
+//! each group are separated by ` : `:
+//!
+//! ```text
+//! $upstream_addr           10.0.0.1:80, 10.0.0.2:80 : 10.0.0.3:80
+//! $upstream_status         502, 200 : 200
+//! $upstream_response_time  0.010, 0.502 : 0.003
+//! ```
+
+use crate::entry;
+use crate::error::{Error, Result};
+use std::str::FromStr;
+
+/// The upstream variables that [`Upstream`] collects, in the order they are read.
+pub const UPSTREAM_FIELDS: [&str; 8] = [
+    "upstream_addr",
+    "upstream_status",
+    "upstream_response_time",
+    "upstream_connect_time",
+    "upstream_header_time",
+    "upstream_response_length",
+    "upstream_bytes_received",
+    "upstream_bytes_sent",
+];
+
+/// One attempt to pass a request to an upstream server.
+///
+/// Values nginx logged as `-`, or whose variable isn't in the log format, are
+/// `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpstreamAttempt {
+    /// The index of the upstream group the attempt belongs to, counted from 0.
+    pub group: usize,
+    /// The server address, from `$upstream_addr`.
+    pub addr: Option<String>,
+    /// The response status, from `$upstream_status`.
+    pub status: Option<u16>,
+    /// The time taken to receive the response in seconds, from `$upstream_response_time`.
+    pub response_time: Option<f64>,
+    /// The time taken to connect in seconds, from `$upstream_connect_time`.
+    pub connect_time: Option<f64>,
+    /// The time taken to receive the response header in seconds, from
+    /// `$upstream_header_time`.
+    pub header_time: Option<f64>,
+    /// The length of the response body, from `$upstream_response_length`.
+    pub response_length: Option<u64>,
+    /// The number of bytes received from the server, from `$upstream_bytes_received`.
+    pub bytes_received: Option<u64>,
+    /// The number of bytes sent to the server, from `$upstream_bytes_sent`.
+    pub bytes_sent: Option<u64>,
+}
+
+impl UpstreamAttempt {
+    /// Set the value of an upstream variable for this attempt.
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = Some(value);
+        match name {
+            "upstream_addr" => self.addr = entry::parse_value(name, value)?,
+            "upstream_status" => self.status = entry::parse_value(name, value)?,
+            "upstream_response_time" => self.response_time = entry::parse_value(name, value)?,
+            "upstream_connect_time" => self.connect_time = entry::parse_value(name, value)?,
+            "upstream_header_time" => self.header_time = entry::parse_value(name, value)?,
+            "upstream_response_length" => self.response_length = entry::parse_value(name, value)?,
+            "upstream_bytes_received" => self.bytes_received = entry::parse_value(name, value)?,
+            "upstream_bytes_sent" => self.bytes_sent = entry::parse_value(name, value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The upstream attempts of a request, with aggregate helpers.
+///
+/// # Example
+///
+/// ```rust
+/// # use rsnx::Parser;
+/// # use rsnx::parser::StringParser;
+/// let parser = Parser::new(r#""$upstream_addr" "$upstream_status" "$upstream_response_time""#)?;
+/// let entry = parser.parse_string(
+///     r#""10.0.0.1:80, 10.0.0.2:80 : 10.0.0.3:80" "502, 200 : 200" "0.010, 0.502 : 0.003""#,
+/// )?;
+///
+/// let upstream = entry.upstream()?;
+/// assert_eq!(upstream.len(), 3);
+/// assert_eq!(upstream.group_count(), 2);
+/// assert_eq!(upstream.attempts()[0].status, Some(502));
+/// assert_eq!(upstream.last().unwrap().addr.as_deref(), Some("10.0.0.3:80"));
+/// assert!((upstream.total_response_time() - 0.515).abs() < 1e-9);
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Upstream {
+    attempts: Vec<UpstreamAttempt>,
+}
+
+impl Upstream {
+    /// Collect the attempts from the upstream variables of an entry.
+    ///
+    /// `lookup` returns the value of a variable, or `None` if the entry doesn't
+    /// have it. Returns an error if none of [`UPSTREAM_FIELDS`] are present or a
+    /// value can't be parsed.
+    pub(crate) fn from_fields<'a>(lookup: impl Fn(&str) -> Option<&'a str>) -> Result<Self> {
+        let mut attempts: Vec<UpstreamAttempt> = Vec::new();
+        let mut found = false;
+
+        for name in UPSTREAM_FIELDS {
+            let Some(value) = lookup(name) else {
+                continue;
+            };
+            found = true;
+            // A lone `-` means the request wasn't passed upstream at all
+            if value == "-" {
+                continue;
+            }
+
+            for (i, (group, item)) in split_list(value).enumerate() {
+                if attempts.len() <= i {
+                    attempts.push(UpstreamAttempt {
+                        group,
+                        ..UpstreamAttempt::default()
+                    });
+                }
+                attempts[i].set(name, item)?;
+            }
+        }
+
+        if !found {
+            return Err(Error::field_not_found(UPSTREAM_FIELDS.join(", ")));
+        }
+        Ok(Self { attempts })
+    }
+
+    /// Get the attempts in the order nginx made them.
+    pub fn attempts(&self) -> &[UpstreamAttempt] {
+        &self.attempts
+    }
+
+    /// Get the number of attempts.
+    pub fn len(&self) -> usize {
+        self.attempts.len()
+    }
+
+    /// Check if the request wasn't passed upstream.
+    pub fn is_empty(&self) -> bool {
+        self.attempts.is_empty()
+    }
+
+    /// Get the number of upstream groups the request was passed to.
+    pub fn group_count(&self) -> usize {
+        self.attempts.last().map_or(0, |attempt| attempt.group + 1)
+    }
+
+    /// Get the last attempt, whose response was used.
+    pub fn last(&self) -> Option<&UpstreamAttempt> {
+        self.attempts.last()
+    }
+
+    /// Get the sum of the response times of all attempts, in seconds.
+    pub fn total_response_time(&self) -> f64 {
+        self.attempts.iter().filter_map(|a| a.response_time).sum()
+    }
+
+    /// Get the sum of the connect times of all attempts, in seconds.
+    pub fn total_connect_time(&self) -> f64 {
+        self.attempts.iter().filter_map(|a| a.connect_time).sum()
+    }
+
+    /// Get the sum of the header times of all attempts, in seconds.
+    pub fn total_header_time(&self) -> f64 {
+        self.attempts.iter().filter_map(|a| a.header_time).sum()
+    }
+
+    /// Get the total number of bytes received from upstream servers.
+    pub fn total_bytes_received(&self) -> u64 {
+        self.attempts.iter().filter_map(|a| a.bytes_received).sum()
+    }
+
+    /// Get the total number of bytes sent to upstream servers.
+    pub fn total_bytes_sent(&self) -> u64 {
+        self.attempts.iter().filter_map(|a| a.bytes_sent).sum()
+    }
+}
+
+/// Split the value of an upstream variable into its items, with the index of
+/// the upstream group each item belongs to.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::upstream::split_list;
+///
+/// let items: Vec<_> = split_list("0.010, 0.502 : 0.003").collect();
+/// assert_eq!(items, [(0, "0.010"), (0, "0.502"), (1, "0.003")]);
+/// ```
+pub fn split_list(value: &str) -> impl Iterator<Item = (usize, &str)> {
+    value
+        .split(" : ")
+        .enumerate()
+        .flat_map(|(group, items)| items.split(", ").map(move |item| (group, item.trim())))
+}
+
+/// Parse each item of an upstream variable, with `-` items as `None`.
+///
+/// `name` is the variable the value came from, used in errors.
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::upstream::parse_list;
+///
+/// let times = parse_list::<f64>("upstream_response_time", "0.010, - : 0.003")?;
+/// assert_eq!(times, [Some(0.010), None, Some(0.003)]);
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+pub fn parse_list<T>(name: &str, value: &str) -> Result<Vec<Option<T>>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    split_list(value)
+        .map(|(_, item)| entry::parse_value(name, Some(item)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn upstream(fields: &[(&str, &str)]) -> Result<Upstream> {
+        let fields: HashMap<&str, &str> = fields.iter().copied().collect();
+        Upstream::from_fields(|name| fields.get(name).copied())
+    }
+
+    #[test]
+    fn test_attempts() {
+        let upstream = upstream(&[
+            ("upstream_addr", "unix:/run/app.sock, 10.0.0.2:80 : backend"),
+            ("upstream_status", "502, 200 : 504"),
+            ("upstream_connect_time", "-, 0.001 : -"),
+            ("upstream_bytes_received", "0, 1200 : 0"),
+        ])
+        .unwrap();
+
+        assert_eq!(upstream.len(), 3);
+        assert_eq!(upstream.group_count(), 2);
+        assert_eq!(
+            upstream.attempts()[0],
+            UpstreamAttempt {
+                group: 0,
+                addr: Some("unix:/run/app.sock".into()),
+                status: Some(502),
+                bytes_received: Some(0),
+                ..UpstreamAttempt::default()
+            }
+        );
+        assert_eq!(upstream.last().unwrap().group, 1);
+        assert_eq!(upstream.total_connect_time(), 0.001);
+        assert_eq!(upstream.total_bytes_received(), 1200);
+        assert_eq!(upstream.total_response_time(), 0.0);
+    }
+
+    #[test]
+    fn test_not_passed_upstream() {
+        let upstream = upstream(&[("upstream_addr", "-"), ("upstream_status", "-")]).unwrap();
+        assert!(upstream.is_empty());
+        assert_eq!(upstream.group_count(), 0);
+        assert_eq!(upstream.last(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            upstream(&[("status", "200")]),
+            Err(Error::FieldNotFound { .. })
+        ));
+        assert!(matches!(
+            upstream(&[("upstream_status", "200, ok")]),
+            Err(Error::FieldParseError { .. })
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+//! Value patterns for known nginx variables.
+
+use crate::error::{Error, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Pattern for a timing in seconds with millisecond resolution, such as `0.042`.
+const SECONDS: &str = r"\d+\.\d{3}";
+
+/// Pattern for an IPv4 or IPv6 address, or `unix:` for UNIX-domain sockets.
+const ADDRESS: &str = r"[0-9A-Fa-f.:]+|unix:";
+
+/// Patterns for variables with a fixed format, keyed by variable name.
+const BUILTIN: &[(&str, &str)] = &[
+    ("body_bytes_sent", r"\d+"),
+    ("bytes_sent", r"\d+"),
+    ("connection", r"\d+"),
+    ("connection_requests", r"\d+"),
+    ("host", r"[a-zA-Z0-9.-]+"),
+    ("msec", SECONDS),
+    ("pid", r"\d+"),
+    ("pipe", r"[p.]"),
+    ("realip_remote_addr", ADDRESS),
+    ("remote_addr", ADDRESS),
+    ("remote_port", r"\d+"),
+    ("request_id", r"[0-9a-f]{32}"),
+    ("request_length", r"\d+"),
+    ("request_method", r"[A-Z]+"),
+    ("request_time", SECONDS),
+    ("scheme", r"https?"),
+    ("server_addr", ADDRESS),
+    ("server_port", r"\d+"),
+    ("server_protocol", r"HTTP/\d(?:\.\d)?"),
+    ("status", r"\d{3}"),
+    (
+        "time_iso8601",
+        r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}[+-]\d{2}:\d{2}",
+    ),
+    (
+        "time_local",
+        r"\d{2}/[A-Z][a-z]{2}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4}",
+    ),
+];
+
+/// Patterns for upstream variables, which hold one value per upstream attempt.
+const UPSTREAM_LISTS: &[(&str, &str)] = &[
+    ("upstream_bytes_received", r"\d+"),
+    ("upstream_bytes_sent", r"\d+"),
+    ("upstream_connect_time", SECONDS),
+    ("upstream_header_time", SECONDS),
+    ("upstream_response_length", r"\d+"),
+    ("upstream_response_time", SECONDS),
+    ("upstream_status", r"\d{3}"),
+];
+
+/// A set of regex patterns describing the values of nginx variables.
+///
+/// By default a field extends up to the delimiter that follows it in the format.
+/// When a parser is built with a registry, fields for registered variables only
+/// match their pattern instead. This splits concatenated fields such as
+/// `$status$body_bytes_sent` or `$request_uri$status` correctly, and lets
+/// `$time_local` appear in a format without surrounding brackets even though it
+/// contains a space. Both [`Engine::Regex`] and [`Engine::Scanner`] split the
+/// fields the same way.
+///
+/// nginx logs unset variables as `-`, so every pattern also accepts a lone `-`.
+/// Patterns must not contain capturing groups, use `(?:...)` to group
+/// alternatives. Nor can they contain anchors such as `^` and `$` or word
+/// boundaries such as `\b`, as patterns are matched within a line and the
+/// engines would disagree on where those match.
+///
+/// [`Engine::Regex`]: crate::Engine::Regex
+/// [`Engine::Scanner`]: crate::Engine::Scanner
+///
+/// # Example
+///
+/// ```rust
+/// use rsnx::parser::StringParser;
+/// use rsnx::{Parser, VariableRegistry};
+///
+/// let mut variables = VariableRegistry::builtin();
+/// variables.register("cache_status", "HIT|MISS|BYPASS")?;
+///
+/// let parser = Parser::with_variables("$time_local $status$cache_status", &variables)?;
+/// let entry = parser.parse_string("08/Nov/2013:13:39:18 +0000 200HIT")?;
+///
+/// assert_eq!(entry.field("time_local")?, "08/Nov/2013:13:39:18 +0000");
+/// assert_eq!(entry.field("status")?, "200");
+/// assert_eq!(entry.field("cache_status")?, "HIT");
+/// # Ok::<(), rsnx::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VariableRegistry {
+    /// Map from variable name to value pattern.
+    patterns: HashMap<String, String>,
+}
+
+impl VariableRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with patterns for well-known nginx variables.
+    ///
+    /// This covers variables whose values nginx generates itself, such as
+    /// `$status`, `$request_time`, `$remote_addr`, `$time_local`, `$time_iso8601`,
+    /// `$msec` and the `$upstream_*` timings and statuses. Upstream variables
+    /// accept the `, ` and ` : ` separated lists nginx logs when a request was
+    /// passed to several servers. Variables that hold client input, such as
+    /// `$request` or `$http_user_agent`, are not included.
+    pub fn builtin() -> Self {
+        let mut patterns: HashMap<String, String> = BUILTIN
+            .iter()
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+            .collect();
+
+        for (name, item) in UPSTREAM_LISTS {
+            let item = format!("(?:{}|-)", item);
+            patterns.insert(name.to_string(), format!("{0}(?:(?:, | : ){0})*", item));
+        }
+
+        Self { patterns }
+    }
+
+    /// Register the value pattern for a variable, replacing any existing pattern.
+    ///
+    /// The variable name is given without the leading `$`. Returns
+    /// [`Error::InvalidPattern`] if the pattern isn't a valid regex, if it has a
+    /// capturing group, which would be mistaken for a field, or if it has an
+    /// anchor or word boundary.
+    pub fn register(&mut self, name: impl Into<String>, pattern: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        let pattern = pattern.into();
+        if let Err(e) = Regex::new(&pattern) {
+            return Err(Error::invalid_pattern(name, e.to_string()));
+        }
+
+        let hir = regex_syntax::parse(&pattern)
+            .map_err(|e| Error::invalid_pattern(&name, e.to_string()))?;
+        if hir.properties().explicit_captures_len() > 0 {
+            let reason = format!("'{}' has a capturing group, use (?:...) instead", pattern);
+            return Err(Error::invalid_pattern(name, reason));
+        }
+        if !hir.properties().look_set().is_empty() {
+            let reason = format!("'{}' has an anchor or word boundary", pattern);
+            return Err(Error::invalid_pattern(name, reason));
+        }
+
+        self.patterns.insert(name, pattern);
+        Ok(())
+    }
+
+    /// Get the value pattern for a variable.
+    pub fn pattern(&self, name: &str) -> Option<&str> {
+        self.patterns.get(name).map(String::as_str)
+    }
+
+    /// Check if a variable has a registered pattern.
+    pub fn contains(&self, name: &str) -> bool {
+        self.patterns.contains_key(name)
+    }
+
+    /// Get the number of registered variables.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Check if the registry has no variables.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Get the regex a field for the variable must match, accepting `-` for unset values.
+    pub(crate) fn value_regex(&self, name: &str) -> Option<String> {
+        self.pattern(name)
+            .map(|pattern| format!("(?:{})|-", pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(variables: &VariableRegistry, name: &str, value: &str) -> bool {
+        let pattern = format!("^(?:{})$", variables.value_regex(name).unwrap());
+        Regex::new(&pattern).unwrap().is_match(value)
+    }
+
+    #[test]
+    fn test_builtin_patterns_are_valid() {
+        let variables = VariableRegistry::builtin();
+        for name in variables.patterns.keys() {
+            assert!(matches(&variables, name, "-"), "{} rejects -", name);
+        }
+
+        assert!(matches(&variables, "status", "404"));
+        assert!(!matches(&variables, "status", "4044"));
+        assert!(matches(&variables, "remote_addr", "2001:db8::1"));
+        assert!(matches(
+            &variables,
+            "time_iso8601",
+            "2013-11-08T13:39:18+00:00"
+        ));
+    }
+
+    #[test]
+    fn test_upstream_lists() {
+        let variables = VariableRegistry::builtin();
+        assert!(matches(&variables, "upstream_response_time", "0.012"));
+        assert!(matches(
+            &variables,
+            "upstream_response_time",
+            "0.001, 0.002 : -"
+        ));
+        assert!(matches(&variables, "upstream_status", "502, 200"));
+        assert!(!matches(&variables, "upstream_status", "502,200"));
+    }
+
+    #[test]
+    fn test_register() {
+        let mut variables = VariableRegistry::new();
+        assert!(variables.is_empty());
+
+        variables.register("cache_status", "HIT|MISS").unwrap();
+        assert_eq!(variables.pattern("cache_status"), Some("HIT|MISS"));
+        assert!(matches(&variables, "cache_status", "MISS"));
+
+        assert!(variables.register("broken", "(").is_err());
+        assert!(!variables.contains("broken"));
+
+        assert!(variables.register("cache_status", "(HIT|MISS)").is_err());
+        assert!(variables.register("named", "(?P<x>HIT)").is_err());
+        for pattern in ["^HIT", "MISS$", r"\bHIT", r"(?m)^HIT", r"\AHIT\z"] {
+            let error = variables.register("cache_status", pattern).unwrap_err();
+            assert!(
+                matches!(&error, Error::InvalidPattern { name, .. } if name == "cache_status"),
+                "{:?}",
+                error
+            );
+        }
+        assert!(variables
+            .register("broken", "(")
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid pattern for $broken: "));
+        assert_eq!(variables.pattern("cache_status"), Some("HIT|MISS"));
+        variables.register("literal", r"\^|\$").unwrap();
+        variables.register("cache_status", "(?:HIT|MISS)").unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+//! Checks that the regex and scanner engines split lines into the same fields.
+
+use rsnx::parser::StringParser;
+use rsnx::{Engine, EscapeMode, NginxReader, Parser, VariableRegistry};
+use std::io::Cursor;
+
+/// Parse `line` with both engines and check they give the same fields, or both fail.
+fn assert_engines_agree(parser: &Parser, line: &str) {
+    let regex = parser.clone().with_engine(Engine::Regex);
+    let scanner = parser.clone().with_engine(Engine::Scanner);
+
+    match (regex.parse_string(line), scanner.parse_string(line)) {
+        (Ok(expected), Ok(actual)) => {
+            let expected: Vec<_> = expected.iter().collect();
+            let actual: Vec<_> = actual.iter().collect();
+            assert_eq!(
+                expected,
+                actual,
+                "format {:?} on {:?}",
+                parser.format(),
+                line
+            );
+        }
+        (Err(_), Err(_)) => {}
+        (expected, actual) => panic!(
+            "engines disagree on format {:?} with {:?}: {:?} vs {:?}",
+            parser.format(),
+            line,
+            expected,
+            actual
+        ),
+    }
+}
+
+#[test]
+fn test_plain_formats() {
+    let cases = [
+        (
+            r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent"#,
+            r#"127.0.0.1 - - [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" 200 612"#,
+        ),
+        ("$host$request_uri $status", "example.com/path?q=1 200"),
+        ("$host$request_uri $status", "/path?q=1 200"),
+        ("$a$b|", "left|"),
+        ("$a$b$c|", "x|"),
+        ("$a$b", "concatenated at the end"),
+        ("[$a] $b", "[x] y"),
+        ("[$a] $b", "[x y"),
+        ("", ""),
+    ];
+
+    for (format, line) in cases {
+        assert_engines_agree(&Parser::new(format).unwrap(), line);
+    }
+}
+
+#[test]
+fn test_registered_variables() {
+    let variables = VariableRegistry::builtin();
+    let cases = [
+        ("$request_uri$status $body_bytes_sent", "/index.html200 612"),
+        ("$request_uri$status $body_bytes_sent", "/index.html 612"),
+        ("$remote_addr:$remote_port $status", "10.0.0.1:8080 200"),
+        ("$remote_addr:$remote_port $status", "2001:db8::1:443 404"),
+        ("$remote_addr:$remote_port $status", "10.0.0.1 200"),
+        ("$request_uri$status", "/a/b404"),
+        ("$status$body_bytes_sent", "404-"),
+        ("$host$request_uri $status", "example.com/a 200"),
+        (
+            "$time_local $status$body_bytes_sent",
+            "08/Nov/2013:13:39:18 +0000 404153",
+        ),
+        (
+            "$time_local $status$body_bytes_sent",
+            "08/Nov/2013:13:39:18 +0000 OK12",
+        ),
+        (
+            "$upstream_addr $upstream_response_time$status",
+            "10.0.0.2:80 0.001, 0.002200",
+        ),
+    ];
+
+    for (format, line) in cases {
+        assert_engines_agree(&Parser::with_variables(format, &variables).unwrap(), line);
+    }
+}
+
+#[test]
+fn test_escaped_formats() {
+    let parser = Parser::new(r#""$http_user_agent" $a$b|"#)
+        .unwrap()
+        .with_escape(EscapeMode::Json)
+        .unwrap();
+
+    for line in [
+        r#""a \"quoted\" \\" x|"#,
+        r#""a \"quoted\" \\" \|x|"#,
+        r#""unterminated \" x|"#,
+    ] {
+        assert_engines_agree(&parser, line);
+    }
+}
+
+#[test]
+fn test_nginx_readers() {
+    let config = r#"
+    log_format main '$remote_addr - $remote_user [$time_local] "$request" $status';
+    "#;
+    let log = r#"127.0.0.1 - - [25/Dec/2013:14:30:00 +0000] "GET / HTTP/1.1" 200
+not a log line
+10.0.0.1 - bob [25/Dec/2013:14:31:00 +0000] "POST /login HTTP/1.1" 302"#;
+
+    let [regex, scanner] = [Engine::Regex, Engine::Scanner].map(|engine| {
+        NginxReader::new(Cursor::new(log), Cursor::new(config), "main")
+            .unwrap()
+            .with_engine(engine)
+            .map(|result| {
+                result
+                    .map(|entry| {
+                        entry
+                            .iter()
+                            .map(|(name, value)| format!("{name}={value}"))
+                            .collect::<Vec<_>>()
+                    })
+                    .map_err(|e| e.to_string())
+            })
+            .collect::<Vec<_>>()
+    });
+
+    assert_eq!(regex.len(), 3);
+    assert_eq!(regex, scanner);
+}
@@ -1,6 +1,7 @@
 //! Integration tests for the rsnx library.
 
-use rsnx::{Error, NginxReader, Reader};
+use rsnx::parser::StringParser;
+use rsnx::{Engine, Error, ErrorPolicy, EscapeMode, NginxReader, Parser, Reader, VariableRegistry};
 use std::f64::consts::PI;
 use std::io::Cursor;
 
@@ -305,3 +306,1288 @@ fn test_empty_lines_handling() {
     assert_eq!(entries[0].field("remote_addr").unwrap(), "127.0.0.1");
     assert_eq!(entries[1].field("remote_addr").unwrap(), "192.168.1.1");
 }
+
+#[test]
+fn test_error_policy_skips_malformed_lines() {
+    let log_data = r#"127.0.0.1 [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" 200 612
+127.0.0.1 [25/Dec/2013:14:30:01 +0000] "GET /trunc
+192.168.1.1 [25/Dec/2013:14:31:00 +0000] "POST /api/login HTTP/1.1" 201 45"#;
+
+    let format = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent"#;
+
+    let mut reader = Reader::new(Cursor::new(log_data), format)
+        .unwrap()
+        .with_error_policy(ErrorPolicy::SkipAndCollect);
+
+    let entries: Result<Vec<_>, _> = reader.by_ref().collect();
+    let entries = entries.unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].field("remote_addr").unwrap(), "192.168.1.1");
+
+    let summary = reader.error_summary();
+    assert_eq!(summary.total_lines(), 3);
+    assert_eq!(summary.rejected_count(), 1);
+    assert_eq!(summary.to_string(), "rejected 1 of 3 lines (33.33%)");
+}
+
+#[test]
+fn test_line_info_on_entries_and_errors() {
+    let log_data = r#"127.0.0.1 [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" 200 612
+bad line
+192.168.1.1 [25/Dec/2013:14:31:00 +0000] "POST /api/login HTTP/1.1" 201 45"#;
+
+    let format = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent"#;
+
+    let mut reader = Reader::new(Cursor::new(log_data), format)
+        .unwrap()
+        .with_error_policy(ErrorPolicy::SkipAndCollect);
+
+    let entries = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries[0].line_info().unwrap().line_number, Some(1));
+    assert_eq!(entries[1].line_info().unwrap().line_number, Some(3));
+    assert_eq!(entries[1].line_info().unwrap().byte_offset, 83);
+
+    let rejected = &reader.error_summary().rejected()[0];
+    let info = rejected.line_info().unwrap();
+    assert_eq!(info.line_number, Some(2));
+    assert_eq!(info.byte_offset, 74);
+}
+
+#[test]
+fn test_mismatch_diagnostics() {
+    // The nginx config gained a field the format doesn't know about
+    let log_line =
+        r#"127.0.0.1 - - [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" 200 612"#;
+    let format = r#"$remote_addr - [$time_local] "$request" $status $body_bytes_sent"#;
+
+    let parser = Parser::new(format).unwrap().with_diagnostics(true);
+    let mut reader = Reader::with_parser(Cursor::new(log_line), parser);
+
+    let error = reader.read().unwrap().unwrap_err();
+    let diagnostic = error.diagnostic().unwrap();
+
+    assert_eq!(diagnostic.segment_index(), Some(1));
+    assert_eq!(diagnostic.column(), 10);
+    assert_eq!(diagnostic.expected(), Some(" - ["));
+    assert_eq!(diagnostic.found(), " - -");
+    assert!(error
+        .to_string()
+        .ends_with(r#"expected " - [" at column 10, found " - -""#));
+}
+
+#[test]
+fn test_entry_schema_and_field_order() {
+    let log_line = r#"127.0.0.1 [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" 200 612"#;
+    let format = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent"#;
+
+    let parser = Parser::new(format).unwrap();
+    let schema = parser.schema().clone();
+    let mut reader = Reader::with_parser(Cursor::new(log_line), parser);
+    let mut entry = reader.read().unwrap().unwrap();
+
+    // Entries share the parser's schema instead of copying field names
+    assert!(std::sync::Arc::ptr_eq(entry.schema(), &schema));
+
+    let status = schema.index_of("status").unwrap();
+    assert_eq!(entry.field_by_index(status).unwrap(), "200");
+    assert!(matches!(
+        entry.field_by_index(99),
+        Err(Error::FieldNotFound { .. })
+    ));
+
+    // Fields keep format order, with fields added later at the end
+    entry.set_field("status", "404");
+    entry.set_field("custom", "value");
+    let names: Vec<_> = entry.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "remote_addr",
+            "time_local",
+            "request",
+            "status",
+            "body_bytes_sent",
+            "custom"
+        ]
+    );
+    assert_eq!(entry.field_by_index(status).unwrap(), "404");
+    assert_eq!(entry.len(), 6);
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_deprecated_fields_map() {
+    let mut entry = Parser::new("$remote_addr $status")
+        .unwrap()
+        .parse_string("127.0.0.1 200")
+        .unwrap();
+    entry.set_field("custom", "value");
+    assert_eq!(entry.fields(), entry.to_fields());
+    assert_eq!(entry.fields()["status"], "200");
+
+    // Changes to the map are written back when the guard is dropped
+    {
+        let mut fields = entry.fields_mut();
+        fields.insert("status".to_string(), "404".to_string());
+        fields.insert("added".to_string(), "1".to_string());
+        fields.remove("remote_addr");
+        fields.remove("custom");
+    }
+    let names: Vec<_> = entry.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["status", "added"]);
+    assert_eq!(entry.field("status").unwrap(), "404");
+    assert!(entry.field("remote_addr").is_err());
+}
+
+#[test]
+fn test_entry_equality_ignores_metadata() {
+    use rsnx::LineInfo;
+
+    let parser = Parser::new("$remote_addr $status").unwrap();
+    let line = "127.0.0.1 200";
+
+    let borrowed = parser.parse_ref(line).unwrap();
+    let located = parser
+        .parse_ref(line)
+        .unwrap()
+        .with_line_info(LineInfo::new(7, 120));
+    assert_eq!(borrowed, located);
+    assert_ne!(borrowed, parser.parse_ref("127.0.0.1 404").unwrap());
+
+    let owned = parser.parse_string(line).unwrap();
+    assert_eq!(owned.clone().with_line_info(LineInfo::new(7, 120)), owned);
+    assert_eq!(located.to_owned(), owned);
+}
+
+#[test]
+fn test_scanner_engine_matches_regex_engine() {
+    let cases = [
+        (
+            r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent"#,
+            r#"127.0.0.1 - - [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" 200 612"#,
+        ),
+        (
+            r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#,
+            r#"192.168.1.1 - john [25/Dec/2013:14:30:00 +0000] "POST /api/login HTTP/1.1" 201 45 "https://example.com/login" "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36""#,
+        ),
+        (
+            r#"[$level] $timestamp $user "$params" $duration"#,
+            r#"[INFO] 2023-12-25T14:30:00Z user@example.com "action=login&result=success" 1.234ms"#,
+        ),
+        (
+            r#"$host$request_uri $method $status"#,
+            r#"example.com/api/users?id=123 GET 200"#,
+        ),
+        (
+            r#"$remote_addr [$time_local] "$request" $status"#,
+            r#"127.0.0.1 [25/Dec/2013:14:30:00 +0000] "GET /index.html HTTP/1.1" not_a_number"#,
+        ),
+        (
+            r#"$remote_addr [$time_local] "$request""#,
+            "This is not a valid log line",
+        ),
+        (
+            "$remote_addr ${request_time}ms ${host}${request_uri}",
+            "10.0.0.1 0.042ms example.com/index.html",
+        ),
+        ("$a$b", "concatenated at the end"),
+        ("", ""),
+    ];
+
+    for (format, line) in cases {
+        let regex = Parser::new(format).unwrap();
+        let scanner = Parser::new(format).unwrap().with_engine(Engine::Scanner);
+
+        match (regex.parse_string(line), scanner.parse_string(line)) {
+            (Ok(expected), Ok(actual)) => {
+                let expected: Vec<_> = expected.iter().collect();
+                let actual: Vec<_> = actual.iter().collect();
+                assert_eq!(expected, actual, "format {:?}", format);
+            }
+            (Err(_), Err(_)) => {}
+            (expected, actual) => panic!(
+                "engines disagree on {:?}: {:?} vs {:?}",
+                format, expected, actual
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_braced_variables() {
+    let parser = Parser::new(r#"$remote_addr ${request_time}ms "${upstream_addr}x""#).unwrap();
+    assert_eq!(
+        parser.field_names(),
+        ["remote_addr", "request_time", "upstream_addr"]
+    );
+
+    let entry = parser
+        .parse_string(r#"10.0.0.1 0.042ms "127.0.0.1:8080x""#)
+        .unwrap();
+    assert_eq!(entry.field("request_time").unwrap(), "0.042");
+    assert_eq!(entry.field("upstream_addr").unwrap(), "127.0.0.1:8080");
+
+    let config = r#"
+    log_format timing '$remote_addr ${request_time}ms "${upstream_addr}x"';
+    "#;
+    let mut reader = NginxReader::new(
+        Cursor::new(r#"10.0.0.1 1.5ms "-x""#),
+        Cursor::new(config),
+        "timing",
+    )
+    .unwrap();
+
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(entry.field("request_time").unwrap(), "1.5");
+    assert_eq!(entry.field("upstream_addr").unwrap(), "-");
+
+    let parser = Parser::new("(${host}${request_uri}) $status").unwrap();
+    let entry = parser.parse_string("(example.com/a?b=1) 200").unwrap();
+    assert_eq!(entry.field("host").unwrap(), "example.com");
+    assert_eq!(entry.field("request_uri").unwrap(), "/a?b=1");
+    assert_eq!(entry.field("status").unwrap(), "200");
+}
+
+#[test]
+fn test_variable_patterns() {
+    let format =
+        r#"$remote_addr $time_local "$request" $status$body_bytes_sent $upstream_response_time"#;
+    let line = r#"10.0.0.1 08/Nov/2013:13:39:18 +0000 "GET / HTTP/1.1" 404153 0.002, 0.010"#;
+
+    // Without patterns, the fields split at the first space
+    assert!(Parser::new(format).unwrap().parse_string(line).is_err());
+
+    let variables = VariableRegistry::builtin();
+    for engine in [Engine::Regex, Engine::Scanner] {
+        let parser = Parser::with_variables(format, &variables)
+            .unwrap()
+            .with_engine(engine);
+        let entry = parser.parse_string(line).unwrap();
+
+        assert_eq!(
+            entry.field("time_local").unwrap(),
+            "08/Nov/2013:13:39:18 +0000"
+        );
+        assert_eq!(entry.field("status").unwrap(), "404");
+        assert_eq!(entry.field("body_bytes_sent").unwrap(), "153");
+        assert_eq!(
+            entry.field("upstream_response_time").unwrap(),
+            "0.002, 0.010"
+        );
+
+        let line = r#"10.0.0.1 08/Nov/2013:13:39:18 +0000 "GET / HTTP/1.1" OK153 -"#;
+        assert!(matches!(
+            parser.parse_string(line),
+            Err(Error::LineFormatMismatch { .. })
+        ));
+    }
+
+    // Groups in user patterns would shift the fields after them
+    let mut variables = VariableRegistry::new();
+    let result = variables.register("upstream_cache_status", "(HIT|MISS)");
+    assert!(matches!(result, Err(Error::InvalidPattern { .. })));
+
+    variables
+        .register("upstream_cache_status", "(?:HIT|MISS)")
+        .unwrap();
+    for engine in [Engine::Regex, Engine::Scanner] {
+        let parser =
+            Parser::with_variables("$upstream_cache_status $remote_addr $status", &variables)
+                .unwrap()
+                .with_engine(engine);
+        let entry = parser.parse_string("HIT 10.0.0.1 200").unwrap();
+
+        assert_eq!(entry.field("upstream_cache_status").unwrap(), "HIT");
+        assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.1");
+        assert_eq!(entry.field("status").unwrap(), "200");
+    }
+}
+
+#[test]
+fn test_concatenated_variable_patterns() {
+    let variables = VariableRegistry::builtin();
+    for engine in [Engine::Regex, Engine::Scanner] {
+        let parser = Parser::with_variables("$request_uri$status $body_bytes_sent", &variables)
+            .unwrap()
+            .with_engine(engine);
+        let entry = parser.parse_string("/index.html200 612").unwrap();
+        assert_eq!(entry.field("request_uri").unwrap(), "/index.html");
+        assert_eq!(entry.field("status").unwrap(), "200");
+        assert_eq!(entry.field("body_bytes_sent").unwrap(), "612");
+
+        let parser = Parser::with_variables("$remote_addr:$remote_port $status", &variables)
+            .unwrap()
+            .with_engine(engine);
+        let entry = parser.parse_string("10.0.0.1:8080 200").unwrap();
+        assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.1");
+        assert_eq!(entry.field("remote_port").unwrap(), "8080");
+
+        let entry = parser.parse_string("2001:db8::1:443 404").unwrap();
+        assert_eq!(entry.field("remote_addr").unwrap(), "2001:db8::1");
+        assert_eq!(entry.field("remote_port").unwrap(), "443");
+
+        assert!(parser.parse_string("10.0.0.1 200").is_err());
+    }
+}
+
+#[test]
+fn test_escaped_values() {
+    let config = r#"
+    log_format main '$remote_addr "$request" "$http_user_agent"';
+    log_format json escape=json '{"addr":"$remote_addr","agent":"$http_user_agent"}';
+    "#;
+
+    let log = r#"10.0.0.1 "GET /?q=\x22x\x22 HTTP/1.1" "curl \x5C o/""#;
+    let mut reader = NginxReader::new(Cursor::new(log), Cursor::new(config), "main")
+        .unwrap()
+        .with_unescape(true);
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(entry.field("request").unwrap(), r#"GET /?q="x" HTTP/1.1"#);
+    assert_eq!(entry.field("http_user_agent").unwrap(), r"curl \ o/");
+
+    let log = r#"{"addr":"10.0.0.1","agent":"say \"hi\", \\o/"}"#;
+    let mut reader = NginxReader::new(Cursor::new(log), Cursor::new(config), "json").unwrap();
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(
+        entry.field("http_user_agent").unwrap(),
+        r#"say \"hi\", \\o/"#
+    );
+
+    for engine in [Engine::Regex, Engine::Scanner] {
+        let parser = Parser::new(r#"{"addr":"$remote_addr","agent":"$http_user_agent"}"#)
+            .unwrap()
+            .with_engine(engine)
+            .with_escape(EscapeMode::Json)
+            .unwrap()
+            .with_unescape(true);
+        assert_eq!(parser.escape(), EscapeMode::Json);
+
+        let entry = parser.parse_ref(log).unwrap();
+        assert_eq!(entry.field("http_user_agent").unwrap(), r#"say "hi", \o/"#);
+        assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.1");
+    }
+}
+
+#[test]
+fn test_json_log_format() {
+    let config = r#"
+    log_format json escape=json '{"ts":"$time_iso8601","client":{"ip":"$remote_addr"},'
+                                '"code":$status,"bytes":$body_bytes_sent,'
+                                '"timing":"$request_time/$upstream_response_time",'
+                                '"req":"$request"}';
+    "#;
+    let log = r#"{"ts":"2013-11-08T13:39:18+00:00","client":{"ip":"10.0.0.1"},"code":200,"bytes":612,"timing":"0.010/0.008","req":"GET /?q=\"a\" HTTP/1.1"}
+{ "req": "GET / HTTP/1.1", "code": 404, "bytes": , "client": { "ip": "10.0.0.2", "port": 5100 }, "timing": "0.001/", "new_key": [1, 2] }
+{"code":200"#;
+
+    let mut reader = NginxReader::new(Cursor::new(log), Cursor::new(config), "json")
+        .unwrap()
+        .with_unescape(true);
+    assert_eq!(reader.reader().parser().engine(), Engine::Json);
+
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(
+        entry.field("time_iso8601").unwrap(),
+        "2013-11-08T13:39:18+00:00"
+    );
+    assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.1");
+    assert_eq!(entry.int_field("status").unwrap(), 200);
+    assert_eq!(entry.field("request_time").unwrap(), "0.010");
+    assert_eq!(entry.field("upstream_response_time").unwrap(), "0.008");
+    assert_eq!(entry.field("request").unwrap(), r#"GET /?q="a" HTTP/1.1"#);
+
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.2");
+    assert_eq!(entry.field("status").unwrap(), "404");
+    assert_eq!(entry.field("body_bytes_sent").unwrap(), "");
+    assert_eq!(entry.field("upstream_response_time").unwrap(), "");
+    assert!(entry.field("time_iso8601").is_err());
+
+    assert!(matches!(
+        reader.read(),
+        Some(Err(Error::LineFormatMismatch { .. }))
+    ));
+    assert!(reader.read().is_none());
+}
+
+#[test]
+fn test_json_unescape() {
+    let format = r#"{"req":"$request","ua":"$http_user_agent"}"#;
+    let line = r#"{"req":"GET /?q=\"a\" HTTP/1.1","ua":"curl\u002F8.0 \x22"}"#;
+
+    // Without an escape mode, values are decoded as JSON strings
+    let parser = Parser::new(format)
+        .unwrap()
+        .with_engine(Engine::Json)
+        .with_unescape(true);
+    assert_eq!(parser.escape(), EscapeMode::None);
+    let entry = parser.parse_string(line).unwrap();
+    assert_eq!(entry.field("request").unwrap(), r#"GET /?q="a" HTTP/1.1"#);
+    assert_eq!(entry.field("http_user_agent").unwrap(), r"curl/8.0 \x22");
+
+    // With escape=default, nginx's \xHH escapes are decoded instead
+    let entry = parser
+        .with_escape(EscapeMode::Default)
+        .unwrap()
+        .parse_string(line)
+        .unwrap();
+    assert_eq!(
+        entry.field("http_user_agent").unwrap(),
+        r#"curl\u002F8.0 ""#
+    );
+
+    let parser = Parser::new(format).unwrap().with_engine(Engine::Json);
+    let entry = parser.parse_string(line).unwrap();
+    assert_eq!(entry.field("request").unwrap(), r#"GET /?q=\"a\" HTTP/1.1"#);
+}
+
+#[test]
+fn test_request_fields() {
+    let format = r#"$remote_addr "$request" $status"#;
+    let log = r#"10.0.0.1 "GET /search?q=rust HTTP/1.1" 200
+10.0.0.2 "-" 400
+10.0.0.3 "\x16\x03\x01\x00\xA5\x01" 400"#;
+
+    let entries = Reader::new(Cursor::new(log), format)
+        .unwrap()
+        .collect_all()
+        .unwrap();
+
+    let entry = &entries[0];
+    assert_eq!(entry.field("request_method").unwrap(), "GET");
+    assert_eq!(entry.field("request_uri").unwrap(), "/search?q=rust");
+    assert_eq!(entry.field("uri_path").unwrap(), "/search");
+    assert_eq!(entry.field("query_string").unwrap(), "q=rust");
+    assert_eq!(entry.field("server_protocol").unwrap(), "HTTP/1.1");
+    assert_eq!(entry.request().unwrap().method(), "GET");
+
+    for entry in &entries[1..] {
+        assert!(entry.request().is_none());
+        assert!(matches!(
+            entry.field("request_method"),
+            Err(Error::FieldNotFound { .. })
+        ));
+    }
+
+    // Logged variables take precedence over derived ones
+    let parser = Parser::new(r#""$request" $request_uri"#).unwrap();
+    let entry = parser
+        .parse_ref(r#""GET /a?b HTTP/1.1" /original"#)
+        .unwrap();
+    assert_eq!(entry.field("request_uri").unwrap(), "/original");
+    assert_eq!(entry.field("query_string").unwrap(), "b");
+}
+
+#[test]
+fn test_timestamps() {
+    let format = r#"$remote_addr [$time_local] "$request""#;
+    let log = r#"10.0.0.1 [08/Nov/2013:14:39:18 +0100] "GET / HTTP/1.1"
+10.0.0.2 [08/Nov/2013:13:39:17 +0000] "GET / HTTP/1.1"
+10.0.0.3 [08/Nov/2013:08:39:19 -0500] "GET / HTTP/1.1""#;
+
+    let mut entries = Reader::new(Cursor::new(log), format)
+        .unwrap()
+        .collect_all()
+        .unwrap();
+    entries.sort_by_key(|entry| entry.timestamp().unwrap());
+
+    let order: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.field("remote_addr").unwrap())
+        .collect();
+    assert_eq!(order, ["10.0.0.2", "10.0.0.1", "10.0.0.3"]);
+
+    let timestamp = entries[1].timestamp().unwrap();
+    assert_eq!(timestamp.unix_timestamp(), 1383917958);
+    assert_eq!(timestamp.offset_seconds(), 3600);
+    assert_eq!(timestamp.to_string(), "2013-11-08T14:39:18+01:00");
+
+    let parser = Parser::new("$msec $status").unwrap();
+    let entry = parser.parse_string("1383917958.123 200").unwrap();
+    assert_eq!(entry.timestamp().unwrap().unix_millis(), 1383917958123);
+
+    let parser = Parser::new("[$time_local] $status").unwrap();
+    let entry = parser.parse_string("[yesterday] 200").unwrap();
+    assert!(matches!(
+        entry.timestamp(),
+        Err(Error::FieldParseError { .. })
+    ));
+
+    let entry = Parser::new("$status").unwrap().parse_string("200").unwrap();
+    assert!(matches!(
+        entry.timestamp(),
+        Err(Error::FieldNotFound { .. })
+    ));
+}
+
+#[test]
+fn test_typed_field_access() {
+    let format = r#"$remote_addr - $remote_user "$http_referer" $status $request_time"#;
+    let log = r#"192.168.1.1 - alice "-" 200 0.125
+192.168.1.2 - - "https://example.com/" 502 -"#;
+
+    let entries = Reader::new(Cursor::new(log), format)
+        .unwrap()
+        .collect_all()
+        .unwrap();
+
+    let entry = &entries[0];
+    let addr: std::net::IpAddr = entry.get("remote_addr").unwrap();
+    assert_eq!(addr.to_string(), "192.168.1.1");
+    assert_eq!(entry.get::<u16>("status").unwrap(), 200);
+    assert_eq!(entry.get::<f64>("request_time").unwrap(), 0.125);
+    assert_eq!(
+        entry.get_opt::<String>("remote_user").unwrap().as_deref(),
+        Some("alice")
+    );
+    assert_eq!(entry.get_opt::<String>("http_referer").unwrap(), None);
+    assert!(matches!(
+        entry.get::<String>("http_referer"),
+        Err(Error::FieldNotFound { .. })
+    ));
+
+    let entry = &entries[1];
+    assert_eq!(entry.get_opt::<String>("remote_user").unwrap(), None);
+    assert_eq!(entry.get_opt::<f64>("request_time").unwrap(), None);
+    assert_eq!(entry.get_opt::<u16>("missing").unwrap(), None);
+    assert!(matches!(
+        entry.get_opt::<u8>("status"),
+        Err(Error::FieldParseError { .. })
+    ));
+
+    // Borrowed entries support the same accessors
+    let parser = Parser::new("$status $body_bytes_sent").unwrap();
+    let entry = parser.parse_ref("404 -").unwrap();
+    assert_eq!(entry.get::<u16>("status").unwrap(), 404);
+    assert_eq!(entry.get_opt::<u64>("body_bytes_sent").unwrap(), None);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_deserialize_entries() {
+    use rsnx::Timestamp;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct LogLine {
+        remote_addr: std::net::IpAddr,
+        #[serde(rename = "time_local")]
+        time: Timestamp,
+        #[serde(rename = "uri_path")]
+        path: String,
+        status: u16,
+        body_bytes_sent: u64,
+        http_referer: Option<String>,
+        request_time: f64,
+    }
+
+    let format = r#"$remote_addr [$time_local] "$request" $status $body_bytes_sent "$http_referer" $request_time"#;
+    let log = r#"10.0.0.1 [08/Nov/2013:13:39:18 +0000] "GET /a?x=1 HTTP/1.1" 200 612 "-" 0.010
+10.0.0.2 [08/Nov/2013:13:39:19 +0000] "GET /b HTTP/1.1" 200 0 "https://example.com/" 0.020
+10.0.0.3 [08/Nov/2013:13:39:20 +0000] "GET /c HTTP/1.1" 200 - "-" 0.030"#;
+
+    let mut lines = Reader::new(Cursor::new(log), format)
+        .unwrap()
+        .deserialize::<LogLine>();
+
+    let line = lines.next().unwrap().unwrap();
+    assert_eq!(line.remote_addr.to_string(), "10.0.0.1");
+    assert_eq!(line.time.unix_timestamp(), 1383917958);
+    assert_eq!(line.path, "/a");
+    assert_eq!(line.status, 200);
+    assert_eq!(line.body_bytes_sent, 612);
+    assert_eq!(line.http_referer, None);
+    assert_eq!(line.request_time, 0.010);
+
+    let line = lines.next().unwrap().unwrap();
+    assert_eq!(line.http_referer.as_deref(), Some("https://example.com/"));
+
+    // "-" is only absent for optional fields
+    assert!(matches!(
+        lines.next(),
+        Some(Err(Error::FieldParseError { .. }))
+    ));
+    assert!(lines.next().is_none());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_derive_nginx_log() {
+    use rsnx::{LogParser, NginxLog, Timestamp};
+
+    #[derive(Debug, NginxLog)]
+    #[nginx(
+        format = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "${http_referer}""#
+    )]
+    struct Combined {
+        remote_addr: std::net::IpAddr,
+        remote_user: Option<String>,
+        #[nginx(variable = "time_local")]
+        time: Timestamp,
+        request: String,
+        status: u16,
+        body_bytes_sent: u64,
+        #[nginx(variable = "$http_referer")]
+        referer: Option<String>,
+    }
+
+    assert_eq!(Combined::VARIABLES.len(), 7);
+
+    let line = Combined::parse(
+        r#"10.0.0.1 - alice [08/Nov/2013:13:39:18 +0000] "GET / HTTP/1.1" 200 612 "-""#,
+    )
+    .unwrap();
+    assert_eq!(line.remote_addr.to_string(), "10.0.0.1");
+    assert_eq!(line.remote_user.as_deref(), Some("alice"));
+    assert_eq!(line.time.unix_timestamp(), 1383917958);
+    assert_eq!(line.request, "GET / HTTP/1.1");
+    assert_eq!(line.status, 200);
+    assert_eq!(line.body_bytes_sent, 612);
+    assert_eq!(line.referer, None);
+
+    assert!(matches!(
+        Combined::parse("not a log line"),
+        Err(Error::LineFormatMismatch { .. })
+    ));
+
+    // A configured parser can back the typed parser
+    let parser = Parser::with_variables(Combined::FORMAT, &VariableRegistry::builtin()).unwrap();
+    let parser = LogParser::<Combined>::with_parser(parser).unwrap();
+    assert!(parser
+        .parse(r#"10.0.0.1 - - [08/Nov/2013:13:39:18 +0000] "GET / HTTP/1.1" 200 abc "-""#)
+        .is_err());
+}
+
+#[test]
+fn test_upstream_attempts() {
+    let format = r#"$remote_addr "$request" $status "$upstream_addr" "$upstream_status" $upstream_response_time $request_time"#;
+    let log = r#"10.0.0.1 "GET /a HTTP/1.1" 200 "10.0.0.1:80, 10.0.0.2:80 : 10.0.0.3:80" "502, 200 : 200" 0.010, 0.502 : 0.003 0.520
+10.0.0.2 "GET /b HTTP/1.1" 200 "10.0.0.1:80" "200" 0.004 0.005
+10.0.0.3 "GET /static HTTP/1.1" 200 "-" "-" - 0.000"#;
+
+    let parser = Parser::with_variables(format, &VariableRegistry::builtin()).unwrap();
+    let entries = Reader::with_parser(Cursor::new(log), parser)
+        .collect_all()
+        .unwrap();
+
+    // float_field can't parse lists, the upstream API can
+    assert!(entries[0].float_field("upstream_response_time").is_err());
+    let upstream = entries[0].upstream().unwrap();
+    assert_eq!(upstream.len(), 3);
+    assert_eq!(upstream.group_count(), 2);
+    let statuses: Vec<_> = upstream.attempts().iter().map(|a| a.status).collect();
+    assert_eq!(statuses, [Some(502), Some(200), Some(200)]);
+    assert!((upstream.total_response_time() - 0.515).abs() < 1e-9);
+    assert_eq!(
+        upstream.last().unwrap().addr.as_deref(),
+        Some("10.0.0.3:80")
+    );
+
+    let upstream = entries[1].upstream().unwrap();
+    assert_eq!(upstream.len(), 1);
+    assert_eq!(upstream.attempts()[0].response_time, Some(0.004));
+
+    assert!(entries[2].upstream().unwrap().is_empty());
+
+    let entry = Parser::new("$status").unwrap().parse_string("200").unwrap();
+    assert!(matches!(entry.upstream(), Err(Error::FieldNotFound { .. })));
+}
+
+#[test]
+fn test_open_compressed_logs() {
+    use std::io::Write;
+
+    let format = "$remote_addr $status";
+    let log = "127.0.0.1 200\n192.168.1.1 404\n";
+    let dir = tempfile::tempdir().unwrap();
+
+    let plain = dir.path().join("access.log.1");
+    std::fs::write(&plain, log).unwrap();
+    let entries = Reader::open(&plain, format).unwrap().collect_all().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    #[allow(unused_mut)]
+    let mut files: Vec<(&str, Vec<u8>)> = Vec::new();
+    #[cfg(feature = "gzip")]
+    {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(log.as_bytes()).unwrap();
+        files.push(("access.log.2.gz", encoder.finish().unwrap()));
+    }
+    #[cfg(feature = "bzip2")]
+    {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(log.as_bytes()).unwrap();
+        files.push(("access.log.3.bz2", encoder.finish().unwrap()));
+    }
+    #[cfg(feature = "xz")]
+    {
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(log.as_bytes()).unwrap();
+        files.push(("access.log.4.xz", encoder.finish().unwrap()));
+    }
+    #[cfg(feature = "zstd")]
+    {
+        files.push((
+            "access.log.5.zst",
+            zstd::encode_all(log.as_bytes(), 0).unwrap(),
+        ));
+    }
+
+    for (name, data) in files {
+        // Compression is detected from the content, not the file name
+        let path = dir.path().join(name).with_extension("");
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+
+        let entries = Reader::open(&path, format).unwrap().collect_all().unwrap();
+        assert_eq!(entries.len(), 2, "{}", name);
+        assert_eq!(entries[1].field("status").unwrap(), "404", "{}", name);
+
+        let config = "log_format short '$remote_addr $status';";
+        let reader = NginxReader::open(&path, Cursor::new(config), "short").unwrap();
+        assert_eq!(reader.count(), 2, "{}", name);
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    {
+        let path = dir.path().join("access.log.2.gz");
+        std::fs::write(&path, b"\x1f\x8b\x08\x00\x00\x00\x00\x00").unwrap();
+        assert!(matches!(
+            Reader::open(&path, format),
+            Err(Error::UnsupportedCompression { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_rotated_reader() {
+    use rsnx::RotatedReader;
+
+    let dir = tempfile::tempdir().unwrap();
+    let files = [
+        ("access.log", "10.0.0.5 200\n10.0.0.6 200\n"),
+        ("access.log.1", "10.0.0.4 200\n"),
+        ("access.log.2", "10.0.0.3 200\n"),
+        ("access.log.10", "10.0.0.1 200\n10.0.0.2 200\n"),
+        ("access.log.bak", "10.0.0.9 200\n"),
+        ("error.log", "not an access log\n"),
+    ];
+    for (name, content) in files {
+        std::fs::write(dir.path().join(name), content).unwrap();
+    }
+
+    let reader =
+        RotatedReader::open(dir.path().join("access.log*"), "$remote_addr $status").unwrap();
+    let names: Vec<_> = reader
+        .files()
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "access.log.10",
+            "access.log.2",
+            "access.log.1",
+            "access.log"
+        ]
+    );
+
+    let entries = reader.collect_all().unwrap();
+    let addrs: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.field("remote_addr").unwrap())
+        .collect();
+    assert_eq!(
+        addrs,
+        ["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.0.5", "10.0.0.6"]
+    );
+    assert_eq!(
+        entries[2].source().unwrap(),
+        dir.path().join("access.log.2")
+    );
+    assert_eq!(entries[2].line_info().unwrap().line_number, Some(1));
+
+    // dateext names are ordered by date
+    let dir = tempfile::tempdir().unwrap();
+    for (name, content) in [
+        ("access.log-20240102", "10.0.0.2 200\n"),
+        ("access.log-20231231", "10.0.0.1 200\n"),
+    ] {
+        std::fs::write(dir.path().join(name), content).unwrap();
+    }
+    let entries = RotatedReader::open(dir.path().join("access.log"), "$remote_addr $status")
+        .unwrap()
+        .collect_all()
+        .unwrap();
+    assert_eq!(entries[0].field("remote_addr").unwrap(), "10.0.0.1");
+
+    // The last line of the live file is read even without a line ending
+    std::fs::write(dir.path().join("access.log"), "10.0.0.3 200\n10.0.0.4 200").unwrap();
+    let entries = RotatedReader::open(dir.path().join("access.log"), "$remote_addr $status")
+        .unwrap()
+        .collect_all()
+        .unwrap();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[3].field("remote_addr").unwrap(), "10.0.0.4");
+
+    assert!(matches!(
+        RotatedReader::open(dir.path().join("missing.log"), "$remote_addr"),
+        Err(Error::Io { .. })
+    ));
+}
+
+#[test]
+fn test_rotated_reader_error_policy() {
+    use rsnx::RotatedReader;
+
+    let dir = tempfile::tempdir().unwrap();
+    for (name, content) in [
+        ("access.log.2", "10.0.0.1 200\ngarbage\n"),
+        ("access.log.1", "garbage\n10.0.0.2 200\n"),
+        ("access.log", "10.0.0.3 200\ngarbage\n"),
+    ] {
+        std::fs::write(dir.path().join(name), content).unwrap();
+    }
+    let open = |policy| {
+        RotatedReader::open(dir.path().join("access.log"), "$remote_addr $status")
+            .unwrap()
+            .with_error_policy(policy)
+    };
+
+    let mut reader = open(ErrorPolicy::SkipAndCollect);
+    assert_eq!(reader.by_ref().count(), 3);
+    let summary = reader.error_summary();
+    assert_eq!(summary.total_lines(), 6);
+    assert_eq!(summary.rejected().len(), 3);
+    assert_eq!(
+        summary.rejected()[1].line_info().unwrap().line_number,
+        Some(1)
+    );
+
+    // Limits count the rejected lines of every file
+    let results: Vec<_> = open(ErrorPolicy::MaxErrors(2)).collect();
+    assert_eq!(results.len(), 4);
+    assert!(matches!(
+        results[3],
+        Err(Error::TooManyErrors {
+            rejected: 3,
+            total: 6
+        })
+    ));
+
+    let result = open(ErrorPolicy::max_error_rate(0.4).unwrap()).collect_all();
+    assert!(matches!(
+        result,
+        Err(Error::TooManyErrors {
+            rejected: 3,
+            total: 6
+        })
+    ));
+}
+
+#[test]
+fn test_follow_reader() {
+    use rsnx::FollowReader;
+    use std::io::Write;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    std::fs::write(&path, "10.0.0.1 200\n").unwrap();
+
+    let mut reader = FollowReader::open(&path, "$remote_addr $status")
+        .unwrap()
+        .with_poll_interval(Duration::from_millis(5));
+    assert!(reader.read_timeout(Duration::from_millis(50)).is_none());
+
+    let writer = std::thread::spawn({
+        let path = path.clone();
+        move || {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            for line in ["10.0.0.2 ", "404\n10.0.0.3 500\n", "bogus\n"] {
+                std::thread::sleep(Duration::from_millis(10));
+                file.write_all(line.as_bytes()).unwrap();
+            }
+        }
+    });
+
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.2");
+    assert_eq!(entry.field("status").unwrap(), "404");
+    assert_eq!(entry.source(), Some(path.as_path()));
+    assert_eq!(
+        reader.read().unwrap().unwrap().field("status").unwrap(),
+        "500"
+    );
+    assert!(matches!(
+        reader.read(),
+        Some(Err(Error::LineFormatMismatch { .. }))
+    ));
+    writer.join().unwrap();
+}
+
+#[test]
+fn test_resume_from_checkpoint() {
+    use rsnx::{Checkpoint, RotatedReader};
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("access.log");
+    let state = dir.path().join("access.checkpoint");
+    let format = "$remote_addr $status";
+
+    let run = || {
+        let mut reader = RotatedReader::open(&log, format)
+            .unwrap()
+            .with_hold_partial(true);
+        if let Some(checkpoint) = Checkpoint::load(&state).unwrap() {
+            reader = reader.resume_from(&checkpoint).unwrap();
+        }
+        let addrs: Vec<String> = reader
+            .by_ref()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.field("remote_addr").unwrap().to_string())
+            .collect();
+        reader.checkpoint().unwrap().save(&state).unwrap();
+        addrs
+    };
+
+    // The last line is still being written
+    fs::write(&log, "10.0.0.1 200\n10.0.0.2 200\n10.0.0.3 20").unwrap();
+    assert_eq!(run(), ["10.0.0.1", "10.0.0.2"]);
+    let checkpoint = Checkpoint::load(&state).unwrap().unwrap();
+    assert_eq!(checkpoint.offset(), 26);
+    assert_eq!(checkpoint.path(), log.as_path());
+
+    // The rest of the line and one more are written, then the log is rotated
+    let mut content = fs::read_to_string(&log).unwrap();
+    content.push_str("4\n10.0.0.4 200\n");
+    fs::write(&log, content).unwrap();
+    fs::rename(&log, dir.path().join("access.log.1")).unwrap();
+    fs::write(&log, "10.0.0.5 200\n").unwrap();
+    assert_eq!(run(), ["10.0.0.3", "10.0.0.4", "10.0.0.5"]);
+
+    // Nothing new
+    assert!(run().is_empty());
+
+    // copytruncate, once the log is long enough to be told apart by its first bytes
+    let mut content = fs::read_to_string(&log).unwrap();
+    content.push_str(&"10.0.0.6 200\n".repeat(5));
+    fs::write(&log, content).unwrap();
+    assert_eq!(run(), ["10.0.0.6"; 5]);
+    fs::copy(&log, dir.path().join("access.log.1")).unwrap();
+    fs::write(&log, "10.0.0.7 200\n").unwrap();
+    assert_eq!(run(), ["10.0.0.7"]);
+}
+
+#[test]
+fn test_checkpoint_short_head() {
+    use rsnx::Checkpoint;
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("access.log");
+    let format = "$remote_addr $status";
+
+    fs::write(&log, "10.0.0.1 200\n").unwrap();
+    let mut reader = Reader::open_resumable(&log, format).unwrap();
+    assert_eq!(reader.by_ref().count(), 1);
+    let checkpoint: Checkpoint = reader.checkpoint().unwrap();
+
+    // A different file that starts with the same line is read from the start
+    let other = dir.path().join("other.log");
+    fs::write(&other, "10.0.0.1 200\n10.0.0.2 200\n").unwrap();
+    let reader = Reader::open_resumable(&other, format)
+        .unwrap()
+        .resume_from(&checkpoint)
+        .unwrap();
+    assert_eq!(reader.count(), 2);
+}
+
+#[test]
+fn test_reader_checkpoint() {
+    use rsnx::Checkpoint;
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("access.log");
+    let format = "$remote_addr $status";
+    fs::write(&log, "10.0.0.1 200\n10.0.0.2 200\n").unwrap();
+
+    let mut reader = Reader::open_resumable(&log, format).unwrap();
+    assert_eq!(reader.by_ref().count(), 2);
+    let checkpoint: Checkpoint = reader.checkpoint().unwrap();
+    assert_eq!(checkpoint.offset(), 26);
+
+    // Only lines logged since the checkpoint are read
+    fs::write(&log, "10.0.0.1 200\n10.0.0.2 200\n10.0.0.3 200\n").unwrap();
+    let mut reader = Reader::open_resumable(&log, format)
+        .unwrap()
+        .resume_from(&checkpoint)
+        .unwrap();
+    let entry = reader.read().unwrap().unwrap();
+    assert_eq!(entry.field("remote_addr").unwrap(), "10.0.0.3");
+    assert_eq!(entry.line_info().unwrap().byte_offset, 26);
+    assert!(reader.read().is_none());
+    assert_eq!(reader.checkpoint().unwrap().offset(), 39);
+
+    // A different file is read from the start
+    fs::write(&log, "10.0.0.9 200\n").unwrap();
+    let reader = Reader::open_resumable(&log, format)
+        .unwrap()
+        .resume_from(&checkpoint)
+        .unwrap();
+    assert_eq!(reader.count(), 1);
+
+    // A line still being written is left for the next run
+    fs::write(&log, "10.0.0.1 200\n10.0.0.1 20").unwrap();
+    let statuses = |reader: &mut Reader<rsnx::input::Input>| -> Vec<String> {
+        reader
+            .map(|entry| entry.unwrap().field("status").unwrap().to_string())
+            .collect()
+    };
+    let mut reader = Reader::open_resumable(&log, format)
+        .unwrap()
+        .with_hold_partial(true);
+    assert_eq!(statuses(&mut reader), ["200"]);
+    let checkpoint = reader.checkpoint().unwrap();
+    assert_eq!(checkpoint.offset(), 13);
+
+    // Without holding it back, the last line is read even without a line ending
+    let mut reader = Reader::open_resumable(&log, format).unwrap();
+    assert_eq!(statuses(&mut reader), ["200", "20"]);
+    assert_eq!(reader.checkpoint().unwrap().offset(), 24);
+
+    let mut content = fs::read_to_string(&log).unwrap();
+    content.push_str("4\n");
+    fs::write(&log, content).unwrap();
+    let mut reader = Reader::open_resumable(&log, format)
+        .unwrap()
+        .resume_from(&checkpoint)
+        .unwrap();
+    assert_eq!(statuses(&mut reader), ["204"]);
+
+    // Readers that don't know their file can't take checkpoints
+    let reader = Reader::open(&log, format).unwrap();
+    assert!(reader.checkpoint().is_none());
+    assert!(matches!(
+        reader.resume_from(&checkpoint),
+        Err(Error::InvalidCheckpoint { .. })
+    ));
+}
+
+#[test]
+fn test_time_range_on_file() {
+    use rsnx::Timestamp;
+    use std::io::Write;
+    use std::time::Duration;
+
+    let mut file = tempfile::tempfile().unwrap();
+    for minute in 0..60 {
+        for second in (0..60).step_by(15) {
+            writeln!(
+                file,
+                r#"10.0.0.1 [08/Nov/2013:13:{:02}:{:02} +0000] "GET /{}/{} HTTP/1.1" 200"#,
+                minute, second, minute, second
+            )
+            .unwrap();
+        }
+        if minute == 31 {
+            // A worker that flushed its log buffer late
+            writeln!(
+                file,
+                r#"10.0.0.2 [08/Nov/2013:13:30:50 +0000] "GET /late HTTP/1.1" 200"#
+            )
+            .unwrap();
+        }
+    }
+
+    let reader = Reader::new(file, r#"$remote_addr [$time_local] "$request" $status"#).unwrap();
+    let start = Timestamp::parse_time_local("08/Nov/2013:13:30:30 +0000").unwrap();
+    let end = Timestamp::parse_time_local("08/Nov/2013:13:31:00 +0000").unwrap();
+    let entries = reader
+        .time_range(start..end, Duration::from_secs(60))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let paths: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.request().unwrap().path().to_string())
+        .collect();
+    assert_eq!(paths, ["/30/30", "/30/45", "/late"]);
+    assert_eq!(entries[0].line_info().unwrap().line_number, None);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parallel_reader() {
+    use rayon::prelude::*;
+    use rsnx::ParallelReader;
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    for i in 0..2000 {
+        writeln!(
+            file,
+            r#"10.0.0.{} [08/Nov/2013:13:39:18 +0000] "GET /{} HTTP/1.1" {}"#,
+            i % 10,
+            i,
+            if i % 4 == 0 { 404 } else { 200 }
+        )
+        .unwrap();
+    }
+
+    let format = r#"$remote_addr [$time_local] "$request" $status"#;
+    let reader = ParallelReader::open(file.path(), format)
+        .unwrap()
+        .with_chunk_size(4096);
+    assert!(reader.chunk_count() > 10);
+
+    let entries = reader.collect_all().unwrap();
+    assert_eq!(entries.len(), 2000);
+    assert_eq!(entries[1234].request().unwrap().path(), "/1234");
+
+    let not_found = reader
+        .par_entries()
+        .filter(|entry| entry.as_ref().unwrap().field("status").unwrap() == "404")
+        .count();
+    assert_eq!(not_found, 500);
+
+    let by_addr = Mutex::new(HashMap::new());
+    reader
+        .process_entries(|entry| -> rsnx::Result<()> {
+            let addr = entry.field("remote_addr")?.to_string();
+            *by_addr.lock().unwrap().entry(addr).or_insert(0) += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(by_addr.into_inner().unwrap()["10.0.0.3"], 200);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_mmap_reader() {
+    use rsnx::ErrorPolicy;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    std::fs::write(
+        &path,
+        "127.0.0.1 [08/Nov/2013:13:39:18 +0000] \"GET /a HTTP/1.1\" 200\n\
+         \n\
+         garbage\n\
+         127.0.0.2 [08/Nov/2013:13:39:19 +0000] \"GET /b HTTP/1.1\" 404\n",
+    )
+    .unwrap();
+
+    let format = r#"$remote_addr [$time_local] "$request" $status"#;
+    let mut reader = Reader::mmap(&path, format)
+        .unwrap()
+        .with_error_policy(ErrorPolicy::Skip);
+    let mut paths = Vec::new();
+    reader
+        .process_entries(|entry| -> rsnx::Result<()> {
+            paths.push(entry.request().unwrap().path().to_string());
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(paths, ["/a", "/b"]);
+    assert_eq!(reader.error_summary().rejected_count(), 1);
+
+    let entries = Reader::mmap(&path, format)
+        .unwrap()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    assert_eq!(entries[1].line_info(), Some(rsnx::LineInfo::new(4, 70)));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_reader() {
+    use rsnx::{AsyncNginxReader, AsyncReader, ErrorPolicy};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    std::fs::write(
+        &path,
+        "127.0.0.1 [08/Nov/2013:13:39:18 +0000] \"GET /a HTTP/1.1\" 200\n\
+         garbage\n\
+         127.0.0.2 [08/Nov/2013:13:39:19 +0000] \"GET /b HTTP/1.1\" 404\n",
+    )
+    .unwrap();
+
+    let format = r#"$remote_addr [$time_local] "$request" $status"#;
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let mut reader = AsyncReader::new(file, format)
+        .unwrap()
+        .with_error_policy(ErrorPolicy::SkipAndCollect);
+    let mut statuses = Vec::new();
+    while let Some(entry) = reader.read().await {
+        statuses.push(entry.unwrap().int_field("status").unwrap());
+    }
+    assert_eq!(statuses, [200, 404]);
+    assert_eq!(reader.error_summary().rejected_count(), 1);
+
+    let config = format!("log_format main '{}';", format);
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let reader = AsyncNginxReader::new(file, config.as_bytes(), "main").unwrap();
+    assert!(reader.collect_all().await.is_err());
+}
+
+#[test]
+fn test_invalid_utf8_lines() {
+    use rsnx::{ErrorPolicy, Utf8Policy};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    std::fs::write(
+        &path,
+        b"127.0.0.1 \"GET /a HTTP/1.1\" 200 \"curl/8.0\"\n\
+          127.0.0.2 \"GET /\xe9t\xe9 HTTP/1.1\" 404 \"Mozilla/5.0 (\xc0)\"\n\
+          127.0.0.3 \"\x16\x03\x01\x02\x00\x01\x00\xff\" 400 \"-\"\n\
+          127.0.0.4 \"GET /b HTTP/1.1\" 200 \"curl/8.0\"\n",
+    )
+    .unwrap();
+    let format = r#"$remote_addr "$request" $status "$http_user_agent""#;
+
+    let mut reader = Reader::open(&path, format)
+        .unwrap()
+        .with_error_policy(ErrorPolicy::SkipAndCollect);
+    let entries = reader.by_ref().collect::<rsnx::Result<Vec<_>>>().unwrap();
+    assert_eq!(entries.len(), 2);
+    let lines: Vec<_> = reader
+        .error_summary()
+        .rejected()
+        .iter()
+        .map(|error| error.line_info().unwrap().line_number)
+        .collect();
+    assert_eq!(lines, [Some(2), Some(3)]);
+
+    let entries = Reader::open(&path, format)
+        .unwrap()
+        .with_utf8_policy(Utf8Policy::Escape)
+        .collect_all()
+        .unwrap();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(
+        entries[1].field("request").unwrap(),
+        r"GET /\xE9t\xE9 HTTP/1.1"
+    );
+    assert_eq!(
+        entries[1].raw_line(),
+        Some(&b"127.0.0.2 \"GET /\xe9t\xe9 HTTP/1.1\" 404 \"Mozilla/5.0 (\xc0)\""[..])
+    );
+    assert_eq!(entries[0].raw_line(), None);
+    assert_eq!(entries[2].int_field("status").unwrap(), 400);
+}